@@ -0,0 +1,634 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind, Url,
+};
+
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, VisitNode, Visitable};
+use crate::utils;
+
+// Methods of headers and header stacks, which aren't calls to an extern.
+const BUILTIN_METHODS: [&str; 5] = [
+    "isValid",
+    "setValid",
+    "setInvalid",
+    "push_front",
+    "pop_front",
+];
+
+/// A declaration calls can go to or come from. Parameters and instances are
+/// only the targets of extern method calls.
+#[derive(Debug, Clone)]
+struct Callable {
+    name: String,
+    kind: SymbolKind,
+    detail: String,
+    range: Range,
+    selection_range: Range,
+    // The control, parser or extern declaring it, if not at the top level.
+    container: Option<Range>,
+    // The extern declaring it, for extern methods.
+    owner: Option<String>,
+}
+
+impl Callable {
+    fn is_instance(&self) -> bool {
+        self.kind == SymbolKind::OBJECT
+    }
+
+    fn get_item(&self, uri: &Url) -> CallHierarchyItem {
+        CallHierarchyItem {
+            name: self.name.clone(),
+            kind: self.kind,
+            tags: None,
+            detail: Some(self.detail.clone()),
+            uri: uri.clone(),
+            range: self.range,
+            selection_range: self.selection_range,
+            data: None,
+        }
+    }
+}
+
+struct Call {
+    caller: usize,
+    callee: usize,
+    range: Range,
+}
+
+#[derive(Default)]
+struct CallGraph {
+    callables: Vec<Callable>,
+    calls: Vec<Call>,
+}
+
+impl CallGraph {
+    fn find_item(&self, item: &CallHierarchyItem) -> Option<usize> {
+        self.callables.iter().position(|callable| {
+            callable.name == item.name && callable.selection_range == item.selection_range
+        })
+    }
+
+    /// The callable declared as `name` at `definition`, or else the one named
+    /// `name` visible from `position`, the innermost first.
+    fn resolve(&self, name: &str, definition: Option<Range>, position: Position) -> Option<usize> {
+        if let Some(index) = definition.and_then(|definition| {
+            self.callables
+                .iter()
+                .position(|callable| callable.selection_range == definition)
+        }) {
+            return Some(index);
+        }
+
+        let visible = |callable: &&Callable| {
+            callable.name == name
+                && callable
+                    .container
+                    .is_none_or(|container| contains(container, position))
+        };
+        let index = self
+            .callables
+            .iter()
+            .filter(visible)
+            .max_by_key(|callable| callable.container.is_some())?;
+        self.callables
+            .iter()
+            .position(|callable| std::ptr::eq(callable, index))
+    }
+
+    /// The method `method` of the extern `owner`.
+    fn find_method(&self, owner: &str, method: &str) -> Option<usize> {
+        self.callables.iter().position(|callable| {
+            callable.name == method && callable.owner.as_deref() == Some(owner)
+        })
+    }
+
+    fn add_call(&mut self, caller: usize, callee: usize, range: Range) {
+        if caller != callee {
+            self.calls.push(Call {
+                caller,
+                callee,
+                range,
+            });
+        }
+    }
+}
+
+fn get_call_graph(
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> CallGraph {
+    let ast_query = ast_query.lock().unwrap();
+    let st_query = st_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let mut graph = CallGraph::default();
+
+    for top_level in root.get_children() {
+        let container = match top_level.get().kind {
+            NodeKind::ControlDec | NodeKind::ParserDec | NodeKind::Extern => {
+                Some(top_level.get().range)
+            }
+            _ => None,
+        };
+        let owner = match top_level.get().kind {
+            NodeKind::Extern => utils::get_name(&top_level),
+            _ => None,
+        };
+        for node in top_level.get_descendants() {
+            let container = container.filter(|_| node.get().range != top_level.get().range);
+            graph
+                .callables
+                .extend(get_callable(&node, container, owner.as_deref()));
+        }
+    }
+
+    let resolve = |graph: &CallGraph, name: &str, position: Position| {
+        let definition = st_query
+            .get_symbol_at_pos(name.to_string(), position)
+            .map(|symbol| symbol.get_definition_range());
+        graph.resolve(name, definition, position)
+    };
+
+    for top_level in root.get_children() {
+        for node in top_level.get_descendants() {
+            let Some(caller) = graph
+                .callables
+                .iter()
+                .position(|callable| !callable.is_instance() && callable.range == node.get().range)
+            else {
+                continue;
+            };
+
+            match node.get().kind {
+                NodeKind::ControlAction | NodeKind::Function => {
+                    if let Some(block) = node.get_child_of_kind(NodeKind::Block) {
+                        add_calls(&mut graph, caller, &block, &resolve);
+                    }
+                }
+                NodeKind::ControlDec | NodeKind::ParserDec => {
+                    let Some(body) = node.get_child_of_kind(NodeKind::Body) else {
+                        continue;
+                    };
+                    for child in body.get_children() {
+                        match child.get().kind {
+                            // The `apply` block of a control.
+                            NodeKind::Block => add_calls(&mut graph, caller, &child, &resolve),
+                            NodeKind::StateParser => {
+                                for kind in [NodeKind::Body, NodeKind::TransitionStatement] {
+                                    if let Some(part) = child.get_child_of_kind(kind) {
+                                        add_calls(&mut graph, caller, &part, &resolve);
+                                    }
+                                }
+                            }
+                            NodeKind::Instantiation => {
+                                // A sub-control or sub-parser.
+                                let Some(type_node) = child.get_type_node() else {
+                                    continue;
+                                };
+                                let type_name = get_type_name(&type_node.get().content);
+                                let range = type_node.get().range;
+                                if let Some(callee) = resolve(&graph, &type_name, range.start)
+                                    .filter(|callee| !graph.callables[*callee].is_instance())
+                                {
+                                    graph.add_call(caller, callee, range);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                NodeKind::ControlTable => {
+                    let Some(table) = node.get_child_of_kind(NodeKind::Table) else {
+                        continue;
+                    };
+                    for property in table.get_children() {
+                        match property.get().kind {
+                            NodeKind::Actions => {
+                                for action in property.get_children() {
+                                    if action.get().kind != NodeKind::Action {
+                                        continue;
+                                    }
+                                    let Some(name_node) = action.get_type_node() else {
+                                        continue;
+                                    };
+                                    let range = name_node.get().range;
+                                    let name = name_node.get().content.trim().to_string();
+                                    if let Some(callee) = resolve(&graph, &name, range.start) {
+                                        graph.add_call(caller, callee, range);
+                                    }
+                                }
+                            }
+                            NodeKind::TableKw => {
+                                let is_default_action =
+                                    property.get_child_of_kind(NodeKind::Name).is_some_and(
+                                        |name| name.get().content.trim() == "default_action",
+                                    );
+                                if let Some(value) =
+                                    property.get_value_node().filter(|_| is_default_action)
+                                {
+                                    add_calls(&mut graph, caller, &value, &resolve);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    graph
+}
+
+/// The callable declared by `node`, if it is one. `owner` is the extern
+/// declaring `node`, if any.
+fn get_callable(
+    node: &VisitNode,
+    container: Option<Range>,
+    owner: Option<&str>,
+) -> Option<Callable> {
+    let (kind, detail) = match node.get().kind {
+        NodeKind::ControlAction => (SymbolKind::FUNCTION, "action".to_string()),
+        NodeKind::ControlDec => (SymbolKind::CLASS, "control".to_string()),
+        NodeKind::ParserDec => (SymbolKind::CLASS, "parser".to_string()),
+        NodeKind::ControlTable => (SymbolKind::STRUCT, "table".to_string()),
+        // Extern objects are only called through their instances, and their
+        // constructors have no prototype.
+        NodeKind::Function | NodeKind::Extern | NodeKind::Method => {
+            let (kind, detail) = match (&node.get().kind, owner) {
+                (NodeKind::Function, _) => (SymbolKind::FUNCTION, "function".to_string()),
+                (NodeKind::Method, Some(owner)) => (SymbolKind::METHOD, format!("{owner} method")),
+                (NodeKind::Method, None) => return None,
+                _ => (SymbolKind::FUNCTION, "extern function".to_string()),
+            };
+            let prototype = node.get_child_of_kind(NodeKind::FunctionName)?;
+            let name = prototype.get_child_of_kind(NodeKind::Name)?;
+            return Some(Callable {
+                name: name.get().content.trim().to_string(),
+                kind,
+                detail,
+                range: node.get().range,
+                selection_range: name.get().range,
+                container,
+                owner: owner
+                    .filter(|_| kind == SymbolKind::METHOD)
+                    .map(str::to_string),
+            });
+        }
+        NodeKind::Instantiation | NodeKind::Param => (
+            SymbolKind::OBJECT,
+            node.get_type_node()?.get().content.trim().to_string(),
+        ),
+        _ => return None,
+    };
+    let name = node.get_child_of_kind(NodeKind::Name)?;
+
+    Some(Callable {
+        name: name.get().content.trim().to_string(),
+        kind,
+        detail,
+        range: node.get().range,
+        selection_range: name.get().range,
+        container,
+        owner: None,
+    })
+}
+
+/// Adds the calls made in `node`: call statements, `apply` calls of control
+/// and parser types, and the calls inside expressions.
+fn add_calls(
+    graph: &mut CallGraph,
+    caller: usize,
+    node: &VisitNode,
+    resolve: &impl Fn(&CallGraph, &str, Position) -> Option<usize>,
+) {
+    for descendant in node.get_descendants() {
+        match descendant.get().kind {
+            // Like `drop();` or `ipv4_lpm.apply();`.
+            NodeKind::Assignment
+                if descendant.get_value_node().is_none()
+                    && descendant.get_child_of_kind(NodeKind::Args).is_some() =>
+            {
+                if let Some(name_statement) = descendant.get_child_of_kind(NodeKind::NameStatement)
+                {
+                    add_path_call(
+                        graph,
+                        caller,
+                        &utils::get_lvalue_path(&name_statement),
+                        resolve,
+                    );
+                }
+            }
+            // Like `MyIngress.apply(hdr, meta);`.
+            NodeKind::DirectApplication => {
+                let Some(type_node) = descendant.get_type_node() else {
+                    continue;
+                };
+                let type_name = get_type_name(&type_node.get().content);
+                let range = type_node.get().range;
+                if let Some(callee) = resolve(graph, &type_name, range.start)
+                    .filter(|callee| graph.callables[*callee].kind == SymbolKind::CLASS)
+                {
+                    graph.add_call(caller, callee, range);
+                }
+            }
+            // Like `t.apply().hit` or `f(x) + 1`.
+            NodeKind::Value => {
+                let content = &descendant.get().content;
+                let start = descendant.get().range.start;
+                for path in utils::get_value_paths(&descendant) {
+                    for index in 0..path.len().min(2) {
+                        let end = utils::get_offset(content, start, path[index].1.end);
+                        let next = path.get(index + 1).map_or(content.len(), |(_, range)| {
+                            utils::get_offset(content, start, range.start)
+                        });
+                        if is_called(content.get(end..next).unwrap_or_default()) {
+                            add_path_call(graph, caller, &path[..=index], resolve);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Adds the call of the expression `path`, like `drop`, `ipv4_lpm.apply` or
+/// `packet.extract`: to actions and functions, `apply` calls of tables,
+/// controls and parsers, and extern method calls.
+fn add_path_call(
+    graph: &mut CallGraph,
+    caller: usize,
+    path: &[(String, Range)],
+    resolve: &impl Fn(&CallGraph, &str, Position) -> Option<usize>,
+) {
+    let (Some((name, first)), Some((_, last))) = (path.first(), path.last()) else {
+        return;
+    };
+    let range = Range::new(first.start, last.end);
+    let Some(target) = resolve(graph, name, range.start) else {
+        return;
+    };
+
+    let callable = &graph.callables[target];
+    let callee = match path.get(1).map(|(method, _)| method.as_str()) {
+        None if callable.kind == SymbolKind::FUNCTION => Some(target),
+        _ if path.len() > 2 => None,
+        Some("apply") if callable.is_instance() => {
+            // The control or parser instantiated.
+            let type_name = get_type_name(&callable.detail);
+            resolve(graph, &type_name, range.start)
+                .filter(|callee| graph.callables[*callee].kind == SymbolKind::CLASS)
+        }
+        Some("apply") => Some(target),
+        // The extern's method, or the instance if the extern is declared in
+        // another document.
+        Some(method) if callable.is_instance() && !BUILTIN_METHODS.contains(&method) => Some(
+            graph
+                .find_method(&get_type_name(&callable.detail), method)
+                .unwrap_or(target),
+        ),
+        _ => None,
+    };
+
+    if let Some(callee) = callee {
+        graph.add_call(caller, callee, range);
+    }
+}
+
+/// Whether the text following a name calls it, like `(x)` or `<bit<8>>(x)`.
+fn is_called(text: &str) -> bool {
+    let text = text.trim_start();
+    let Some(arguments) = text.strip_prefix('<') else {
+        return text.starts_with('(');
+    };
+
+    let mut depth = 1;
+    for (index, c) in arguments.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ';' | '{' | '}' | '(' | ')' => return false,
+            _ => {}
+        }
+        if depth == 0 {
+            return arguments[index + 1..].trim_start().starts_with('(');
+        }
+    }
+    false
+}
+
+/// `bit<8>`'s or `Checksum<bit<16>>`'s base name.
+fn get_type_name(type_: &str) -> String {
+    type_
+        .split('<')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn contains(range: Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+/// The action, function, table, control or parser declared or called at
+/// `position`.
+pub fn prepare(
+    uri: &Url,
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Vec<CallHierarchyItem>> {
+    let graph = get_call_graph(ast_query, st_query);
+
+    let callable = graph
+        .callables
+        .iter()
+        .find(|callable| !callable.is_instance() && contains(callable.selection_range, position))
+        .or_else(|| {
+            graph
+                .calls
+                .iter()
+                .find(|call| contains(call.range, position))
+                .map(|call| &graph.callables[call.callee])
+        })?;
+
+    Some(vec![callable.get_item(uri)])
+}
+
+/// The declarations calling `item`, with the ranges of their calls.
+pub fn get_incoming_calls(
+    uri: &Url,
+    item: &CallHierarchyItem,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<CallHierarchyIncomingCall> {
+    let graph = get_call_graph(ast_query, st_query);
+    let Some(callee) = graph.find_item(item) else {
+        return vec![];
+    };
+
+    group_calls(&graph, |call| {
+        (call.callee == callee).then_some(call.caller)
+    })
+    .into_iter()
+    .map(|(caller, from_ranges)| CallHierarchyIncomingCall {
+        from: graph.callables[caller].get_item(uri),
+        from_ranges,
+    })
+    .collect()
+}
+
+/// The declarations `item` calls, with the ranges of the calls.
+pub fn get_outgoing_calls(
+    uri: &Url,
+    item: &CallHierarchyItem,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let graph = get_call_graph(ast_query, st_query);
+    let Some(caller) = graph.find_item(item) else {
+        return vec![];
+    };
+
+    group_calls(&graph, |call| {
+        (call.caller == caller).then_some(call.callee)
+    })
+    .into_iter()
+    .map(|(callee, from_ranges)| CallHierarchyOutgoingCall {
+        to: graph.callables[callee].get_item(uri),
+        from_ranges,
+    })
+    .collect()
+}
+
+/// The ranges of the calls selected by `key`, grouped by the callable it
+/// returns, in the order of the first call.
+fn group_calls(
+    graph: &CallGraph,
+    key: impl Fn(&Call) -> Option<usize>,
+) -> Vec<(usize, Vec<Range>)> {
+    let mut groups: Vec<(usize, Vec<Range>)> = vec![];
+    for call in &graph.calls {
+        let Some(index) = key(call) else {
+            continue;
+        };
+        match groups.iter_mut().find(|(other, _)| *other == index) {
+            Some((_, ranges)) if !ranges.contains(&call.range) => ranges.push(call.range),
+            Some(_) => {}
+            None => groups.push((index, vec![call.range])),
+        }
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, Url};
+
+    use super::{get_incoming_calls, get_outgoing_calls, is_called, prepare};
+    use crate::utils;
+
+    const SOURCE: &str = r"extern void log_msg(in bit<8> x);
+extern Checksum {
+    Checksum();
+    void update(in bit<8> data);
+}
+control c(inout bit<8> x) {
+    Checksum() ck;
+    action a() {
+        log_msg(x);
+        ck.update(x);
+    }
+    table t {
+        actions = { a; }
+        default_action = a();
+    }
+    apply {
+        t.apply();
+        if (t.apply().hit) {
+            a();
+        }
+    }
+}
+";
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn test_call_graph() {
+        let file = utils::parse_file(SOURCE);
+        let uri = Url::parse("file:///test.p4").unwrap();
+        let (ast, st) = (&file.ast_manager, &file.symbol_table_manager);
+        let item = |position| prepare(&uri, position, ast, st).unwrap().remove(0);
+
+        let outgoing: Vec<(String, Option<String>, Vec<Range>)> =
+            get_outgoing_calls(&uri, &item(Position::new(7, 11)), ast, st)
+                .into_iter()
+                .map(|call| (call.to.name, call.to.detail, call.from_ranges))
+                .collect();
+        assert_eq!(
+            outgoing,
+            vec![
+                (
+                    "log_msg".to_string(),
+                    Some("extern function".to_string()),
+                    vec![range(8, 8, 15)]
+                ),
+                (
+                    "update".to_string(),
+                    Some("Checksum method".to_string()),
+                    vec![range(9, 8, 17)]
+                ),
+            ]
+        );
+
+        let outgoing: Vec<(String, Vec<Range>)> =
+            get_outgoing_calls(&uri, &item(Position::new(5, 8)), ast, st)
+                .into_iter()
+                .map(|call| (call.to.name, call.from_ranges))
+                .collect();
+        assert_eq!(
+            outgoing,
+            vec![
+                ("t".to_string(), vec![range(16, 8, 15), range(17, 12, 19)]),
+                ("a".to_string(), vec![range(18, 12, 13)]),
+            ]
+        );
+
+        let incoming: Vec<(String, Vec<Range>)> =
+            get_incoming_calls(&uri, &item(Position::new(18, 12)), ast, st)
+                .into_iter()
+                .map(|call| (call.from.name, call.from_ranges))
+                .collect();
+        assert_eq!(
+            incoming,
+            vec![
+                ("c".to_string(), vec![range(18, 12, 13)]),
+                ("t".to_string(), vec![range(12, 20, 21), range(13, 25, 26)]),
+            ]
+        );
+
+        let incoming: Vec<String> = get_incoming_calls(&uri, &item(Position::new(3, 10)), ast, st)
+            .into_iter()
+            .map(|call| call.from.name)
+            .collect();
+        assert_eq!(incoming, vec!["a"]);
+    }
+
+    #[test]
+    fn test_is_called() {
+        assert!(is_called("(x)"));
+        assert!(is_called(" <ethernet_t>(hdr.ethernet)"));
+        assert!(is_called("<bit<8>>()"));
+        assert!(!is_called(".apply()"));
+        assert!(!is_called(" < 4 && (x)"));
+        assert!(!is_called(""));
+    }
+}
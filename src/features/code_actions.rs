@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, Range, TextEdit, Url,
+    WorkspaceEdit,
+};
+
+/// A fix for a diagnostic, carried in its `data` so the code action doesn't
+/// need the document to be analysed again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuickFix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+    #[serde(default)]
+    pub is_preferred: bool,
+}
+
+impl QuickFix {
+    pub fn new(title: String, edits: Vec<TextEdit>) -> QuickFix {
+        QuickFix {
+            title,
+            edits,
+            is_preferred: false,
+        }
+    }
+
+    /// The fix replacing a misspelled name at `range` with `suggestion`.
+    pub fn rename(range: Range, suggestion: &str) -> QuickFix {
+        QuickFix {
+            title: format!("Change to `{suggestion}`"),
+            edits: vec![TextEdit::new(range, suggestion.to_string())],
+            is_preferred: true,
+        }
+    }
+}
+
+/// Attaches `fixes` to `diagnostic`.
+pub fn with_fixes(mut diagnostic: Diagnostic, fixes: Vec<QuickFix>) -> Diagnostic {
+    if !fixes.is_empty() {
+        diagnostic.data = serde_json::to_value(fixes).ok();
+    }
+    diagnostic
+}
+
+/// The candidate closest to `name`, if it's close enough for `name` to be a
+/// typo of it.
+pub fn get_suggestion<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.as_str() != name)
+        .map(|candidate| (get_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// The edit distance between `a` and `b`, ignoring case, where swapping two
+/// adjacent characters counts as a single edit.
+fn get_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// The fixes `diagnostic` carries.
+pub fn get_fixes(diagnostic: &Diagnostic) -> Vec<QuickFix> {
+    diagnostic
+        .data
+        .clone()
+        .and_then(|data| serde_json::from_value(data).ok())
+        .unwrap_or_default()
+}
+
+/// The code action applying `fix` to the document `uri`, for `diagnostic`
+/// if it fixes one.
+pub fn new_code_action(
+    uri: &Url,
+    fix: QuickFix,
+    diagnostic: Option<&Diagnostic>,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: fix.title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: diagnostic.map(|diagnostic| vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), fix.edits)])),
+            ..Default::default()
+        }),
+        is_preferred: Some(fix.is_preferred),
+        ..Default::default()
+    })
+}
+
+/// The quick fixes of `diagnostics`, the ones of the code action request's
+/// context.
+pub fn get_code_actions(uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeActionOrCommand> {
+    diagnostics
+        .iter()
+        .flat_map(|diagnostic| {
+            get_fixes(diagnostic)
+                .into_iter()
+                .map(move |fix| new_code_action(uri, fix, Some(diagnostic)))
+        })
+        .collect()
+}
+
+/// The actions of `actions` whose kind is one of `kinds` or a subkind of it,
+/// like `refactor.extract` for `refactor`.
+pub fn filter_by_kind(
+    actions: Vec<CodeActionOrCommand>,
+    kinds: &[CodeActionKind],
+) -> Vec<CodeActionOrCommand> {
+    actions
+        .into_iter()
+        .filter(|action| {
+            let CodeActionOrCommand::CodeAction(action) = action else {
+                return true;
+            };
+            action.kind.as_ref().is_some_and(|kind| {
+                kinds.iter().any(|only| {
+                    kind == only || kind.as_str().starts_with(&format!("{}.", only.as_str()))
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use super::*;
+
+    #[test]
+    fn test_get_suggestion() {
+        let candidates: Vec<String> = ["ipv4_forward", "drop", "NoAction"]
+            .map(String::from)
+            .to_vec();
+
+        assert_eq!(
+            get_suggestion("ipv4_froward", &candidates),
+            Some("ipv4_forward")
+        );
+        assert_eq!(get_suggestion("noaction", &candidates), Some("NoAction"));
+        assert_eq!(get_suggestion("dorp", &candidates), Some("drop"));
+        assert_eq!(get_suggestion("forward", &candidates), None);
+        assert_eq!(get_suggestion("drop", &candidates), None);
+    }
+
+    #[test]
+    fn test_get_code_actions() {
+        let uri = Url::parse("file:///test.p4").unwrap();
+        let range = Range::new(Position::new(3, 12), Position::new(3, 16));
+        let diagnostic = with_fixes(
+            Diagnostic::new_simple(range, "Action `dorp` is not defined.".to_string()),
+            vec![QuickFix::rename(range, "drop")],
+        );
+
+        let actions = get_code_actions(&uri, &[diagnostic.clone(), Diagnostic::default()]);
+        assert_eq!(actions.len(), 1);
+        let CodeActionOrCommand::CodeAction(action) = &actions[0] else {
+            panic!("expected a code action");
+        };
+        assert_eq!(action.title, "Change to `drop`");
+        assert_eq!(action.diagnostics, Some(vec![diagnostic]));
+        assert_eq!(
+            action.edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri],
+            vec![TextEdit::new(range, "drop".to_string())]
+        );
+    }
+}
@@ -1,7 +1,37 @@
 use std::sync::{Arc, Mutex};
 
-use crate::metadata::{SymbolTableQuery, Symbols};
-use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, Position};
+use crate::features::diagnostics::BUILTIN_MATCH_KINDS;
+use crate::features::members::{self, MemberKind, Segment};
+use crate::features::snippets::{self, Scope};
+use crate::metadata::{
+    AstQuery, NodeKind, Symbol, SymbolTableQuery, Symbols, TypeDecType, VisitNode, Visitable,
+};
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, Position, Url};
+use tree_sitter::Tree;
+
+const BASE_TYPES: [&str; 6] = ["bool", "bit", "int", "varbit", "error", "string"];
+
+// Longer declarations are previewed up to their body.
+const MAX_PREVIEW_LINES: usize = 12;
+
+/// Kept in the items' `data` to find their declaration on resolve.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompletionData {
+    /// The document declaring the symbol.
+    pub uri: Url,
+    /// Where the symbol is in scope, none for top-level declarations.
+    pub position: Option<Position>,
+    /// The document being completed.
+    pub document: Url,
+}
+
+pub struct Declaration {
+    pub preview: String,
+    pub documentation: Option<String>,
+    pub type_name: Option<String>,
+}
 
 pub struct CompletionBuilder {
     items: Vec<CompletionItem>,
@@ -31,75 +61,742 @@ impl CompletionBuilder {
         self
     }
 
+    /// Like `add`, with the `detail` of each item, usually its type.
+    pub fn add_detailed(
+        mut self,
+        new_items: &[(String, Option<String>)],
+        completion_type: CompletionItemKind,
+    ) -> CompletionBuilder {
+        for (label, detail) in new_items {
+            if self.items.iter().any(|item| &item.label == label) {
+                continue;
+            }
+            self.items.push(CompletionItem {
+                label: label.clone(),
+                kind: Some(completion_type),
+                detail: detail.clone(),
+                ..Default::default()
+            });
+        }
+
+        self
+    }
+
     pub fn build(self) -> Vec<CompletionItem> {
         self.items
     }
 }
 
+/// What the grammar accepts at the cursor.
+#[derive(Debug, PartialEq)]
+pub enum Context {
+    Type,
+    State,
+    TableAction,
+    MatchKind,
+    Member(Vec<Segment>),
+    Declaration(Scope),
+    Any,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The name of the field of its parent `node` is in, like `name` for the
+/// state of a `transition`.
+fn get_field_name(node: tree_sitter::Node) -> Option<&'static str> {
+    let parent = node.parent()?;
+    let mut cursor = parent.walk();
+    if !cursor.goto_first_child() {
+        return None;
+    }
+    loop {
+        if cursor.node() == node {
+            return cursor.field_name();
+        }
+        if !cursor.goto_next_sibling() {
+            return None;
+        }
+    }
+}
+
+/// The last token before `byte`, skipping comments and the tokens the parser
+/// inserted to recover from errors.
+fn get_previous_token(node: tree_sitter::Node, byte: usize) -> Option<tree_sitter::Node> {
+    let mut cursor = node.walk();
+    let children: Vec<tree_sitter::Node> = node.children(&mut cursor).collect();
+    for child in children.into_iter().rev() {
+        if child.start_byte() >= byte || child.is_missing() || child.kind() == "comment" {
+            continue;
+        }
+        if child.child_count() == 0 {
+            return Some(child);
+        }
+        if let Some(token) = get_previous_token(child, byte) {
+            return Some(token);
+        }
+    }
+    None
+}
+
+fn get_ancestors(node: tree_sitter::Node) -> Vec<tree_sitter::Node> {
+    let mut ancestors = vec![];
+    let mut current = node.parent();
+    while let Some(node) = current {
+        ancestors.push(node);
+        current = node.parent();
+    }
+    ancestors
+}
+
+// The table property `node` is in, if any.
+fn get_table_property(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    get_ancestors(node).into_iter().find(|ancestor| {
+        matches!(
+            ancestor.kind(),
+            "keys_table" | "action_table" | "entries_table" | "name_table"
+        )
+    })
+}
+
+fn is_default_action(name_table: tree_sitter::Node, source_code: &str) -> bool {
+    name_table
+        .child_by_field_name("name")
+        .is_some_and(|name| utils::get_node_text(&name, source_code).trim() == "default_action")
+}
+
+/// The context of the name at `node`, from the field it fills.
+pub fn get_slot_context(mut node: tree_sitter::Node, source_code: &str) -> Option<Context> {
+    // The field holds the node wrapping the identifier, like a `type_name`.
+    while let Some(parent) = node.parent() {
+        if parent.byte_range() != node.byte_range() {
+            break;
+        }
+        node = parent;
+    }
+    let parent = node.parent()?;
+    let field = get_field_name(node);
+
+    if field == Some("name") && matches!(parent.kind(), "transition_statement" | "select_case") {
+        return Some(Context::State);
+    }
+
+    let property = get_table_property(node)?;
+    match property.kind() {
+        // The name of a key is its match kind, the name of an action list
+        // element the action.
+        "keys_table" if field == Some("name") => Some(Context::MatchKind),
+        "action_table" if field == Some("name") => Some(Context::TableAction),
+        "name_table" if is_default_action(property, source_code) => {
+            // The action called, not its arguments.
+            let in_arguments = get_ancestors(node)
+                .iter()
+                .take_while(|ancestor| **ancestor != property)
+                .any(|ancestor| ancestor.kind() == "argument");
+            (!in_arguments).then_some(Context::TableAction)
+        }
+        _ => None,
+    }
+}
+
+/// The context after `token`, the token before the cursor.
+fn get_token_context(token: tree_sitter::Node, source_code: &str) -> Option<Context> {
+    let parent = token.parent();
+    let property = get_table_property(token);
+    let property_kind = property.map(|property| property.kind());
+
+    match token.kind() {
+        "." => {
+            let receiver = members::get_receiver(token)?;
+            Some(Context::Member(members::get_segments(
+                receiver,
+                source_code,
+            )?))
+        }
+        "transition" => Some(Context::State),
+        ":" if get_ancestors(token)
+            .iter()
+            .any(|ancestor| ancestor.kind() == "select_case") =>
+        {
+            Some(Context::State)
+        }
+        ":" if property_kind == Some("keys_table") => Some(Context::MatchKind),
+        "{" | ";" if property_kind == Some("action_table") => Some(Context::TableAction),
+        "=" if property.is_some_and(|property| {
+            property.kind() == "name_table" && is_default_action(property, source_code)
+        }) =>
+        {
+            Some(Context::TableAction)
+        }
+        "in" | "out" | "inout" | "typedef" | "const" => Some(Context::Type),
+        "(" | ","
+            if parent.is_some_and(|parent| {
+                parent.kind().contains("parameter")
+                    || matches!(
+                        get_field_name(parent),
+                        Some("parameters" | "parameters_list")
+                    )
+            }) =>
+        {
+            Some(Context::Type)
+        }
+        "{" | ";"
+            if parent.is_some_and(|parent| {
+                matches!(
+                    parent.kind(),
+                    "struct_type_declaration"
+                        | "header_type_declaration"
+                        | "header_union_declaration"
+                ) || parent.parent().is_some_and(|grand_parent| {
+                    matches!(
+                        grand_parent.kind(),
+                        "struct_type_declaration"
+                            | "header_type_declaration"
+                            | "header_union_declaration"
+                    )
+                })
+            }) =>
+        {
+            Some(Context::Type)
+        }
+        _ => None,
+    }
+}
+
+/// The declarations the scope at `node` accepts.
+fn get_scope_context(node: tree_sitter::Node) -> Context {
+    let scope =
+        std::iter::once(node)
+            .chain(get_ancestors(node))
+            .find_map(|ancestor| match ancestor.kind() {
+                "keys_table" | "entries_table" => Some(None),
+                "block_statement"
+                | "action_declaration"
+                | "function_declaration"
+                | "conditional_statement"
+                | "switch_statement" => Some(Some(Scope::Statement)),
+                "parser_state" | "parser_block_statement" => Some(Some(Scope::State)),
+                "table_declaration" => Some(Some(Scope::Table)),
+                "control_declaration" => Some(Some(Scope::Control)),
+                "parser_declaration" => Some(Some(Scope::Parser)),
+                _ => None,
+            });
+    match scope {
+        Some(None) => Context::Any,
+        Some(Some(scope)) => Context::Declaration(scope),
+        None => Context::Declaration(Scope::TopLevel),
+    }
+}
+
+fn get_context(tree: &Tree, source_code: &str, position: Position) -> Context {
+    let byte = utils::pos_to_byte(position, source_code).min(source_code.len());
+    let Some(before) = source_code.get(..byte) else {
+        return Context::Any;
+    };
+    // The name being typed is not part of the context.
+    let word_start = before.trim_end_matches(is_name_char).len();
+    let root = tree.root_node();
+
+    if word_start < byte {
+        if let Some(context) = root
+            .descendant_for_byte_range(word_start, byte)
+            .and_then(|node| get_slot_context(node, source_code))
+        {
+            return context;
+        }
+    }
+
+    let token = get_previous_token(root, word_start);
+    if let Some(context) = token.and_then(|token| get_token_context(token, source_code)) {
+        return context;
+    }
+
+    match token {
+        Some(token) if !matches!(token.kind(), "{" | ";" | "}") => Context::Any,
+        _ => get_scope_context(
+            root.descendant_for_byte_range(word_start, word_start)
+                .unwrap_or(root),
+        ),
+    }
+}
+
+fn contains(node: &VisitNode, position: Position) -> bool {
+    let range = node.get().range;
+    range.start <= position && position <= range.end
+}
+
+fn get_types(root: &VisitNode) -> Vec<(String, Option<String>, CompletionItemKind)> {
+    let mut types = vec![];
+
+    for node in root.get_descendants() {
+        let (detail, kind) = match &node.get().kind {
+            NodeKind::TypeDec(type_dec_type) => match type_dec_type {
+                TypeDecType::HeaderType => ("header".to_string(), CompletionItemKind::STRUCT),
+                TypeDecType::HeaderUnion => {
+                    ("header_union".to_string(), CompletionItemKind::STRUCT)
+                }
+                TypeDecType::Struct => ("struct".to_string(), CompletionItemKind::STRUCT),
+                TypeDecType::Enum => ("enum".to_string(), CompletionItemKind::ENUM),
+                TypeDecType::TypeDef => {
+                    let base = node
+                        .get_type_node()
+                        .map(|type_node| type_node.get().content.clone())
+                        .unwrap_or_default();
+                    (
+                        format!("typedef {base}").trim_end().to_string(),
+                        CompletionItemKind::TYPE_PARAMETER,
+                    )
+                }
+                TypeDecType::Parser => ("parser".to_string(), CompletionItemKind::INTERFACE),
+                TypeDecType::Control => ("control".to_string(), CompletionItemKind::INTERFACE),
+                TypeDecType::Package => ("package".to_string(), CompletionItemKind::INTERFACE),
+            },
+            NodeKind::Extern => ("extern".to_string(), CompletionItemKind::CLASS),
+            _ => continue,
+        };
+
+        if let Some(name) = utils::get_name(&node) {
+            types.push((name, Some(detail), kind));
+        }
+    }
+
+    types
+}
+
+fn get_states(root: &VisitNode, position: Position) -> Vec<(String, Option<String>)> {
+    let mut states: Vec<(String, Option<String>)> = vec![];
+
+    if let Some(parser) = root
+        .get_descendants()
+        .into_iter()
+        .rfind(|node| node.get().kind == NodeKind::ParserDec && contains(node, position))
+    {
+        for state in parser.get_descendants() {
+            if state.get().kind == NodeKind::StateParser {
+                if let Some(name) = utils::get_name(&state) {
+                    states.push((name, Some("state".to_string())));
+                }
+            }
+        }
+    }
+    for name in ["accept", "reject"] {
+        states.push((name.to_string(), Some("state".to_string())));
+    }
+
+    states
+}
+
+fn get_action_detail(action: &VisitNode) -> String {
+    let params: Vec<String> = action
+        .get_child_of_kind(NodeKind::Params)
+        .map(|params| {
+            params
+                .get_children()
+                .iter()
+                .filter(|param| param.get().kind == NodeKind::Param)
+                .map(|param| param.get().content.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    format!("action({})", params.join(", "))
+}
+
+fn get_actions(root: &VisitNode, position: Position) -> Vec<(String, Option<String>)> {
+    let mut actions: Vec<(String, Option<String>)> = vec![];
+
+    let control = root
+        .get_descendants()
+        .into_iter()
+        .rfind(|node| node.get().kind == NodeKind::ControlDec && contains(node, position));
+    if let Some(control) = control {
+        for action in control.get_descendants() {
+            if action.get().kind == NodeKind::ControlAction {
+                if let Some(name) = utils::get_name(&action) {
+                    actions.push((name, Some(get_action_detail(&action))));
+                }
+            }
+        }
+    }
+    // Actions declared outside of any control are usable in all of them.
+    for action in root.get_children() {
+        if action.get().kind == NodeKind::ControlAction {
+            if let Some(name) = utils::get_name(&action) {
+                actions.push((name, Some(get_action_detail(&action))));
+            }
+        }
+    }
+    actions.push(("NoAction".to_string(), Some("action()".to_string())));
+
+    actions
+}
+
+fn get_match_kinds(root: &VisitNode) -> Vec<(String, Option<String>)> {
+    let mut match_kinds: Vec<(String, Option<String>)> = BUILTIN_MATCH_KINDS
+        .iter()
+        .map(|name| (name.to_string(), Some("match_kind".to_string())))
+        .collect();
+
+    for match_kind in root.get_descendants() {
+        if match_kind.get().kind != NodeKind::MatchKind {
+            continue;
+        }
+        for option in match_kind.get_descendants() {
+            if option.get().kind == NodeKind::Option {
+                if let Some(name) = utils::get_name(&option) {
+                    match_kinds.push((name.trim().to_string(), Some("match_kind".to_string())));
+                }
+            }
+        }
+    }
+
+    match_kinds
+}
+
+fn get_members(
+    receiver: &[Segment],
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<CompletionItem> {
+    let Some(type_) = members::get_type(receiver, position, ast_query, st_query) else {
+        return vec![];
+    };
+
+    let mut builder = CompletionBuilder::new();
+    for member in members::get_type_members(&type_, ast_query) {
+        let kind = match member.kind {
+            MemberKind::Field => CompletionItemKind::FIELD,
+            MemberKind::Method => CompletionItemKind::METHOD,
+        };
+        builder = builder.add_detailed(&[(member.name, Some(member.detail))], kind);
+    }
+    builder.build()
+}
+
 pub fn get_list(
     position: Position,
     source_code: &str,
-    query: &Arc<Mutex<impl SymbolTableQuery>>,
+    tree: &Tree,
+    target: Option<&str>,
+    external: &[CompletionItem],
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
 ) -> Option<Vec<CompletionItem>> {
     fn default(
         position: Position,
         query: &Arc<Mutex<impl SymbolTableQuery>>,
     ) -> Option<Vec<CompletionItem>> {
         let symbols: Symbols = query.lock().unwrap().get_symbols_at_pos(position);
+        let detailed = |symbols: &Vec<Symbol>| {
+            symbols
+                .iter()
+                .map(|s| (s.get_name(), s.get_type_name()))
+                .collect::<Vec<_>>()
+        };
 
         Some(
             CompletionBuilder::new()
-                .add(
-                    &symbols
-                        .types
-                        .iter()
-                        .map(|s| s.get_name())
-                        .collect::<Vec<_>>(),
+                .add_detailed(
+                    &detailed(&symbols.types),
                     CompletionItemKind::TYPE_PARAMETER,
                 )
-                .add(
-                    &symbols
-                        .constants
-                        .iter()
-                        .map(|s| s.get_name())
-                        .collect::<Vec<_>>(),
-                    CompletionItemKind::CONSTANT,
-                )
-                .add(
-                    &symbols
-                        .variables
-                        .iter()
-                        .map(|s| s.get_name())
-                        .collect::<Vec<_>>(),
-                    CompletionItemKind::VARIABLE,
+                .add_detailed(&detailed(&symbols.constants), CompletionItemKind::CONSTANT)
+                .add_detailed(&detailed(&symbols.variables), CompletionItemKind::VARIABLE)
+                .add_detailed(&detailed(&symbols.functions), CompletionItemKind::FUNCTION)
+                .build(),
+        )
+    }
+
+    let context = get_context(tree, source_code, position);
+    debug!("Completion context: {:?}", context);
+    let context_accepts_globals = match context {
+        Context::Any => true,
+        Context::Declaration(scope) => scope != Scope::Table,
+        _ => false,
+    };
+
+    let mut items = match context {
+        Context::Member(receiver) => get_members(&receiver, position, ast_query, st_query),
+        Context::Type => {
+            let ast_query = ast_query.lock().unwrap();
+            let mut builder = CompletionBuilder::new();
+            for (name, detail, kind) in get_types(&ast_query.visit_root()) {
+                builder = builder.add_detailed(&[(name, detail)], kind);
+            }
+            let mut items = builder
+                .add(&BASE_TYPES.map(String::from), CompletionItemKind::KEYWORD)
+                .build();
+            items.extend(
+                external
+                    .iter()
+                    .filter(|item| is_type_kind(item.kind))
+                    .cloned(),
+            );
+            items
+        }
+        Context::State => {
+            let ast_query = ast_query.lock().unwrap();
+            CompletionBuilder::new()
+                .add_detailed(
+                    &get_states(&ast_query.visit_root(), position),
+                    CompletionItemKind::ENUM_MEMBER,
                 )
-                .add(
-                    &symbols
-                        .functions
-                        .iter()
-                        .map(|s| s.get_name())
-                        .collect::<Vec<_>>(),
+                .build()
+        }
+        Context::TableAction => {
+            let ast_query = ast_query.lock().unwrap();
+            CompletionBuilder::new()
+                .add_detailed(
+                    &get_actions(&ast_query.visit_root(), position),
                     CompletionItemKind::FUNCTION,
                 )
-                .build(),
+                .build()
+        }
+        Context::MatchKind => {
+            let ast_query = ast_query.lock().unwrap();
+            CompletionBuilder::new()
+                .add_detailed(
+                    &get_match_kinds(&ast_query.visit_root()),
+                    CompletionItemKind::ENUM_MEMBER,
+                )
+                .build()
+        }
+        Context::Declaration(Scope::Table) => snippets::get_items(Scope::Table, target),
+        Context::Declaration(scope) => {
+            let mut items = snippets::get_items(scope, target);
+            items.extend(default(position, st_query).unwrap_or_default());
+            items
+        }
+        Context::Any => default(position, st_query).unwrap_or_default(),
+    };
+
+    if context_accepts_globals {
+        for item in external {
+            if !items.iter().any(|local| local.label == item.label) {
+                items.push(item.clone());
+            }
+        }
+    }
+
+    Some(items)
+}
+
+fn is_type_kind(kind: Option<CompletionItemKind>) -> bool {
+    matches!(
+        kind,
+        Some(
+            CompletionItemKind::STRUCT
+                | CompletionItemKind::ENUM
+                | CompletionItemKind::TYPE_PARAMETER
+                | CompletionItemKind::INTERFACE
+                | CompletionItemKind::CLASS
         )
+    )
+}
+
+/// The top-level declarations of a document, which other documents can use
+/// once they include it.
+pub fn get_exported_items(ast_query: &Arc<Mutex<impl AstQuery>>) -> Vec<CompletionItem> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    let mut builder = CompletionBuilder::new();
+    for (name, detail, kind) in get_types(&root) {
+        builder = builder.add_detailed(&[(name, detail)], kind);
+    }
+    for node in root.get_children() {
+        let Some(name) = utils::get_name(&node) else {
+            continue;
+        };
+        let (detail, kind) = match node.get().kind {
+            NodeKind::ConstantDec => (
+                node.get_type_node()
+                    .map(|type_node| type_node.get().content.clone()),
+                CompletionItemKind::CONSTANT,
+            ),
+            NodeKind::ControlAction => {
+                (Some(get_action_detail(&node)), CompletionItemKind::FUNCTION)
+            }
+            NodeKind::ParserDec => (Some("parser".to_string()), CompletionItemKind::MODULE),
+            NodeKind::ControlDec => (Some("control".to_string()), CompletionItemKind::MODULE),
+            _ => continue,
+        };
+        builder = builder.add_detailed(&[(name, detail)], kind);
     }
 
-    let name_field = query.lock().unwrap().get_name_field(position, source_code);
+    builder.build()
+}
 
-    match name_field {
-        Some(fields) => {
-            return Some(
-                CompletionBuilder::new()
-                    .add(
-                        &fields.iter().map(|s| s.get_name()).collect::<Vec<_>>(),
-                        CompletionItemKind::FIELD,
-                    )
-                    .build(),
-            )
+/// Finds the declaration of a completed symbol, the one in scope at
+/// `position` or a top-level one.
+pub fn get_declaration(
+    name: &str,
+    position: Option<Position>,
+    source_code: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Declaration> {
+    let (definition_range, mut type_name) = match position {
+        Some(position) => {
+            let st_query = st_query.lock().unwrap();
+            let symbol = st_query.get_symbol_at_pos(name.to_string(), position)?;
+            (Some(symbol.get_definition_range()), symbol.get_type_name())
         }
-        None => {
-            return default(position, query);
+        None => (None, None),
+    };
+
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let candidates = match definition_range {
+        Some(_) => root.get_descendants(),
+        None => root.get_children(),
+    };
+    let node = candidates.into_iter().find(|node| {
+        node.get_child_of_kind(NodeKind::Name)
+            .is_some_and(|name_node| {
+                let name_node = name_node.get();
+                match definition_range {
+                    Some(range) => name_node.range == range,
+                    None => name_node.content == name,
+                }
+            })
+    })?;
+
+    if type_name.is_none() {
+        type_name = node
+            .get_type_node()
+            .map(|type_node| type_node.get().content.clone());
+    }
+
+    let content = &node.get().content;
+    let preview = if content.lines().count() > MAX_PREVIEW_LINES {
+        let header = content.split('{').next().unwrap_or_default().trim_end();
+        format!("{header} {{ ... }}")
+    } else {
+        content.clone()
+    };
+
+    Some(Declaration {
+        preview,
+        documentation: utils::get_doc_comment(source_code, node.get().range.start.line as usize),
+        type_name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_context, Context};
+    use crate::features::members::Segment;
+    use crate::features::snippets::Scope;
+    use crate::utils;
+
+    /// The context at the `$` in `source_code`.
+    fn context_at(source_code: &str) -> Context {
+        let byte = source_code.find('$').unwrap();
+        let source_code = source_code.replacen('$', "", 1);
+        let line = source_code[..byte].matches('\n').count() as u32;
+        let character = (byte - source_code[..byte].rfind('\n').map_or(0, |i| i + 1)) as u32;
+        let tree = utils::parse(&source_code);
+        get_context(
+            &tree,
+            &source_code,
+            tower_lsp::lsp_types::Position::new(line, character),
+        )
+    }
+
+    const PARSER: &str = "parser P(packet_in pkt, out headers hdr) {
+    state start {
+        pkt.extract(hdr.ethernet);
+        transition select(hdr.ethernet.etherType) {
+            0x800: parse_$;
+            default: accept;
         }
     }
+}";
+
+    #[test]
+    fn test_state_context() {
+        assert_eq!(context_at(PARSER), Context::State);
+        assert_eq!(
+            context_at("parser P() { state start { transition $ } }"),
+            Context::State
+        );
+        assert_eq!(
+            context_at("parser P() { state start { transition acc$; } }"),
+            Context::State
+        );
+    }
+
+    #[test]
+    fn test_table_contexts() {
+        let table = "control C(inout headers hdr) {
+    action drop() {}
+    table t {
+        key = { hdr.ipv4.dstAddr: $MATCH; }
+        actions = { $ACTION; }
+        default_action = $DEFAULT();
+    }
+    apply {}
+}";
+        let at = |marker: &str| {
+            let source = table
+                .replace(marker, "$")
+                .replace("$MATCH", "lpm")
+                .replace("$ACTION", "drop")
+                .replace("$DEFAULT", "drop");
+            context_at(&source)
+        };
+        assert_eq!(at("$MATCH"), Context::MatchKind);
+        assert_eq!(at("$ACTION"), Context::TableAction);
+        assert_eq!(at("$DEFAULT"), Context::TableAction);
+    }
+
+    #[test]
+    fn test_member_context() {
+        assert_eq!(
+            context_at("control C(inout headers hdr) { apply { hdr.ipv4.$ } }"),
+            Context::Member(vec![
+                Segment::Name("hdr".to_string()),
+                Segment::Name("ipv4".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_type_context() {
+        assert_eq!(
+            context_at("control C(inout $ hdr) { apply {} }"),
+            Context::Type
+        );
+        assert_eq!(context_at("header ethernet_t {\n    $\n}"), Context::Type);
+        assert_eq!(context_at("typedef $"), Context::Type);
+    }
+
+    #[test]
+    fn test_declaration_contexts() {
+        assert_eq!(context_at("$"), Context::Declaration(Scope::TopLevel));
+        assert_eq!(
+            context_at("control C() {\n    $\n    apply {}\n}"),
+            Context::Declaration(Scope::Control)
+        );
+        assert_eq!(
+            context_at("control C() { apply { x = 1; $ } }"),
+            Context::Declaration(Scope::Statement)
+        );
+        assert_eq!(
+            context_at("parser P() { state start { $ transition accept; } }"),
+            Context::Declaration(Scope::State)
+        );
+        assert_eq!(
+            context_at("control C() { table t {\n    $\n} apply {} }"),
+            Context::Declaration(Scope::Table)
+        );
+    }
+
+    #[test]
+    fn test_expression_context() {
+        assert_eq!(context_at("control C() { apply { x = $ } }"), Context::Any);
+    }
 }
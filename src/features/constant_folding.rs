@@ -0,0 +1,650 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::metadata::{AstQuery, BaseType, NodeKind, Type, VisitNode, Visitable};
+
+lazy_static! {
+    static ref WIDTH_LITERAL: Regex = Regex::new(r"^(\d+)([wWsS])(-?)([0-9a-zA-Z_]+)").unwrap();
+    static ref LITERAL: Regex = Regex::new(r"^[0-9][0-9a-zA-Z_]*").unwrap();
+    static ref IDENTIFIER: Regex = Regex::new(r"^[A-Za-z_][\w.]*").unwrap();
+    static ref CAST: Regex = Regex::new(r"^\(\s*(bit|int)\s*<\s*(\d+)\s*>\s*\)").unwrap();
+}
+
+// Longest operators first so that `<<` isn't read as two `<`.
+const OPERATORS: [&str; 23] = [
+    "|+|", "|-|", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "+", "-", "*", "/", "%",
+    "&", "|", "^", "<", ">", "~", "!",
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConstantType {
+    Bit(u32),
+    Int(u32),
+    Infinite,
+    Bool,
+}
+
+impl fmt::Display for ConstantType {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConstantType::Bit(width) => write!(fmt, "bit<{width}>"),
+            ConstantType::Int(width) => write!(fmt, "int<{width}>"),
+            ConstantType::Infinite => fmt.write_str("int"),
+            ConstantType::Bool => fmt.write_str("bool"),
+        }
+    }
+}
+
+impl ConstantType {
+    pub fn from_type(type_: Type) -> Option<ConstantType> {
+        match type_ {
+            Type::Base(BaseType::SizedBit(Some(width))) => Some(ConstantType::Bit(width)),
+            Type::Base(BaseType::SizedInt(Some(width))) => Some(ConstantType::Int(width)),
+            Type::Base(BaseType::Int) => Some(ConstantType::Infinite),
+            Type::Base(BaseType::Bool) => Some(ConstantType::Bool),
+            _ => None,
+        }
+    }
+
+    fn is_fixed(&self) -> bool {
+        matches!(self, ConstantType::Bit(_) | ConstantType::Int(_))
+    }
+
+    fn bounds(&self) -> Option<(i128, i128)> {
+        match *self {
+            ConstantType::Bit(width) if width < 127 => Some((0, (1 << width) - 1)),
+            ConstantType::Int(width) if (1..127).contains(&width) => {
+                Some((-(1 << (width - 1)), (1 << (width - 1)) - 1))
+            }
+            ConstantType::Bool => Some((0, 1)),
+            _ => None,
+        }
+    }
+
+    /// Whether `value` can be represented without truncation.
+    pub fn fits(&self, value: i128) -> bool {
+        match self.bounds() {
+            Some((min, max)) => value >= min && value <= max,
+            None => true,
+        }
+    }
+
+    /// Truncates `value` to the width of the type, the way P4 does on overflow.
+    fn wrap(&self, value: i128) -> i128 {
+        match *self {
+            ConstantType::Bit(width) if width < 127 => value.rem_euclid(1 << width),
+            ConstantType::Int(width) if (1..127).contains(&width) => {
+                let value = value.rem_euclid(1 << width);
+                if value >= 1 << (width - 1) {
+                    value - (1 << width)
+                } else {
+                    value
+                }
+            }
+            ConstantType::Bool => (value != 0) as i128,
+            _ => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Constant {
+    pub value: i128,
+    pub type_: ConstantType,
+}
+
+impl Constant {
+    pub fn new(value: i128, type_: ConstantType) -> Constant {
+        Constant {
+            value: type_.wrap(value),
+            type_,
+        }
+    }
+
+    fn cast(self, type_: ConstantType) -> Constant {
+        Constant::new(self.value, type_)
+    }
+
+    /// The value as its two's complement bit pattern for fixed width types.
+    fn unsigned(&self) -> Option<u128> {
+        match self.type_ {
+            ConstantType::Int(width) if self.value < 0 && width < 128 => {
+                Some((self.value + (1 << width)) as u128)
+            }
+            _ if self.value >= 0 => Some(self.value as u128),
+            _ => None,
+        }
+    }
+
+    pub fn to_decimal(self) -> String {
+        match self.type_ {
+            ConstantType::Bool => (self.value != 0).to_string(),
+            _ => self.value.to_string(),
+        }
+    }
+
+    pub fn to_hex(self) -> String {
+        match self.unsigned() {
+            Some(value) => format!("0x{value:X}"),
+            None => format!("-0x{:X}", self.value.unsigned_abs()),
+        }
+    }
+
+    pub fn to_binary(self) -> String {
+        match self.unsigned() {
+            Some(value) => format!("0b{value:b}"),
+            None => format!("-0b{:b}", self.value.unsigned_abs()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Literal(String, Constant),
+    Identifier(String),
+    Operator(&'static str),
+    Cast(ConstantType),
+    LParen,
+    RParen,
+    // Separators and accesses, which never appear in a constant expression
+    // but may surround the literals of other ones.
+    Punctuation(char),
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let (token, length) = if let Some(found) = WIDTH_LITERAL.find(rest) {
+            let literal = found.as_str().to_string();
+            (
+                Token::Literal(literal.clone(), parse_literal(&literal)?),
+                found.end(),
+            )
+        } else if let Some(found) = LITERAL.find(rest) {
+            let literal = found.as_str().to_string();
+            (
+                Token::Literal(literal.clone(), parse_literal(&literal)?),
+                found.end(),
+            )
+        } else if let Some(found) = IDENTIFIER.find(rest) {
+            (Token::Identifier(found.as_str().to_string()), found.end())
+        } else if let Some(captures) = CAST.captures(rest) {
+            let width = captures[2].parse().ok()?;
+            let type_ = match &captures[1] {
+                "bit" => ConstantType::Bit(width),
+                _ => ConstantType::Int(width),
+            };
+            (Token::Cast(type_), captures[0].len())
+        } else if rest.starts_with('(') {
+            (Token::LParen, 1)
+        } else if rest.starts_with(')') {
+            (Token::RParen, 1)
+        } else if let Some(c) = rest.chars().next().filter(|c| ",.[]?:{}=;".contains(*c)) {
+            (Token::Punctuation(c), 1)
+        } else {
+            let operator = OPERATORS.iter().find(|op| rest.starts_with(*op))?;
+            (Token::Operator(operator), operator.len())
+        };
+
+        tokens.push(token);
+        rest = rest[length..].trim_start();
+    }
+
+    Some(tokens)
+}
+
+/// Parses an integer literal such as `42`, `0x800`, `8w255` or `16s-1`. The
+/// value is kept as written, even if it doesn't fit the literal's width.
+fn parse_literal(text: &str) -> Option<Constant> {
+    let (type_, negative, digits) = match WIDTH_LITERAL.captures(text) {
+        Some(captures) => {
+            let width = captures[1].parse().ok()?;
+            let type_ = match &captures[2] {
+                "w" | "W" => ConstantType::Bit(width),
+                _ => ConstantType::Int(width),
+            };
+            (type_, !captures[3].is_empty(), captures[4].to_string())
+        }
+        None => (ConstantType::Infinite, false, text.to_string()),
+    };
+
+    let digits = digits.replace('_', "").to_ascii_lowercase();
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0d") => (10, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    let value = i128::from_str_radix(digits, radix).ok()?;
+
+    Some(Constant {
+        value: if negative { -value } else { value },
+        type_,
+    })
+}
+
+/// Returns the width annotated literals in `text` whose value doesn't fit in
+/// their width, e.g. `8w256`.
+pub fn get_overflowing_literals(text: &str) -> Vec<(String, ConstantType)> {
+    tokenize(text)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Literal(text, constant) if !constant.type_.fits(constant.value) => {
+                Some((text, constant.type_))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Evaluates a constant expression, looking identifiers up in `constants`.
+/// Returns `None` if the expression isn't a compile-time constant.
+pub fn evaluate(text: &str, constants: &HashMap<String, Constant>) -> Option<Constant> {
+    let tokens = tokenize(text)?;
+    let mut evaluator = Evaluator {
+        tokens: &tokens,
+        position: 0,
+        constants,
+    };
+
+    let result = evaluator.expression(0)?;
+    if evaluator.position != tokens.len() {
+        return None;
+    }
+    Some(result)
+}
+
+fn precedence(operator: &str) -> Option<u8> {
+    Some(match operator {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "++" => 9,
+        "+" | "-" | "|+|" | "|-|" => 10,
+        "*" | "/" | "%" => 11,
+        _ => return None,
+    })
+}
+
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    constants: &'a HashMap<String, Constant>,
+}
+
+impl Evaluator<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn expression(&mut self, min_precedence: u8) -> Option<Constant> {
+        let mut left = self.unary()?;
+
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator_precedence = match precedence(operator) {
+                Some(operator_precedence) if operator_precedence > min_precedence => {
+                    operator_precedence
+                }
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.expression(operator_precedence)?;
+            left = binary(operator, left, right)?;
+        }
+
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Constant> {
+        match self.next()?.clone() {
+            Token::Literal(_, constant) => Some(Constant::new(constant.value, constant.type_)),
+            Token::Identifier(name) => match name.as_str() {
+                "true" => Some(Constant::new(1, ConstantType::Bool)),
+                "false" => Some(Constant::new(0, ConstantType::Bool)),
+                _ => self.constants.get(&name).copied(),
+            },
+            Token::Cast(type_) => Some(self.unary()?.cast(type_)),
+            Token::LParen => {
+                let result = self.expression(0)?;
+                match self.next()? {
+                    Token::RParen => Some(result),
+                    _ => None,
+                }
+            }
+            Token::Operator(operator) => {
+                let operand = self.unary()?;
+                match (operator, operand.type_) {
+                    ("-", ConstantType::Bool) | ("~", ConstantType::Bool) => None,
+                    ("-", type_) => Some(Constant::new(operand.value.checked_neg()?, type_)),
+                    ("~", ConstantType::Infinite) => None,
+                    ("~", type_) => Some(Constant::new(!operand.value, type_)),
+                    ("!", ConstantType::Bool | ConstantType::Infinite) => Some(Constant::new(
+                        (operand.value == 0) as i128,
+                        ConstantType::Bool,
+                    )),
+                    _ => None,
+                }
+            }
+            Token::RParen | Token::Punctuation(_) => None,
+        }
+    }
+}
+
+fn binary(operator: &str, left: Constant, right: Constant) -> Option<Constant> {
+    match operator {
+        "&&" | "||" => {
+            // Integers are accepted as truth values, like in `#if` conditions.
+            let is_logical = |constant: &Constant| {
+                matches!(constant.type_, ConstantType::Bool | ConstantType::Infinite)
+            };
+            if !is_logical(&left) || !is_logical(&right) {
+                return None;
+            }
+            let value = match operator {
+                "&&" => left.value != 0 && right.value != 0,
+                _ => left.value != 0 || right.value != 0,
+            } as i128;
+            return Some(Constant::new(value, ConstantType::Bool));
+        }
+        "<<" | ">>" => {
+            if left.type_ == ConstantType::Bool || right.value < 0 {
+                return None;
+            }
+            let shift = u32::try_from(right.value).ok()?;
+            let value = match operator {
+                "<<" => match left.type_.bounds() {
+                    // Bits shifted out of a fixed width value are discarded.
+                    Some(_) if shift >= 127 => 0,
+                    Some(_) => left.value.checked_mul(1 << shift)?,
+                    None => left.value.checked_mul(1i128.checked_shl(shift)?)?,
+                },
+                _ => left.value >> shift.min(127),
+            };
+            return Some(Constant::new(value, left.type_));
+        }
+        "++" => {
+            let (left_width, right_width) = match (left.type_, right.type_) {
+                (
+                    ConstantType::Bit(l) | ConstantType::Int(l),
+                    ConstantType::Bit(r) | ConstantType::Int(r),
+                ) => (l, r),
+                _ => return None,
+            };
+            let width = left_width + right_width;
+            if width >= 127 {
+                return None;
+            }
+            let right_bits = right.unsigned()? as i128;
+            let value = (left.value << right_width) | right_bits;
+            let type_ = match left.type_ {
+                ConstantType::Int(_) => ConstantType::Int(width),
+                _ => ConstantType::Bit(width),
+            };
+            return Some(Constant::new(value, type_));
+        }
+        _ => {}
+    }
+
+    // Infinite precision operands take the type of the fixed width operand.
+    let type_ = match (left.type_, right.type_) {
+        (l, r) if l == r => l,
+        (ConstantType::Infinite, r) if r.is_fixed() => r,
+        (l, ConstantType::Infinite) if l.is_fixed() => l,
+        _ => return None,
+    };
+    let (left, right) = (left.cast(type_), right.cast(type_));
+
+    let comparison = match operator {
+        "==" => Some(left.value == right.value),
+        "!=" => Some(left.value != right.value),
+        "<" => Some(left.value < right.value),
+        ">" => Some(left.value > right.value),
+        "<=" => Some(left.value <= right.value),
+        ">=" => Some(left.value >= right.value),
+        _ => None,
+    };
+    if let Some(comparison) = comparison {
+        return Some(Constant::new(comparison as i128, ConstantType::Bool));
+    }
+    if type_ == ConstantType::Bool {
+        return None;
+    }
+
+    let value = match operator {
+        "+" => left.value.checked_add(right.value)?,
+        "-" => left.value.checked_sub(right.value)?,
+        "*" => left.value.checked_mul(right.value)?,
+        "/" => left.value.checked_div(right.value)?,
+        "%" => left.value.checked_rem(right.value)?,
+        "&" => left.value & right.value,
+        "|" => left.value | right.value,
+        "^" => left.value ^ right.value,
+        "|+|" | "|-|" => {
+            let value = match operator {
+                "|+|" => left.value.checked_add(right.value)?,
+                _ => left.value.checked_sub(right.value)?,
+            };
+            match type_.bounds() {
+                Some((min, max)) => value.clamp(min, max),
+                None => value,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(Constant::new(value, type_))
+}
+
+/// Evaluates the constants and `#define`s visible at `position`, the ones
+/// declared before it in the scopes enclosing it, local ones shadowing the
+/// others.
+pub fn get_constants(root: &VisitNode, position: Position) -> HashMap<String, Constant> {
+    fn add_scope(scope: &VisitNode, position: Position, constants: &mut HashMap<String, Constant>) {
+        for child in scope.get_children() {
+            let range = child.get().range;
+            if range.end <= position
+                && matches!(
+                    child.get().kind,
+                    NodeKind::ConstantDec | NodeKind::PreprocDefine
+                )
+            {
+                let name = match child.get_child_of_kind(NodeKind::Name) {
+                    Some(name) => name.get().content.trim().to_string(),
+                    None => continue,
+                };
+                match evaluate_declaration(&child, constants) {
+                    Some(constant) => constants.insert(name, constant),
+                    None => constants.remove(&name),
+                };
+            } else if range.start <= position && position < range.end {
+                add_scope(&child, position, constants);
+            }
+        }
+    }
+
+    let mut constants = HashMap::new();
+    add_scope(root, position, &mut constants);
+    constants
+}
+
+fn evaluate_declaration(
+    declaration: &VisitNode,
+    constants: &HashMap<String, Constant>,
+) -> Option<Constant> {
+    let value = declaration
+        .get_value_node()
+        .and_then(|value| evaluate(&value.get().content, constants))?;
+    let declared_type = declaration
+        .get_type_node()
+        .and_then(|type_node| type_node.get_type())
+        .and_then(ConstantType::from_type);
+
+    Some(match declared_type {
+        Some(type_) => value.cast(type_),
+        None => value,
+    })
+}
+
+/// The value of the constant whose name is declared at `definition`.
+pub fn get_constant(ast_query: &Arc<Mutex<impl AstQuery>>, definition: Range) -> Option<Constant> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let declaration = root.get_descendants().into_iter().find(|node| {
+        node.get().kind == NodeKind::ConstantDec
+            && node
+                .get_child_of_kind(NodeKind::Name)
+                .is_some_and(|name| name.get().range == definition)
+    })?;
+
+    let constants = get_constants(&root, declaration.get().range.start);
+    evaluate_declaration(&declaration, &constants)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tower_lsp::lsp_types::Position;
+
+    use super::{evaluate, get_constants, get_overflowing_literals, Constant, ConstantType};
+    use crate::metadata::AstQuery;
+    use crate::utils;
+
+    fn eval(text: &str) -> Option<Constant> {
+        evaluate(text, &HashMap::new())
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(
+            eval("0x800"),
+            Some(Constant::new(2048, ConstantType::Infinite))
+        );
+        assert_eq!(
+            eval("8w255"),
+            Some(Constant::new(255, ConstantType::Bit(8)))
+        );
+        assert_eq!(
+            eval("16s-1"),
+            Some(Constant::new(-1, ConstantType::Int(16)))
+        );
+        assert_eq!(
+            eval("32w0b1010_1010"),
+            Some(Constant::new(170, ConstantType::Bit(32)))
+        );
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(
+            eval("(1 + 2) * 3 << 2"),
+            Some(Constant::new(36, ConstantType::Infinite))
+        );
+        assert_eq!(
+            eval("8w255 + 1"),
+            Some(Constant::new(0, ConstantType::Bit(8)))
+        );
+        assert_eq!(
+            eval("(bit<4>) 0x1F"),
+            Some(Constant::new(15, ConstantType::Bit(4)))
+        );
+        assert_eq!(
+            eval("4w0xA ++ 4w0x5"),
+            Some(Constant::new(0xA5, ConstantType::Bit(8)))
+        );
+        assert_eq!(
+            eval("8w250 |+| 10"),
+            Some(Constant::new(255, ConstantType::Bit(8)))
+        );
+        assert_eq!(eval("hdr.ipv4.ttl - 1"), None);
+
+        let constants = HashMap::from([(
+            "TYPE_IPV4".to_string(),
+            Constant::new(0x800, ConstantType::Bit(16)),
+        )]);
+        assert_eq!(
+            evaluate("TYPE_IPV4 | 1", &constants),
+            Some(Constant::new(0x801, ConstantType::Bit(16)))
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(
+            get_overflowing_literals("8w256 + 4s7 + 4s8"),
+            vec![
+                ("8w256".to_string(), ConstantType::Bit(8)),
+                ("4s8".to_string(), ConstantType::Int(4)),
+            ]
+        );
+        assert!(!ConstantType::Bit(8).fits(256));
+        assert!(ConstantType::Int(8).fits(-128));
+
+        let constant = Constant::new(-1, ConstantType::Int(8));
+        assert_eq!(constant.to_hex(), "0xFF");
+        assert_eq!(constant.to_binary(), "0b11111111");
+    }
+
+    #[test]
+    fn test_overflow_in_expressions() {
+        assert_eq!(
+            get_overflowing_literals("f(x, 8w256)"),
+            vec![("8w256".to_string(), ConstantType::Bit(8))]
+        );
+        assert_eq!(
+            get_overflowing_literals("x[4w16:0]"),
+            vec![("4w16".to_string(), ConstantType::Bit(4))]
+        );
+        assert_eq!(
+            get_overflowing_literals("c ? 2w4 : f().x"),
+            vec![("2w4".to_string(), ConstantType::Bit(2))]
+        );
+        assert_eq!(eval("f(1, 2)"), None);
+    }
+
+    #[test]
+    fn test_scoped_constants() {
+        let source = r#"
+const bit<8> SIZE = 4;
+control C() {
+    const bit<8> SIZE = 8;
+    apply {}
+}
+const bit<8> DOUBLE = SIZE * 2;
+"#;
+        let file = utils::parse_file(source);
+        let ast_manager = file.ast_manager.lock().unwrap();
+        let root = ast_manager.visit_root();
+
+        let inside = get_constants(&root, Position::new(4, 10));
+        assert_eq!(
+            inside.get("SIZE"),
+            Some(&Constant::new(8, ConstantType::Bit(8)))
+        );
+        let after = get_constants(&root, Position::new(7, 0));
+        assert_eq!(
+            after.get("SIZE"),
+            Some(&Constant::new(4, ConstantType::Bit(8)))
+        );
+        assert_eq!(
+            after.get("DOUBLE"),
+            Some(&Constant::new(8, ConstantType::Bit(8)))
+        );
+    }
+}
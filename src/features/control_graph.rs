@@ -0,0 +1,801 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::Range;
+
+use crate::metadata::{AstQuery, NodeKind, VisitNode, Visitable};
+use crate::utils;
+
+pub const COMMAND: &str = "p4lsp.controlGraph";
+
+// Methods of headers and tables, which aren't part of the fields read.
+const METHODS: [&str; 7] = [
+    "isValid",
+    "setValid",
+    "setInvalid",
+    "apply",
+    "hit",
+    "miss",
+    "action_run",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphNodeKind {
+    Start,
+    End,
+    Table,
+    Action,
+    Call,
+    Condition,
+    Switch,
+}
+
+impl GraphNodeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GraphNodeKind::Start => "start",
+            GraphNodeKind::End => "end",
+            GraphNodeKind::Table => "table",
+            GraphNodeKind::Action => "action",
+            GraphNodeKind::Call => "call",
+            GraphNodeKind::Condition => "condition",
+            GraphNodeKind::Switch => "switch",
+        }
+    }
+
+    fn dot_shape(&self) -> &'static str {
+        match self {
+            GraphNodeKind::Start | GraphNodeKind::End => "circle",
+            GraphNodeKind::Table => "box",
+            GraphNodeKind::Action | GraphNodeKind::Call => "ellipse",
+            GraphNodeKind::Condition | GraphNodeKind::Switch => "diamond",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphNode {
+    pub id: usize,
+    pub kind: GraphNodeKind,
+    pub label: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependencyKind {
+    Match,
+    Action,
+    ReverseMatch,
+}
+
+impl DependencyKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DependencyKind::Match => "match",
+            DependencyKind::Action => "action",
+            DependencyKind::ReverseMatch => "reverse_match",
+        }
+    }
+
+    // Reverse-match dependencies can be resolved inside a single stage.
+    fn needs_new_stage(&self) -> bool {
+        !matches!(self, DependencyKind::ReverseMatch)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub name: String,
+    pub keys: Vec<(String, String)>,
+    pub actions: Vec<String>,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    pub stage: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableDependency {
+    pub from: String,
+    pub to: String,
+    pub kind: DependencyKind,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlGraph {
+    pub control: String,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    pub tables: Vec<TableInfo>,
+    pub dependencies: Vec<TableDependency>,
+}
+
+impl ControlGraph {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "control": self.control,
+            "nodes": self.nodes.iter().map(|node| json!({
+                "id": node.id,
+                "kind": node.kind.as_str(),
+                "label": node.label,
+                "range": node.range,
+            })).collect::<Vec<Value>>(),
+            "edges": self.edges.iter().map(|edge| json!({
+                "from": edge.from,
+                "to": edge.to,
+                "label": edge.label,
+            })).collect::<Vec<Value>>(),
+            "tables": self.tables.iter().map(|table| json!({
+                "name": table.name,
+                "keys": table.keys.iter().map(|(field, match_kind)| json!({
+                    "field": field,
+                    "match_kind": match_kind,
+                })).collect::<Vec<Value>>(),
+                "actions": table.actions,
+                "reads": table.reads,
+                "writes": table.writes,
+                "stage": table.stage,
+            })).collect::<Vec<Value>>(),
+            "dependencies": self.dependencies.iter().map(|dependency| json!({
+                "from": dependency.from,
+                "to": dependency.to,
+                "kind": dependency.kind.as_str(),
+                "fields": dependency.fields,
+            })).collect::<Vec<Value>>(),
+        })
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut output = format!("digraph \"{}\" {{\n", escape(&self.control));
+        output.push_str("    node [fontname=\"monospace\"];\n");
+        for node in &self.nodes {
+            output.push_str(&format!(
+                "    n{} [label=\"{}\", shape={}];\n",
+                node.id,
+                escape(&node.label),
+                node.kind.dot_shape()
+            ));
+        }
+        for edge in &self.edges {
+            match &edge.label {
+                Some(label) => output.push_str(&format!(
+                    "    n{} -> n{} [label=\"{}\"];\n",
+                    edge.from,
+                    edge.to,
+                    escape(label)
+                )),
+                None => output.push_str(&format!("    n{} -> n{};\n", edge.from, edge.to)),
+            }
+        }
+        output.push_str("}\n");
+
+        output.push_str(&format!(
+            "digraph \"{}_dependencies\" {{\n",
+            escape(&self.control)
+        ));
+        output.push_str("    node [fontname=\"monospace\", shape=box];\n");
+        for table in &self.tables {
+            output.push_str(&format!(
+                "    \"{}\" [label=\"{}\\nstage {}\"];\n",
+                escape(&table.name),
+                escape(&table.name),
+                table.stage
+            ));
+        }
+        for dependency in &self.dependencies {
+            output.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{}: {}\"];\n",
+                escape(&dependency.from),
+                escape(&dependency.to),
+                dependency.kind.as_str(),
+                escape(&dependency.fields.join(", "))
+            ));
+        }
+        output.push_str("}\n");
+
+        output
+    }
+}
+
+pub fn get_control_graphs(
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    control_name: Option<&str>,
+) -> Vec<ControlGraph> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    let global_actions: Vec<VisitNode> = root
+        .get_children()
+        .into_iter()
+        .filter(|child| child.get().kind == NodeKind::ControlAction)
+        .collect();
+
+    root.get_children()
+        .into_iter()
+        .filter(|child| child.get().kind == NodeKind::ControlDec)
+        .filter(|control| match control_name {
+            Some(name) => utils::get_name(control).as_deref() == Some(name),
+            None => true,
+        })
+        .filter_map(|control| build_graph(control, &global_actions))
+        .collect()
+}
+
+fn build_graph(control: VisitNode, global_actions: &[VisitNode]) -> Option<ControlGraph> {
+    let name = utils::get_name(&control)?;
+    let body = control.get_child_of_kind(NodeKind::Body)?;
+
+    let mut actions: HashMap<String, ActionInfo> = HashMap::new();
+    for action in global_actions.iter().copied().chain(
+        body.get_children()
+            .into_iter()
+            .filter(|child| child.get().kind == NodeKind::ControlAction),
+    ) {
+        if let Some(action_name) = utils::get_name(&action) {
+            actions.insert(action_name, ActionInfo::new(action));
+        }
+    }
+
+    let mut tables: Vec<TableInfo> = body
+        .get_children()
+        .into_iter()
+        .filter(|child| child.get().kind == NodeKind::ControlTable)
+        .filter_map(|table| TableInfo::new(table, &actions))
+        .collect();
+
+    let mut builder = GraphBuilder {
+        nodes: vec![],
+        edges: vec![],
+        tables: tables.iter().map(|table| table.name.clone()).collect(),
+        actions: actions.keys().cloned().collect(),
+        apply_order: vec![],
+    };
+
+    let start = builder.add_node(GraphNodeKind::Start, "start".into(), control.get().range);
+    let exits = match body.get_child_of_kind(NodeKind::Block) {
+        Some(apply_block) => builder.walk_statement(apply_block, vec![(start, None)]),
+        None => vec![(start, None)],
+    };
+    let end = builder.add_node(GraphNodeKind::End, "end".into(), control.get().range);
+    builder.connect(&exits, end);
+
+    let dependencies = compute_dependencies(&builder.apply_order, &tables);
+    assign_stages(&builder.apply_order, &mut tables, &dependencies);
+
+    Some(ControlGraph {
+        control: name,
+        nodes: builder.nodes,
+        edges: builder.edges,
+        tables,
+        dependencies,
+    })
+}
+
+struct ActionInfo {
+    reads: HashSet<String>,
+    writes: HashSet<String>,
+}
+
+impl ActionInfo {
+    fn new(action: VisitNode) -> ActionInfo {
+        let mut reads = HashSet::new();
+        let mut writes = HashSet::new();
+
+        for node in action.get_descendants() {
+            match node.get().kind {
+                NodeKind::Assignment => {
+                    if let (Some(name), Some(_)) = (
+                        node.get_child_of_kind(NodeKind::NameStatement),
+                        node.get_value_node(),
+                    ) {
+                        writes.insert(utils::normalize(&name.get().content));
+                    }
+                }
+                NodeKind::Value => reads.extend(field_paths(&node)),
+                _ => {}
+            }
+        }
+
+        ActionInfo { reads, writes }
+    }
+}
+
+impl TableInfo {
+    fn new(table: VisitNode, actions: &HashMap<String, ActionInfo>) -> Option<TableInfo> {
+        let name = utils::get_name(&table)?;
+        let table_body = table.get_child_of_kind(NodeKind::Table)?;
+
+        let mut keys = vec![];
+        let mut table_actions = vec![];
+        for child in table_body.get_children() {
+            match child.get().kind {
+                NodeKind::Keys => {
+                    for key in child.get_children() {
+                        if key.get().kind != NodeKind::Key {
+                            continue;
+                        }
+                        let field = key
+                            .get_value_node()
+                            .map(|value| utils::normalize(&value.get().content))
+                            .unwrap_or_default();
+                        let match_kind = utils::get_name(&key).unwrap_or_default();
+                        keys.push((field, match_kind));
+                    }
+                }
+                NodeKind::Actions => {
+                    for action in child.get_children() {
+                        if action.get().kind != NodeKind::Action {
+                            continue;
+                        }
+                        if let Some(type_node) = action.get_type_node() {
+                            table_actions.push(utils::normalize(&type_node.get().content));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut reads: HashSet<String> = keys.iter().map(|(field, _)| field.clone()).collect();
+        let mut writes: HashSet<String> = HashSet::new();
+        for action_name in &table_actions {
+            if let Some(action) = actions.get(action_name) {
+                reads.extend(action.reads.iter().cloned());
+                writes.extend(action.writes.iter().cloned());
+            }
+        }
+
+        Some(TableInfo {
+            name,
+            keys,
+            actions: table_actions,
+            reads: sorted(reads),
+            writes: sorted(writes),
+            stage: 0,
+        })
+    }
+}
+
+type Exit = (usize, Option<String>);
+
+struct GraphBuilder {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+    tables: HashSet<String>,
+    actions: HashSet<String>,
+    apply_order: Vec<String>,
+}
+
+impl GraphBuilder {
+    fn add_node(&mut self, kind: GraphNodeKind, label: String, range: Range) -> usize {
+        let id = self.nodes.len();
+        if kind == GraphNodeKind::Table && !self.apply_order.contains(&label) {
+            self.apply_order.push(label.clone());
+        }
+        self.nodes.push(GraphNode {
+            id,
+            kind,
+            label,
+            range,
+        });
+        id
+    }
+
+    fn connect(&mut self, preds: &[Exit], to: usize) {
+        for (from, label) in preds {
+            self.edges.push(GraphEdge {
+                from: *from,
+                to,
+                label: label.clone(),
+            });
+        }
+    }
+
+    fn add_linked_node(
+        &mut self,
+        kind: GraphNodeKind,
+        label: String,
+        range: Range,
+        preds: Vec<Exit>,
+    ) -> Vec<Exit> {
+        let id = self.add_node(kind, label, range);
+        self.connect(&preds, id);
+        vec![(id, None)]
+    }
+
+    /// The table applied in the expression path, like `t` in `t.apply().hit`.
+    fn get_applied_table(&self, path: &[(String, Range)]) -> Option<String> {
+        match path {
+            [(table, _), (method, _), ..] if method == "apply" && self.tables.contains(table) => {
+                Some(table.clone())
+            }
+            _ => None,
+        }
+    }
+
+    // Table applications hidden inside an expression (`t.apply().hit`).
+    fn add_expression_tables(&mut self, expression: &VisitNode, mut preds: Vec<Exit>) -> Vec<Exit> {
+        for path in utils::get_value_paths(expression) {
+            if let Some(table) = self.get_applied_table(&path) {
+                preds = self.add_linked_node(
+                    GraphNodeKind::Table,
+                    table,
+                    expression.get().range,
+                    preds,
+                );
+            }
+        }
+        preds
+    }
+
+    fn walk_statements(&mut self, statements: Vec<VisitNode>, mut preds: Vec<Exit>) -> Vec<Exit> {
+        for statement in statements {
+            preds = self.walk_statement(statement, preds);
+        }
+        preds
+    }
+
+    fn walk_statement(&mut self, statement: VisitNode, preds: Vec<Exit>) -> Vec<Exit> {
+        match statement.get().kind {
+            NodeKind::Block | NodeKind::BodyIf | NodeKind::BodyElse => {
+                self.walk_statements(statement.get_children(), preds)
+            }
+            NodeKind::Assignment => {
+                let name_statement = match statement.get_child_of_kind(NodeKind::NameStatement) {
+                    Some(name_statement) => name_statement,
+                    None => return preds,
+                };
+
+                if let Some(value) = statement.get_value_node() {
+                    return self.add_expression_tables(&value, preds);
+                }
+
+                let range = statement.get().range;
+                let path = utils::get_lvalue_path(&name_statement);
+                let name = utils::normalize(&name_statement.get().content);
+                match self.get_applied_table(&path) {
+                    Some(table) if path.len() == 2 => {
+                        self.add_linked_node(GraphNodeKind::Table, table, range, preds)
+                    }
+                    _ if self.actions.contains(&name) => {
+                        self.add_linked_node(GraphNodeKind::Action, name, range, preds)
+                    }
+                    _ => self.add_linked_node(GraphNodeKind::Call, name, range, preds),
+                }
+            }
+            NodeKind::DirectApplication => {
+                let name = statement
+                    .get_type_node()
+                    .map(|type_node| utils::normalize(&type_node.get().content))
+                    .unwrap_or_default();
+                self.add_linked_node(
+                    GraphNodeKind::Call,
+                    format!("{name}.apply"),
+                    statement.get().range,
+                    preds,
+                )
+            }
+            NodeKind::VariableDec | NodeKind::ConstantDec => match statement.get_value_node() {
+                Some(value) => self.add_expression_tables(&value, preds),
+                None => preds,
+            },
+            NodeKind::Conditional => self.walk_conditional(statement, preds),
+            NodeKind::Switch => self.walk_switch(statement, preds),
+            _ => preds,
+        }
+    }
+
+    fn walk_conditional(&mut self, statement: VisitNode, preds: Vec<Exit>) -> Vec<Exit> {
+        let condition = match statement.get_value_node() {
+            Some(condition) => condition,
+            None => return preds,
+        };
+        let text = utils::normalize(&condition.get().content);
+
+        let (true_label, false_label) = match self.get_table_result(&condition) {
+            Some(true) => ("hit", "miss"),
+            Some(false) => ("miss", "hit"),
+            None => ("true", "false"),
+        };
+
+        let preds = self.add_expression_tables(&condition, preds);
+        let condition_id = self.add_node(GraphNodeKind::Condition, text, condition.get().range);
+        self.connect(&preds, condition_id);
+
+        let mut exits = vec![];
+        let true_branch = vec![(condition_id, Some(true_label.to_string()))];
+        match statement.get_child_of_kind(NodeKind::BodyIf) {
+            Some(body) => exits.append(&mut self.walk_statement(body, true_branch)),
+            None => exits.extend(true_branch),
+        }
+        let false_branch = vec![(condition_id, Some(false_label.to_string()))];
+        match statement.get_child_of_kind(NodeKind::BodyElse) {
+            Some(body) => exits.append(&mut self.walk_statement(body, false_branch)),
+            None => exits.extend(false_branch),
+        }
+
+        exits
+    }
+
+    /// Whether the condition holds when the table it applies hits, for
+    /// conditions like `t.apply().hit` or `!t.apply().miss`.
+    fn get_table_result(&self, condition: &VisitNode) -> Option<bool> {
+        let paths = utils::get_value_paths(condition);
+        let [path] = paths.as_slice() else {
+            return None;
+        };
+        self.get_applied_table(path)?;
+        let hit = match path.get(2).map(|(result, _)| result.as_str()) {
+            Some("hit") => true,
+            Some("miss") => false,
+            _ => return None,
+        };
+
+        let node = condition.get();
+        let before = utils::get_offset(&node.content, node.range.start, path[0].1.start);
+        let negated = node.content[..before]
+            .trim_end_matches(|c: char| c == '(' || c.is_whitespace())
+            .ends_with('!');
+        Some(hit ^ negated)
+    }
+
+    fn walk_switch(&mut self, statement: VisitNode, preds: Vec<Exit>) -> Vec<Exit> {
+        let expression = match statement.get_value_node() {
+            Some(expression) => expression,
+            None => return preds,
+        };
+
+        let preds = self.add_expression_tables(&expression, preds);
+        let switch_id = self.add_node(
+            GraphNodeKind::Switch,
+            utils::normalize(&expression.get().content),
+            expression.get().range,
+        );
+        self.connect(&preds, switch_id);
+
+        let mut exits = vec![];
+        let mut has_default = false;
+        for label in statement.get_children() {
+            if label.get().kind != NodeKind::SwitchLabel {
+                continue;
+            }
+            let label_text = label
+                .get_value_node()
+                .map(|value| utils::normalize(&value.get().content))
+                .unwrap_or_default();
+            has_default |= label_text == "default";
+
+            let branch = vec![(switch_id, Some(label_text))];
+            match label.get_child_of_kind(NodeKind::Block) {
+                Some(block) => exits.append(&mut self.walk_statement(block, branch)),
+                None => exits.extend(branch),
+            }
+        }
+        if !has_default {
+            exits.push((switch_id, Some("default".into())));
+        }
+
+        exits
+    }
+}
+
+fn compute_dependencies(apply_order: &[String], tables: &[TableInfo]) -> Vec<TableDependency> {
+    let find = |name: &String| tables.iter().find(|table| &table.name == name);
+    let mut dependencies = vec![];
+
+    for (i, first_name) in apply_order.iter().enumerate() {
+        let first = match find(first_name) {
+            Some(table) => table,
+            None => continue,
+        };
+        for second_name in &apply_order[i + 1..] {
+            let second = match find(second_name) {
+                Some(table) => table,
+                None => continue,
+            };
+            let second_keys: Vec<String> = second.keys.iter().map(|k| k.0.clone()).collect();
+            let first_keys: Vec<String> = first.keys.iter().map(|k| k.0.clone()).collect();
+
+            let candidates = [
+                (
+                    DependencyKind::Match,
+                    intersect(&first.writes, &second_keys),
+                ),
+                (
+                    DependencyKind::Action,
+                    intersect(&first.writes, &second.writes),
+                ),
+                (
+                    DependencyKind::ReverseMatch,
+                    intersect(&first_keys, &second.writes),
+                ),
+            ];
+            for (kind, fields) in candidates {
+                if !fields.is_empty() {
+                    dependencies.push(TableDependency {
+                        from: first.name.clone(),
+                        to: second.name.clone(),
+                        kind,
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+
+    dependencies
+}
+
+fn assign_stages(
+    apply_order: &[String],
+    tables: &mut [TableInfo],
+    dependencies: &[TableDependency],
+) {
+    let mut stages: HashMap<String, usize> = HashMap::new();
+    for name in apply_order {
+        let stage = dependencies
+            .iter()
+            .filter(|dependency| &dependency.to == name)
+            .filter_map(|dependency| {
+                let from_stage = *stages.get(&dependency.from)?;
+                Some(if dependency.kind.needs_new_stage() {
+                    from_stage + 1
+                } else {
+                    from_stage
+                })
+            })
+            .max()
+            .unwrap_or(0);
+        stages.insert(name.clone(), stage);
+    }
+
+    for table in tables.iter_mut() {
+        table.stage = stages.get(&table.name).copied().unwrap_or(0);
+    }
+}
+
+/// The fields read in the expression `value`, without the methods called on
+/// them such as `isValid` in `hdr.ipv4.isValid()`.
+fn field_paths(value: &VisitNode) -> Vec<String> {
+    utils::get_value_paths(value)
+        .into_iter()
+        .filter_map(|path| {
+            let names: Vec<&str> = path
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .take_while(|name| !METHODS.contains(name))
+                .collect();
+            // Plain names are locals and parameters rather than fields.
+            (names.len() > 1).then(|| names.join("."))
+        })
+        .collect()
+}
+
+fn intersect(first: &[String], second: &[String]) -> Vec<String> {
+    let mut fields: Vec<String> = first
+        .iter()
+        .filter(|field| second.contains(field))
+        .cloned()
+        .collect();
+    fields.dedup();
+    fields
+}
+
+fn sorted(set: HashSet<String>) -> Vec<String> {
+    let mut vec: Vec<String> = set.into_iter().collect();
+    vec.sort();
+    vec
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_control_graphs, DependencyKind, GraphNodeKind};
+    use crate::utils;
+
+    const SOURCE: &str = r#"
+control MyIngress(inout headers hdr, inout metadata meta) {
+    action set_port(bit<9> port) {
+        meta.egress_port = port;
+    }
+    action drop() {
+        mark_to_drop();
+    }
+    table ipv4_lpm {
+        key = { hdr.ipv4.dstAddr: lpm; }
+        actions = { set_port; drop; }
+    }
+    table port_acl {
+        key = { meta.egress_port: exact; }
+        actions = { drop; }
+    }
+    apply {
+        // acl.apply();
+        if (!ipv4_lpm.apply().miss) {
+            port_acl.apply();
+        }
+    }
+}
+"#;
+
+    #[test]
+    fn test_control_graph() {
+        let file = utils::parse_file(SOURCE);
+        let graphs = get_control_graphs(&file.ast_manager, Some("MyIngress"));
+        let [graph] = graphs.as_slice() else {
+            panic!("expected one graph, got {}", graphs.len());
+        };
+
+        let labels: Vec<(GraphNodeKind, &str)> = graph
+            .nodes
+            .iter()
+            .map(|node| (node.kind, node.label.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                (GraphNodeKind::Start, "start"),
+                (GraphNodeKind::Table, "ipv4_lpm"),
+                (GraphNodeKind::Condition, "!ipv4_lpm.apply().miss"),
+                (GraphNodeKind::Table, "port_acl"),
+                (GraphNodeKind::End, "end"),
+            ]
+        );
+
+        let edges: Vec<(usize, usize, Option<&str>)> = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.label.as_deref()))
+            .collect();
+        // The commented out table isn't applied.
+        assert_eq!(
+            edges,
+            vec![
+                (0, 1, None),
+                (1, 2, None),
+                (2, 3, Some("hit")),
+                (3, 4, None),
+                (2, 4, Some("miss")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_dependencies() {
+        let file = utils::parse_file(SOURCE);
+        let graph = get_control_graphs(&file.ast_manager, None).remove(0);
+
+        let ipv4_lpm = &graph.tables[0];
+        assert_eq!(ipv4_lpm.reads, vec!["hdr.ipv4.dstAddr"]);
+        assert_eq!(ipv4_lpm.writes, vec!["meta.egress_port"]);
+
+        let [dependency] = graph.dependencies.as_slice() else {
+            panic!("expected one dependency");
+        };
+        assert_eq!(
+            (dependency.from.as_str(), dependency.to.as_str()),
+            ("ipv4_lpm", "port_acl")
+        );
+        assert_eq!(dependency.kind, DependencyKind::Match);
+        assert_eq!(dependency.fields, vec!["meta.egress_port"]);
+        assert_eq!(
+            graph
+                .tables
+                .iter()
+                .map(|table| table.stage)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+}
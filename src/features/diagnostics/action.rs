@@ -0,0 +1,823 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range};
+
+use crate::metadata::{AstQuery, Direction, NodeKind, SymbolTableQuery, VisitNode, Visitable};
+use crate::utils;
+
+use super::diagnostics::{new_diagnostic, DiagnosticProvider};
+
+lazy_static! {
+    static ref IDENTIFIER: Regex = Regex::new(r"\b[A-Za-z_]\w*").unwrap();
+}
+
+pub struct Action {}
+
+impl DiagnosticProvider for Action {
+    fn get_diagnostics(
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        _symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+
+        let mut context = Context::default();
+        for child in root.get_children() {
+            match child.get().kind {
+                NodeKind::ConstantDec | NodeKind::PreprocDefine => {
+                    context.constants.extend(utils::get_name(&child));
+                }
+                NodeKind::ControlAction => context.add_action(&child),
+                NodeKind::Function => {
+                    if let Some(prototype) = child.get_child_of_kind(NodeKind::FunctionName) {
+                        if let Some(name) = utils::get_name(&prototype) {
+                            context.callables.insert(name, get_parameters(&prototype));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut diags = vec![];
+        for child in root.get_children() {
+            match child.get().kind {
+                NodeKind::ControlAction => {
+                    check_action(&child, &HashMap::new(), &context, &mut diags);
+                }
+                NodeKind::Function => {
+                    let parameters = child
+                        .get_child_of_kind(NodeKind::FunctionName)
+                        .map(|prototype| get_parameters(&prototype))
+                        .unwrap_or_default();
+                    if let Some(block) = child.get_child_of_kind(NodeKind::Block) {
+                        check_body(
+                            &block,
+                            &parameters,
+                            &read_only(&parameters),
+                            &context,
+                            &mut diags,
+                        );
+                    }
+                }
+                NodeKind::ControlDec => check_control(&child, &context, &mut diags),
+                NodeKind::ParserDec => {
+                    let parameters = get_parameters(&child);
+                    if let Some(body) = child.get_child_of_kind(NodeKind::Body) {
+                        check_writes(&body, &read_only(&parameters), &context, &mut diags);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        diags
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Parameter {
+    name: String,
+    direction: Option<Direction>,
+    has_default: bool,
+    range: Range,
+}
+
+impl Parameter {
+    fn describe(&self) -> &'static str {
+        match self.direction {
+            Some(Direction::In) => "`in`",
+            Some(Direction::Out) => "`out`",
+            Some(Direction::InOut) => "`inout`",
+            None => "directionless",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Context {
+    callables: HashMap<String, Vec<Parameter>>,
+    actions: HashSet<String>,
+    constants: HashSet<String>,
+}
+
+impl Context {
+    fn add_action(&mut self, action: &VisitNode) {
+        if let Some(name) = utils::get_name(action) {
+            self.callables.insert(name.clone(), get_parameters(action));
+            self.actions.insert(name);
+        }
+    }
+
+    fn is_lvalue(&self, arg: &Argument) -> bool {
+        arg.lvalue_root
+            .as_ref()
+            .is_some_and(|root| !self.constants.contains(root))
+    }
+}
+
+fn check_control(control: &VisitNode, context: &Context, diags: &mut Vec<Diagnostic>) {
+    let body = match control.get_child_of_kind(NodeKind::Body) {
+        Some(body) => body,
+        None => return,
+    };
+    let parameters = get_parameters(control);
+    let control_read_only = read_only(&parameters);
+
+    let mut context = context.clone();
+    for child in body.get_children() {
+        match child.get().kind {
+            NodeKind::ConstantDec => context.constants.extend(utils::get_name(&child)),
+            NodeKind::ControlAction => context.add_action(&child),
+            _ => {}
+        }
+    }
+
+    for child in body.get_children() {
+        match child.get().kind {
+            NodeKind::ControlAction => check_action(&child, &control_read_only, &context, diags),
+            NodeKind::ControlTable => check_table(&child, &context, diags),
+            NodeKind::Block => check_body(&child, &parameters, &control_read_only, &context, diags),
+            _ => {}
+        }
+    }
+}
+
+fn check_action(
+    action: &VisitNode,
+    enclosing_read_only: &HashMap<String, Parameter>,
+    context: &Context,
+    diags: &mut Vec<Diagnostic>,
+) {
+    let parameters = get_parameters(action);
+
+    let mut seen_directionless = false;
+    for parameter in &parameters {
+        if parameter.direction.is_none() {
+            seen_directionless = true;
+        } else if seen_directionless {
+            diags.push(new_diagnostic(
+                parameter.range,
+                DiagnosticSeverity::ERROR,
+                "parameter-order",
+                format!(
+                    "Directional parameter `{}` must come before the directionless parameters.",
+                    parameter.name
+                ),
+            ));
+        }
+    }
+
+    // Action parameters shadow the parameters of the enclosing control.
+    let mut action_read_only: HashMap<String, Parameter> = enclosing_read_only
+        .iter()
+        .filter(|(name, _)| !parameters.iter().any(|p| &&p.name == name))
+        .map(|(name, parameter)| (name.clone(), parameter.clone()))
+        .collect();
+    action_read_only.extend(read_only(&parameters));
+
+    if let Some(block) = action.get_child_of_kind(NodeKind::Block) {
+        check_body(&block, &parameters, &action_read_only, context, diags);
+    }
+}
+
+fn check_body(
+    block: &VisitNode,
+    parameters: &[Parameter],
+    read_only: &HashMap<String, Parameter>,
+    context: &Context,
+    diags: &mut Vec<Diagnostic>,
+) {
+    check_writes(block, read_only, context, diags);
+
+    let out_parameters: Vec<String> = parameters
+        .iter()
+        .filter(|parameter| parameter.direction == Some(Direction::Out))
+        .map(|parameter| parameter.name.clone())
+        .collect();
+    if !out_parameters.is_empty() {
+        let mut checker = UninitializedChecker {
+            out_parameters,
+            reported: HashSet::new(),
+            context,
+            diags,
+        };
+        checker.walk(block, &mut HashSet::new());
+    }
+}
+
+fn check_writes(
+    body: &VisitNode,
+    read_only: &HashMap<String, Parameter>,
+    context: &Context,
+    diags: &mut Vec<Diagnostic>,
+) {
+    for statement in body.get_descendants() {
+        if statement.get().kind != NodeKind::Assignment {
+            continue;
+        }
+        let name_node = match statement.get_child_of_kind(NodeKind::NameStatement) {
+            Some(name_node) => name_node,
+            None => continue,
+        };
+        let name = utils::normalize(&name_node.get().content);
+
+        if statement.get_value_node().is_some() {
+            if let Some(parameter) = root_name(&name).and_then(|root| read_only.get(&root)) {
+                diags.push(new_diagnostic(
+                    name_node.get().range,
+                    DiagnosticSeverity::ERROR,
+                    "assign-to-in-parameter",
+                    format!(
+                        "Cannot assign to {} parameter `{}`.",
+                        parameter.describe(),
+                        parameter.name
+                    ),
+                ));
+            }
+            continue;
+        }
+
+        let parameters = match context.callables.get(&name) {
+            Some(parameters) => parameters,
+            None => continue,
+        };
+        let args = get_args(&statement);
+
+        if context.actions.contains(&name) || args.len() > parameters.len() {
+            let required = parameters.iter().filter(|p| !p.has_default).count();
+            if args.len() < required || args.len() > parameters.len() {
+                diags.push(new_diagnostic(
+                    statement.get().range,
+                    DiagnosticSeverity::ERROR,
+                    "unbound-parameters",
+                    format!(
+                        "`{name}` expects {} argument(s) but {} were given; direct calls must bind every parameter.",
+                        parameters.len(),
+                        args.len()
+                    ),
+                ));
+            }
+        }
+
+        for (arg, parameter) in bind_args(&args, parameters) {
+            if !matches!(parameter.direction, Some(Direction::Out | Direction::InOut)) {
+                continue;
+            }
+            let text = utils::normalize(&arg.text);
+            if !context.is_lvalue(arg) {
+                diags.push(new_diagnostic(
+                    arg.range,
+                    DiagnosticSeverity::ERROR,
+                    "argument-not-lvalue",
+                    format!(
+                        "Argument `{text}` for {} parameter `{}` must be an lvalue.",
+                        parameter.describe(),
+                        parameter.name
+                    ),
+                ));
+            } else if let Some(read_only_parameter) =
+                root_name(&text).and_then(|root| read_only.get(&root))
+            {
+                diags.push(new_diagnostic(
+                    arg.range,
+                    DiagnosticSeverity::ERROR,
+                    "assign-to-in-parameter",
+                    format!(
+                        "Cannot pass {} parameter `{}` as an {} argument.",
+                        read_only_parameter.describe(),
+                        read_only_parameter.name,
+                        parameter.describe()
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+fn check_table(table: &VisitNode, context: &Context, diags: &mut Vec<Diagnostic>) {
+    let table_body = match table.get_child_of_kind(NodeKind::Table) {
+        Some(table_body) => table_body,
+        None => return,
+    };
+
+    for property in table_body.get_children() {
+        match property.get().kind {
+            NodeKind::Actions => {
+                for action in property.get_children() {
+                    let name_node = match action.get_type_node() {
+                        Some(name_node) => name_node,
+                        None => continue,
+                    };
+                    let name = utils::normalize(&name_node.get().content);
+                    let parameters = match context.callables.get(&name) {
+                        Some(parameters) => parameters,
+                        None => continue,
+                    };
+
+                    let directional = parameters
+                        .iter()
+                        .filter(|parameter| parameter.direction.is_some())
+                        .count();
+                    let bound = get_args(&action).len();
+                    if bound != directional {
+                        diags.push(new_diagnostic(
+                            action.get().range,
+                            DiagnosticSeverity::ERROR,
+                            "unbound-parameters",
+                            format!(
+                                "Action `{name}` has {directional} directional parameter(s) but {bound} are bound; only directionless parameters can be left to the control plane."
+                            ),
+                        ));
+                    }
+                }
+            }
+            NodeKind::TableKw => {
+                let is_default_action = property
+                    .get_child_of_kind(NodeKind::Name)
+                    .is_some_and(|name| utils::normalize(&name.get().content) == "default_action");
+                let value = match property.get_value_node() {
+                    Some(value) if is_default_action => value,
+                    _ => continue,
+                };
+
+                let text = utils::normalize(&value.get().content);
+                let (name, bound) = match text.split_once('(') {
+                    Some((name, args)) => {
+                        let args = args.trim_end_matches(';').trim_end_matches(')');
+                        let count = if args.is_empty() {
+                            0
+                        } else {
+                            utils::split_top_level(args, ',').len()
+                        };
+                        (name.to_string(), count)
+                    }
+                    None => (text.clone(), 0),
+                };
+                if let Some(parameters) = context.callables.get(&name) {
+                    if bound != parameters.len() {
+                        diags.push(new_diagnostic(
+                            value.get().range,
+                            DiagnosticSeverity::ERROR,
+                            "unbound-parameters",
+                            format!(
+                                "Default action `{name}` must bind all {} parameter(s), {bound} given.",
+                                parameters.len()
+                            ),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct UninitializedChecker<'a> {
+    out_parameters: Vec<String>,
+    reported: HashSet<String>,
+    context: &'a Context,
+    diags: &'a mut Vec<Diagnostic>,
+}
+
+impl UninitializedChecker<'_> {
+    fn read(&mut self, text: &str, range: Range, written: &HashSet<String>) {
+        for name in root_names(text) {
+            if self.out_parameters.contains(&name)
+                && !written.contains(&name)
+                && self.reported.insert(name.clone())
+            {
+                self.diags.push(new_diagnostic(
+                    range,
+                    DiagnosticSeverity::WARNING,
+                    "out-read-before-write",
+                    format!("`out` parameter `{name}` is read before being written."),
+                ));
+            }
+        }
+    }
+
+    fn read_value(&mut self, node: &VisitNode, written: &HashSet<String>) {
+        if let Some(value) = node.get_value_node() {
+            self.read(&value.get().content, value.get().range, written);
+        }
+    }
+
+    fn walk_branches(&mut self, branches: Vec<Option<VisitNode>>, written: &mut HashSet<String>) {
+        // A parameter is only written after the statement if every branch writes it.
+        let mut result: Option<HashSet<String>> = None;
+        for branch in branches {
+            let mut branch_written = written.clone();
+            if let Some(branch) = branch {
+                self.walk(&branch, &mut branch_written);
+            }
+            result = Some(match result {
+                Some(result) => result.intersection(&branch_written).cloned().collect(),
+                None => branch_written,
+            });
+        }
+        if let Some(result) = result {
+            *written = result;
+        }
+    }
+
+    fn walk(&mut self, statement: &VisitNode, written: &mut HashSet<String>) {
+        match statement.get().kind {
+            NodeKind::Block | NodeKind::BodyIf | NodeKind::BodyElse => {
+                for child in statement.get_children() {
+                    self.walk(&child, written);
+                }
+            }
+            NodeKind::VariableDec | NodeKind::ConstantDec | NodeKind::Return => {
+                self.read_value(statement, written);
+            }
+            NodeKind::Assignment => {
+                let name = statement
+                    .get_child_of_kind(NodeKind::NameStatement)
+                    .map(|name| utils::normalize(&name.get().content))
+                    .unwrap_or_default();
+
+                if statement.get_value_node().is_some() {
+                    self.read_value(statement, written);
+                    written.extend(root_name(&name));
+                    return;
+                }
+
+                let args = get_args(statement);
+                match self.context.callables.get(&name) {
+                    Some(parameters) => {
+                        let bound = bind_args(&args, parameters);
+                        for (arg, parameter) in &bound {
+                            if parameter.direction != Some(Direction::Out) {
+                                self.read(&arg.text, arg.range, written);
+                            }
+                        }
+                        for (arg, parameter) in &bound {
+                            if matches!(
+                                parameter.direction,
+                                Some(Direction::Out | Direction::InOut)
+                            ) {
+                                written.extend(root_name(&utils::normalize(&arg.text)));
+                            }
+                        }
+                    }
+                    // Extern methods may write through any of their arguments.
+                    None => {
+                        for arg in &args {
+                            written.extend(root_name(&utils::normalize(&arg.text)));
+                        }
+                    }
+                }
+            }
+            NodeKind::DirectApplication => {
+                for arg in get_args(statement) {
+                    written.extend(root_name(&utils::normalize(&arg.text)));
+                }
+            }
+            NodeKind::Conditional => {
+                self.read_value(statement, written);
+                self.walk_branches(
+                    vec![
+                        statement.get_child_of_kind(NodeKind::BodyIf),
+                        statement.get_child_of_kind(NodeKind::BodyElse),
+                    ],
+                    written,
+                );
+            }
+            NodeKind::Switch => {
+                self.read_value(statement, written);
+
+                let labels: Vec<VisitNode> = statement
+                    .get_children()
+                    .into_iter()
+                    .filter(|child| child.get().kind == NodeKind::SwitchLabel)
+                    .collect();
+                let has_default = labels.iter().any(|label| {
+                    label
+                        .get_value_node()
+                        .is_some_and(|value| utils::normalize(&value.get().content) == "default")
+                });
+
+                let mut branches: Vec<Option<VisitNode>> = labels
+                    .iter()
+                    .map(|label| label.get_child_of_kind(NodeKind::Block))
+                    .collect();
+                if !has_default {
+                    branches.push(None);
+                }
+                self.walk_branches(branches, written);
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Argument {
+    name: Option<String>,
+    text: String,
+    range: Range,
+    lvalue_root: Option<String>,
+}
+
+fn get_args(node: &VisitNode) -> Vec<Argument> {
+    let args = match node.get_child_of_kind(NodeKind::Args) {
+        Some(args) => args,
+        None => return vec![],
+    };
+
+    args.get_children()
+        .into_iter()
+        .filter(|arg| arg.get().kind == NodeKind::Arg)
+        .map(|arg| {
+            let value = arg.get_value_node();
+            Argument {
+                name: utils::get_name(&arg),
+                text: value
+                    .map(|value| value.get().content.clone())
+                    .unwrap_or_default(),
+                range: arg.get().range,
+                lvalue_root: value.and_then(|value| get_lvalue_root(&value)),
+            }
+        })
+        .collect()
+}
+
+fn bind_args<'a>(
+    args: &'a [Argument],
+    parameters: &'a [Parameter],
+) -> Vec<(&'a Argument, &'a Parameter)> {
+    args.iter()
+        .enumerate()
+        .filter_map(|(index, arg)| {
+            let parameter = match &arg.name {
+                Some(name) => parameters.iter().find(|p| &p.name == name),
+                None => parameters.get(index),
+            }?;
+            Some((arg, parameter))
+        })
+        .collect()
+}
+
+fn get_parameters(node: &VisitNode) -> Vec<Parameter> {
+    let params = match node.get_child_of_kind(NodeKind::Params) {
+        Some(params) => params,
+        None => return vec![],
+    };
+
+    params
+        .get_children()
+        .into_iter()
+        .filter(|param| param.get().kind == NodeKind::Param)
+        .filter_map(|param| {
+            let direction =
+                param
+                    .get_children()
+                    .into_iter()
+                    .find_map(|child| match &child.get().kind {
+                        NodeKind::Direction(direction) => Some(direction.clone()),
+                        _ => None,
+                    });
+
+            Some(Parameter {
+                name: utils::get_name(&param)?,
+                direction,
+                has_default: param.get_value_node().is_some(),
+                range: param.get().range,
+            })
+        })
+        .collect()
+}
+
+fn read_only(parameters: &[Parameter]) -> HashMap<String, Parameter> {
+    parameters
+        .iter()
+        .filter(|parameter| matches!(parameter.direction, Some(Direction::In) | None))
+        .map(|parameter| (parameter.name.clone(), parameter.clone()))
+        .collect()
+}
+
+/// The root variable of `value` if it is a variable, a member of one or an
+/// element of a stack: a single path of names spanning the whole value, only
+/// separated by `.` and indices.
+fn get_lvalue_root(value: &VisitNode) -> Option<String> {
+    let node = value.get();
+    // Names in indices are paths of their own.
+    let paths: Vec<Vec<(String, Range)>> = utils::get_value_paths(value)
+        .into_iter()
+        .filter(|path| {
+            let offset = utils::get_offset(&node.content, node.range.start, path[0].1.start);
+            utils::get_depth(node.content.get(..offset).unwrap_or_default()) == 0
+        })
+        .collect();
+    let path = match paths.as_slice() {
+        [path] if path[0].1.start == node.range.start => path,
+        _ => return None,
+    };
+
+    let mut ends: Vec<Position> = path.iter().skip(1).map(|(_, range)| range.start).collect();
+    ends.push(node.range.end);
+    let is_lvalue = path.iter().zip(ends).all(|((_, range), end)| {
+        let start = utils::get_offset(&node.content, node.range.start, range.end);
+        let end = utils::get_offset(&node.content, node.range.start, end);
+        let between = utils::normalize(node.content.get(start..end).unwrap_or_default());
+        is_indices(between.strip_suffix('.').unwrap_or(&between))
+    });
+    is_lvalue.then(|| path[0].0.clone())
+}
+
+// Whether `text` is only bracketed indices, like `[0]` or `[i][1]`.
+fn is_indices(text: &str) -> bool {
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth > 0 => depth -= 1,
+            '(' | ')' | ']' => return false,
+            _ if depth == 0 => return false,
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+fn root_name(text: &str) -> Option<String> {
+    IDENTIFIER
+        .find(text)
+        .filter(|found| found.start() == 0)
+        .map(|found| found.as_str().to_string())
+}
+
+fn root_names(text: &str) -> Vec<String> {
+    IDENTIFIER
+        .find_iter(text)
+        .filter(|found| !text[..found.start()].trim_end().ends_with('.'))
+        .map(|found| found.as_str().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::NumberOrString;
+
+    use super::Action;
+    use crate::features::diagnostics::diagnostics::DiagnosticProvider;
+    use crate::utils;
+
+    /// The code and line of each action diagnostic of `source_code`.
+    fn get_codes(source_code: &str) -> Vec<(String, u32)> {
+        let file = utils::parse_file(source_code);
+        Action::get_diagnostics(&file.ast_manager, &file.symbol_table_manager)
+            .iter()
+            .map(|diagnostic| {
+                let code = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => code.clone(),
+                    _ => String::new(),
+                };
+                (code, diagnostic.range.start.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_argument_not_lvalue() {
+        let source = r#"
+const bit<8> LIMIT = 8;
+action set(out bit<8> value) {
+    value = 1;
+}
+control C(inout headers hdr, inout bit<8> x) {
+    apply {
+        set(hdr.stack[x + 1].ttl);
+        set(x);
+        set(x + 1);
+        set(LIMIT);
+        set(get(x));
+        set(8w3);
+    }
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("argument-not-lvalue".to_string(), 9),
+                ("argument-not-lvalue".to_string(), 10),
+                ("argument-not-lvalue".to_string(), 11),
+                ("argument-not-lvalue".to_string(), 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_order() {
+        let source = r#"
+control C(inout bit<8> x) {
+    action ordered(inout bit<8> y, bit<8> value) {
+        y = value;
+    }
+    action unordered(bit<8> value, out bit<8> y, in bit<8> z) {
+        y = value + z;
+    }
+    apply {}
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("parameter-order".to_string(), 5),
+                ("parameter-order".to_string(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assign_to_in_parameter() {
+        let source = r#"
+action set(out bit<8> value) {
+    value = 1;
+}
+control C(inout headers hdr, in bit<8> port) {
+    action update(bit<8> ttl) {
+        ttl = 1;
+        hdr.ttl = ttl;
+    }
+    apply {
+        port = 0;
+        set(port);
+        hdr.ttl = port;
+    }
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("assign-to-in-parameter".to_string(), 6),
+                ("assign-to-in-parameter".to_string(), 10),
+                ("assign-to-in-parameter".to_string(), 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_read_before_write() {
+        let source = r#"
+action copy(out bit<8> a, out bit<8> b, in bit<8> c) {
+    if (c == 0) {
+        a = 1;
+    } else {
+        b = 1;
+    }
+    b = a;
+    a = b;
+}
+action init(out bit<8> x) {
+    copy(x, x, 0);
+    x = x + 1;
+}
+action increment(out bit<8> y) {
+    y = y + 1;
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("out-read-before-write".to_string(), 7),
+                ("out-read-before-write".to_string(), 15),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unbound_parameters() {
+        let source = r#"
+action set(inout bit<8> x, bit<8> value) {
+    x = value;
+}
+control C(inout bit<8> y) {
+    action forward(bit<9> port) {}
+    table t {
+        actions = { set(y); forward; set; }
+        default_action = set(y, 1);
+    }
+    table u {
+        actions = { forward; }
+        default_action = forward;
+    }
+    apply {
+        set(y);
+        set(y, 1);
+        forward(1);
+    }
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("unbound-parameters".to_string(), 7),
+                ("unbound-parameters".to_string(), 12),
+                ("unbound-parameters".to_string(), 15),
+            ]
+        );
+    }
+}
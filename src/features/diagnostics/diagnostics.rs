@@ -1,8 +1,13 @@
 use std::sync::{Arc, Mutex};
 
-use tower_lsp::lsp_types::Diagnostic;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range};
 
+use super::action::Action;
+use super::overflow::Overflow;
 use super::parse::Parse;
+use super::state::State;
+use super::table::Table;
+use super::width::Width;
 use crate::metadata::{AstQuery, SymbolTableQuery};
 
 macro_rules! diags {
@@ -26,16 +31,51 @@ pub trait DiagnosticProvider {
     ) -> Vec<Diagnostic>;
 }
 
+/// A diagnostic of the AST checks, identified by `code`.
+pub fn new_diagnostic(
+    range: Range,
+    severity: DiagnosticSeverity,
+    code: &str,
+    message: String,
+) -> Diagnostic {
+    Diagnostic::new(
+        range,
+        Some(severity),
+        Some(NumberOrString::String(code.to_string())),
+        Some("AST".to_string()),
+        message,
+        None,
+        None,
+    )
+}
+
+pub fn new_error(range: Range, code: &str, message: String) -> Diagnostic {
+    new_diagnostic(range, DiagnosticSeverity::ERROR, code, message)
+}
+
 pub fn get_quick_diagnostics(
     ast_query: &Arc<Mutex<impl AstQuery>>,
     symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
 ) -> Vec<Diagnostic> {
-    diags![Parse::get_diagnostics(ast_query, symbol_table_query)]
+    diags![
+        Parse::get_diagnostics(ast_query, symbol_table_query),
+        Table::get_diagnostics(ast_query, symbol_table_query),
+        State::get_diagnostics(ast_query, symbol_table_query),
+        Action::get_diagnostics(ast_query, symbol_table_query),
+        Overflow::get_diagnostics(ast_query, symbol_table_query)
+    ]
 }
 
 pub fn get_full_diagnostics(
     ast_query: &Arc<Mutex<impl AstQuery>>,
     symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
 ) -> Vec<Diagnostic> {
-    diags![Parse::get_diagnostics(ast_query, symbol_table_query)]
+    diags![
+        Parse::get_diagnostics(ast_query, symbol_table_query),
+        Table::get_diagnostics(ast_query, symbol_table_query),
+        State::get_diagnostics(ast_query, symbol_table_query),
+        Action::get_diagnostics(ast_query, symbol_table_query),
+        Overflow::get_diagnostics(ast_query, symbol_table_query),
+        Width::get_diagnostics(ast_query, symbol_table_query)
+    ]
 }
@@ -0,0 +1,115 @@
+use tower_lsp::lsp_types::{Diagnostic, Position, Range, TextEdit, Url};
+use tree_sitter::{Node, Tree};
+
+use crate::features::code_actions::{self, QuickFix};
+use crate::features::preprocessor::Preprocessor;
+use crate::utils;
+
+use super::diagnostics::new_error;
+
+// Declarations of core.p4 that programs can hardly do without.
+const CORE_NAMES: [&str; 6] = [
+    "packet_in",
+    "packet_out",
+    "NoAction",
+    "exact",
+    "ternary",
+    "lpm",
+];
+
+/// An error on the first use of a core.p4 declaration when the document
+/// doesn't include it. The fix inserts the include at `include_position`.
+pub fn get_missing_core_diagnostics(
+    uri: &Url,
+    source_code: &str,
+    tree: &Tree,
+    preprocessor: &Preprocessor,
+    include_position: Position,
+) -> Vec<Diagnostic> {
+    let is_core = uri
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .is_some_and(|name| name == "core.p4");
+    if is_core || preprocessor.may_include("core.p4") {
+        return vec![];
+    }
+
+    let Some(node) = find_core_name(tree.root_node(), source_code) else {
+        return vec![];
+    };
+    let name = node.utf8_text(source_code.as_bytes()).unwrap_or_default();
+
+    let diagnostic = new_error(
+        utils::ts_range_to_lsp_range(node.range()),
+        "missing-core-include",
+        format!("`{name}` is declared in core.p4, which isn't included."),
+    );
+    vec![code_actions::with_fixes(
+        diagnostic,
+        vec![QuickFix::new(
+            "Add `#include <core.p4>`".to_string(),
+            vec![TextEdit::new(
+                Range::new(include_position, include_position),
+                "#include <core.p4>\n".to_string(),
+            )],
+        )],
+    )]
+}
+
+fn find_core_name<'a>(node: Node<'a>, source_code: &str) -> Option<Node<'a>> {
+    if node.child_count() == 0 {
+        let text = node.utf8_text(source_code.as_bytes()).ok()?;
+        return CORE_NAMES.contains(&text).then_some(node);
+    }
+
+    let mut cursor = node.walk();
+    let children: Vec<Node> = node.children(&mut cursor).collect();
+    children
+        .into_iter()
+        .find_map(|child| find_core_name(child, source_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Url};
+
+    use super::get_missing_core_diagnostics;
+    use crate::features::code_actions;
+    use crate::features::preprocessor::Preprocessor;
+    use crate::utils;
+
+    const SOURCE: &str =
+        "parser P(packet_in packet) {\n    state start { transition accept; }\n}\n";
+
+    #[test]
+    fn test_include_fix() {
+        let uri = Url::parse("file:///test.p4").unwrap();
+        let diagnostics = get_missing_core_diagnostics(
+            &uri,
+            SOURCE,
+            &utils::parse(SOURCE),
+            &Preprocessor::default(),
+            Position::new(0, 0),
+        );
+        assert_eq!(diagnostics.len(), 1);
+
+        let fixes = code_actions::get_fixes(&diagnostics[0]);
+        assert_eq!(
+            utils::apply_edits(SOURCE, &fixes[0].edits),
+            format!("#include <core.p4>\n{SOURCE}")
+        );
+    }
+
+    #[test]
+    fn test_included_header() {
+        let file = utils::parse_file(SOURCE);
+        let is_missing_core = |diagnostics: Vec<Diagnostic>| {
+            diagnostics.iter().any(|diagnostic| {
+                diagnostic.code == Some(NumberOrString::String("missing-core-include".to_string()))
+            })
+        };
+
+        assert!(is_missing_core(file.get_quick_diagnostics(false)));
+        assert!(!is_missing_core(file.get_quick_diagnostics(true)));
+    }
+}
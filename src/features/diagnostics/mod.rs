@@ -1,4 +1,12 @@
+mod action;
 mod diagnostics;
+mod include;
+mod overflow;
 mod parse;
+mod state;
+mod table;
+mod width;
 
 pub use diagnostics::{get_full_diagnostics, get_quick_diagnostics};
+pub use include::get_missing_core_diagnostics;
+pub use table::{get_default_action_fix, skip_included_names, BUILTIN_MATCH_KINDS};
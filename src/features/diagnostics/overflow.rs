@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+
+use crate::features::constant_folding::{self, ConstantType};
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, Visitable};
+
+use super::diagnostics::{new_diagnostic, DiagnosticProvider};
+
+pub struct Overflow {}
+
+impl DiagnosticProvider for Overflow {
+    fn get_diagnostics(
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        _symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+
+        let mut diags = vec![];
+        for node in root.get_descendants() {
+            match node.get().kind {
+                NodeKind::Value => {
+                    for (literal, type_) in
+                        constant_folding::get_overflowing_literals(&node.get().content)
+                    {
+                        diags.push(new_diagnostic(
+                            node.get().range,
+                            DiagnosticSeverity::WARNING,
+                            "literal-overflow",
+                            format!(
+                                "Literal `{literal}` doesn't fit in {type_} and will be truncated."
+                            ),
+                        ));
+                    }
+                }
+                NodeKind::ConstantDec | NodeKind::VariableDec => {
+                    let declared_type = match node
+                        .get_type_node()
+                        .and_then(|type_node| type_node.get_type())
+                        .and_then(ConstantType::from_type)
+                    {
+                        Some(type_) if type_ != ConstantType::Bool => type_,
+                        _ => continue,
+                    };
+                    let value = match node.get_value_node() {
+                        Some(value) => value,
+                        None => continue,
+                    };
+
+                    // Only untyped values are implicitly cast to the declared type.
+                    let constants = constant_folding::get_constants(&root, node.get().range.start);
+                    let constant = constant_folding::evaluate(&value.get().content, &constants);
+                    if let Some(constant) = constant {
+                        if constant.type_ == ConstantType::Infinite
+                            && !declared_type.fits(constant.value)
+                        {
+                            diags.push(new_diagnostic(
+                                value.get().range,
+                                DiagnosticSeverity::ERROR,
+                                "constant-overflow",
+                                format!("Value {} doesn't fit in {declared_type}.", constant.value),
+                            ));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        diags
+    }
+}
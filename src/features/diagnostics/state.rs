@@ -0,0 +1,153 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{Diagnostic, Position, Range, TextEdit};
+
+use crate::features::code_actions::{self, QuickFix};
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, Type, VisitNode, Visitable};
+use crate::utils;
+
+use super::diagnostics::{new_error, DiagnosticProvider};
+
+const BUILTIN_STATES: [&str; 2] = ["accept", "reject"];
+
+pub struct State {}
+
+impl DiagnosticProvider for State {
+    fn get_diagnostics(
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        _symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+
+        let mut diags = vec![];
+        for parser in root.get_children() {
+            if parser.get().kind != NodeKind::ParserDec {
+                continue;
+            }
+            if let Some(body) = parser.get_child_of_kind(NodeKind::Body) {
+                diags.append(&mut check_parser(&body));
+            }
+        }
+
+        diags
+    }
+}
+
+fn check_parser(body: &VisitNode) -> Vec<Diagnostic> {
+    let states: Vec<VisitNode> = body
+        .get_children()
+        .into_iter()
+        .filter(|child| child.get().kind == NodeKind::StateParser)
+        .collect();
+    let mut names: Vec<String> = BUILTIN_STATES.map(String::from).to_vec();
+    names.extend(states.iter().filter_map(utils::get_name));
+
+    let mut diags = vec![];
+    for state in &states {
+        let Some(transition) = state.get_child_of_kind(NodeKind::TransitionStatement) else {
+            continue;
+        };
+
+        // The next state of a plain transition, or of each select case.
+        let mut targets: Vec<(String, Range)> = vec![];
+        let mut add_targets = |nodes: Vec<VisitNode>| {
+            for node in nodes {
+                if node.get().kind == NodeKind::Type(Type::Name) {
+                    targets.push((utils::normalize(&node.get().content), node.get().range));
+                }
+            }
+        };
+        add_targets(transition.get_children());
+        if let Some(cases) = transition.get_child_of_kind(NodeKind::Body) {
+            for case in cases.get_children() {
+                add_targets(case.get_children());
+            }
+        }
+
+        for (name, range) in targets {
+            if name.is_empty() || names.contains(&name) {
+                continue;
+            }
+
+            let mut fixes: Vec<QuickFix> = code_actions::get_suggestion(&name, &names)
+                .map(|suggestion| QuickFix::rename(range, suggestion))
+                .into_iter()
+                .collect();
+            fixes.extend(states.last().map(|last| declare_state(body, last, &name)));
+            diags.push(code_actions::with_fixes(
+                new_error(
+                    range,
+                    "undefined-state",
+                    format!("State `{name}` is not defined."),
+                ),
+                fixes,
+            ));
+        }
+    }
+
+    diags
+}
+
+/// The fix declaring a state `name` accepting the packet, after the last
+/// state of the parser and indented like it.
+fn declare_state(body: &VisitNode, last: &VisitNode, name: &str) -> QuickFix {
+    let range = last.get().range;
+    let indent = utils::get_indentation(body, last);
+    // The transition is indented like the last state's, or one level more
+    // than the state when it is on the same line.
+    let inner_indent = match last.get_child_of_kind(NodeKind::TransitionStatement) {
+        Some(transition) if transition.get().range.start.line > range.start.line => {
+            utils::get_indentation(last, &transition)
+        }
+        _ => indent.repeat(2),
+    };
+    let position = Position::new(range.end.line, range.end.character);
+    QuickFix::new(
+        format!("Declare state `{name}`"),
+        vec![TextEdit::new(
+            Range::new(position, position),
+            format!("\n\n{indent}state {name} {{\n{inner_indent}transition accept;\n{indent}}}"),
+        )],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::State;
+    use crate::features::code_actions;
+    use crate::features::diagnostics::diagnostics::DiagnosticProvider;
+    use crate::utils;
+
+    #[test]
+    fn test_declare_state_fix() {
+        let source = r#"
+parser P(packet_in packet) {
+  state start {
+    transition parse_ipv4;
+  }
+}
+"#;
+        let file = utils::parse_file(source);
+        let fix = State::get_diagnostics(&file.ast_manager, &file.symbol_table_manager)
+            .iter()
+            .flat_map(code_actions::get_fixes)
+            .find(|fix| fix.title == "Declare state `parse_ipv4`")
+            .unwrap();
+
+        assert_eq!(
+            utils::apply_edits(source, &fix.edits),
+            r#"
+parser P(packet_in packet) {
+  state start {
+    transition parse_ipv4;
+  }
+
+  state parse_ipv4 {
+    transition accept;
+  }
+}
+"#
+        );
+    }
+}
@@ -0,0 +1,709 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::features::code_actions::{self, QuickFix};
+use crate::features::constant_folding::{self, Constant};
+use crate::features::preprocessor::Preprocessor;
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, VisitNode, Visitable};
+use crate::utils;
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, Position, Range, TextEdit};
+
+use super::diagnostics::{new_error, DiagnosticProvider};
+
+// Match kinds declared by core.p4 and the common architecture files, which
+// aren't part of the AST since includes aren't parsed.
+pub const BUILTIN_MATCH_KINDS: [&str; 6] =
+    ["exact", "ternary", "lpm", "range", "optional", "selector"];
+const BUILTIN_ACTIONS: [&str; 1] = ["NoAction"];
+// The errors about names an included file may declare.
+const UNDEFINED_CODES: [&str; 2] = ["undefined-action", "undefined-match-kind"];
+
+pub struct Table {}
+
+impl DiagnosticProvider for Table {
+    fn get_diagnostics(
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        _symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+
+        let mut match_kinds: Vec<String> = BUILTIN_MATCH_KINDS.map(String::from).to_vec();
+        let mut global_actions: Vec<String> = BUILTIN_ACTIONS.map(String::from).to_vec();
+        for child in root.get_children() {
+            match child.get().kind {
+                NodeKind::MatchKind => {
+                    if let Some(options) = child.get_child_of_kind(NodeKind::Options) {
+                        match_kinds
+                            .extend(options.get_children().iter().filter_map(utils::get_name));
+                    }
+                }
+                NodeKind::ControlAction => global_actions.extend(utils::get_name(&child)),
+                _ => {}
+            }
+        }
+
+        let mut diags = vec![];
+        for control in root.get_children() {
+            if control.get().kind != NodeKind::ControlDec {
+                continue;
+            }
+            let body = match control.get_child_of_kind(NodeKind::Body) {
+                Some(body) => body,
+                None => continue,
+            };
+
+            let mut actions = global_actions.clone();
+            actions.extend(
+                body.get_children()
+                    .iter()
+                    .filter(|child| child.get().kind == NodeKind::ControlAction)
+                    .filter_map(utils::get_name),
+            );
+
+            for table in body.get_children() {
+                if table.get().kind != NodeKind::ControlTable {
+                    continue;
+                }
+                let constants = constant_folding::get_constants(&root, table.get().range.start);
+                let indent = utils::get_indentation(&body, &table);
+                diags.append(&mut check_table(
+                    &table,
+                    &match_kinds,
+                    &actions,
+                    &constants,
+                    &indent,
+                ));
+            }
+        }
+
+        diags
+    }
+}
+
+/// `diagnostics` without the undefined action and match kind errors whose
+/// name may be declared by a file the document includes.
+pub fn skip_included_names(
+    mut diagnostics: Vec<Diagnostic>,
+    source_code: &str,
+    preprocessor: &Preprocessor,
+) -> Vec<Diagnostic> {
+    diagnostics.retain(|diagnostic| {
+        let is_undefined = matches!(
+            &diagnostic.code,
+            Some(NumberOrString::String(code)) if UNDEFINED_CODES.contains(&code.as_str())
+        );
+        let range = diagnostic.range;
+        let name = source_code.get(
+            utils::pos_to_byte(range.start, source_code)
+                ..utils::pos_to_byte(range.end, source_code),
+        );
+        !is_undefined || !name.is_some_and(|name| preprocessor.may_declare(name.trim()))
+    });
+    diagnostics
+}
+
+struct TableKey {
+    match_kind: String,
+}
+
+fn check_table(
+    control_table: &VisitNode,
+    match_kinds: &[String],
+    actions: &[String],
+    constants: &HashMap<String, Constant>,
+    indent: &str,
+) -> Vec<Diagnostic> {
+    let mut diags = vec![];
+    let table = match control_table.get_child_of_kind(NodeKind::Table) {
+        Some(table) => table,
+        None => return diags,
+    };
+    let actions_node = table.get_child_of_kind(NodeKind::Actions);
+
+    let mut keys: Vec<TableKey> = vec![];
+    let mut listed_actions: Vec<String> = vec![];
+
+    for property in table.get_children() {
+        match property.get().kind {
+            NodeKind::Keys => {
+                for key in property.get_children() {
+                    if key.get().kind != NodeKind::Key {
+                        continue;
+                    }
+                    let match_kind_node = match key.get_child_of_kind(NodeKind::Name) {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    let match_kind = utils::normalize(&match_kind_node.get().content);
+
+                    if !match_kinds.contains(&match_kind) {
+                        let range = match_kind_node.get().range;
+                        let fixes = code_actions::get_suggestion(&match_kind, match_kinds)
+                            .map(|suggestion| QuickFix::rename(range, suggestion))
+                            .into_iter()
+                            .collect();
+                        diags.push(code_actions::with_fixes(
+                            new_error(
+                                range,
+                                "undefined-match-kind",
+                                format!("Match kind `{match_kind}` is not a declared match_kind."),
+                            ),
+                            fixes,
+                        ));
+                    }
+                    keys.push(TableKey { match_kind });
+                }
+            }
+            NodeKind::Actions => {
+                for action in property.get_children() {
+                    if action.get().kind != NodeKind::Action {
+                        continue;
+                    }
+                    let name_node = match action.get_type_node() {
+                        Some(node) => node,
+                        None => continue,
+                    };
+                    let name = utils::normalize(&name_node.get().content);
+
+                    if !actions.contains(&name) {
+                        let range = name_node.get().range;
+                        let mut fixes: Vec<QuickFix> = code_actions::get_suggestion(&name, actions)
+                            .map(|suggestion| QuickFix::rename(range, suggestion))
+                            .into_iter()
+                            .collect();
+                        fixes.push(declare_action(control_table, &name, indent));
+                        diags.push(code_actions::with_fixes(
+                            new_error(
+                                range,
+                                "undefined-action",
+                                format!("Action `{name}` is not defined."),
+                            ),
+                            fixes,
+                        ));
+                    }
+                    listed_actions.push(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for property in table.get_children() {
+        match property.get().kind {
+            NodeKind::TableKw => {
+                let name = property
+                    .get_child_of_kind(NodeKind::Name)
+                    .map(|node| utils::normalize(&node.get().content))
+                    .unwrap_or_default();
+                let value = match property.get_value_node() {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                match name.as_str() {
+                    "default_action" => {
+                        let action = action_name(&value.get().content);
+                        if !listed_actions.contains(&action) {
+                            let range = value.get().range;
+                            let name_range = Range::new(
+                                range.start,
+                                Position::new(
+                                    range.start.line,
+                                    range.start.character + action.len() as u32,
+                                ),
+                            );
+                            let mut fixes: Vec<QuickFix> =
+                                code_actions::get_suggestion(&action, &listed_actions)
+                                    .map(|suggestion| QuickFix::rename(name_range, suggestion))
+                                    .into_iter()
+                                    .collect();
+                            fixes.extend(add_listed_action(actions_node.as_ref(), &action));
+                            diags.push(code_actions::with_fixes(
+                                new_error(
+                                    range,
+                                    "default-action-not-listed",
+                                    format!(
+                                        "Default action `{action}` is not in the table's action list."
+                                    ),
+                                ),
+                                fixes,
+                            ));
+                        }
+                    }
+                    "size" => {
+                        let text = utils::normalize(&value.get().content);
+                        let is_identifier = text
+                            .chars()
+                            .next()
+                            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+
+                        let invalid = match constant_folding::evaluate(&text, constants) {
+                            Some(size) => size.value <= 0,
+                            None => !is_identifier,
+                        };
+                        if invalid {
+                            diags.push(new_error(
+                                value.get().range,
+                                "invalid-table-size",
+                                format!("Table size `{text}` must be a positive integer."),
+                            ));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            NodeKind::Entries => {
+                for entry in property.get_children() {
+                    if entry.get().kind == NodeKind::Entrie {
+                        diags.append(&mut check_entry(
+                            entry,
+                            &keys,
+                            &listed_actions,
+                            actions_node.as_ref(),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    diags
+}
+
+/// The fix declaring an empty action `name` before the table, indented with
+/// the table's `indent`.
+fn declare_action(control_table: &VisitNode, name: &str, indent: &str) -> QuickFix {
+    let start = control_table.get().range.start;
+    QuickFix::new(
+        format!("Declare action `{name}`"),
+        vec![TextEdit::new(
+            Range::new(start, start),
+            format!("action {name}() {{\n{indent}}}\n\n{indent}"),
+        )],
+    )
+}
+
+/// The edit adding `name` to the table's `actions` list: on its own line
+/// before the closing brace if the list spans several lines, or before the
+/// brace otherwise.
+fn add_listed_action_edit(actions_node: &VisitNode, name: &str) -> TextEdit {
+    let range = actions_node.get().range;
+    let listed: Vec<VisitNode> = actions_node
+        .get_children()
+        .into_iter()
+        .filter(|child| child.get().kind == NodeKind::Action)
+        .collect();
+
+    match (listed.first(), listed.last()) {
+        (Some(first), Some(last)) if last.get().range.end.line < range.end.line => {
+            let position = Position::new(range.end.line, 0);
+            let indent = utils::get_indentation(actions_node, first);
+            TextEdit::new(Range::new(position, position), format!("{indent}{name};\n"))
+        }
+        _ => {
+            let position = Position::new(range.end.line, range.end.character.saturating_sub(1));
+            TextEdit::new(Range::new(position, position), format!("{name}; "))
+        }
+    }
+}
+
+fn add_listed_action(actions_node: Option<&VisitNode>, name: &str) -> Option<QuickFix> {
+    let edit = add_listed_action_edit(actions_node?, name);
+    Some(QuickFix::new(
+        format!("Add `{name}` to the table's actions"),
+        vec![edit],
+    ))
+}
+
+/// The edits adding a `default_action` property on its own line, after the
+/// other properties.
+fn add_default_action(control_table: &VisitNode, table: &VisitNode) -> Option<Vec<TextEdit>> {
+    let end = control_table.get().range.end;
+    let properties = table.get_children();
+    let (first, last) = (properties.first()?, properties.last()?);
+    if last.get().range.end.line >= end.line {
+        return None;
+    }
+
+    let position = Position::new(end.line, 0);
+    let indent = utils::get_indentation(table, first);
+    Some(vec![TextEdit::new(
+        Range::new(position, position),
+        format!("{indent}default_action = NoAction();\n"),
+    )])
+}
+
+/// The fix adding `default_action = NoAction()` to the table named at
+/// `position`, when it has no default action. `NoAction` is listed in its
+/// actions if it isn't already.
+pub fn get_default_action_fix(
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Option<QuickFix> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    let descendants = root.get_descendants();
+    let control_table = descendants.iter().find(|node| {
+        node.get().kind == NodeKind::ControlTable
+            && node.get_child_of_kind(NodeKind::Name).is_some_and(|name| {
+                let range = name.get().range;
+                range.start <= position && position <= range.end
+            })
+    })?;
+    let table = control_table.get_child_of_kind(NodeKind::Table)?;
+
+    let properties = table.get_children();
+    let has_default_action = properties.iter().any(|property| {
+        property.get().kind == NodeKind::TableKw
+            && utils::get_name(property).as_deref() == Some("default_action")
+    });
+    if has_default_action {
+        return None;
+    }
+
+    let mut edits = add_default_action(control_table, &table)?;
+    if let Some(actions_node) = table.get_child_of_kind(NodeKind::Actions) {
+        let is_listed = actions_node.get_children().iter().any(|action| {
+            action.get().kind == NodeKind::Action
+                && action
+                    .get_type_node()
+                    .is_some_and(|name| utils::normalize(&name.get().content) == "NoAction")
+        });
+        if !is_listed {
+            edits.push(add_listed_action_edit(&actions_node, "NoAction"));
+        }
+    }
+
+    Some(QuickFix::new(
+        "Add `default_action = NoAction()`".to_string(),
+        edits,
+    ))
+}
+
+#[derive(Debug, PartialEq)]
+enum KeysetForm {
+    Value,
+    Mask,
+    Range,
+    DontCare,
+}
+
+fn check_entry(
+    entry: VisitNode,
+    keys: &[TableKey],
+    actions: &[String],
+    actions_node: Option<&VisitNode>,
+) -> Vec<Diagnostic> {
+    let mut diags = vec![];
+    let range = entry.get().range;
+
+    let content = &entry.get().content;
+    let (keyset, action) = match utils::split_top_level(content, ':').as_slice() {
+        [keyset, action, ..] => (keyset.trim().to_string(), action_name(action)),
+        _ => return diags,
+    };
+
+    if !action.is_empty() && !actions.contains(&action) {
+        diags.push(code_actions::with_fixes(
+            new_error(
+                range,
+                "entry-action-not-listed",
+                format!("Action `{action}` is not in the table's action list."),
+            ),
+            add_listed_action(actions_node, &action)
+                .into_iter()
+                .collect(),
+        ));
+    }
+
+    let keyset = match keyset.strip_prefix('(').and_then(|k| k.strip_suffix(')')) {
+        Some(inner) => inner.to_string(),
+        None => keyset,
+    };
+    let elements: Vec<String> = utils::split_top_level(&keyset, ',')
+        .iter()
+        .map(|element| element.trim().to_string())
+        .collect();
+
+    // A lone `_` or `default` matches every key.
+    if elements.len() == 1 && (elements[0] == "_" || elements[0] == "default") {
+        return diags;
+    }
+
+    if elements.len() != keys.len() {
+        diags.push(new_error(
+            range,
+            "entry-arity",
+            format!(
+                "Entry has {} key value(s) but the table has {} key(s).",
+                elements.len(),
+                keys.len()
+            ),
+        ));
+        return diags;
+    }
+
+    for (element, key) in elements.iter().zip(keys) {
+        let form = if element == "_" || element == "default" {
+            KeysetForm::DontCare
+        } else if element.contains("&&&") {
+            KeysetForm::Mask
+        } else if element.contains("..") {
+            KeysetForm::Range
+        } else {
+            KeysetForm::Value
+        };
+
+        let allowed = match key.match_kind.as_str() {
+            "exact" => vec![KeysetForm::Value],
+            "ternary" | "lpm" => vec![KeysetForm::Value, KeysetForm::Mask, KeysetForm::DontCare],
+            "range" => vec![KeysetForm::Value, KeysetForm::Range, KeysetForm::DontCare],
+            "optional" => vec![KeysetForm::Value, KeysetForm::DontCare],
+            _ => continue,
+        };
+
+        if !allowed.contains(&form) {
+            let description = match form {
+                KeysetForm::Value => "A value",
+                KeysetForm::Mask => "A `&&&` mask",
+                KeysetForm::Range => "A `..` range",
+                KeysetForm::DontCare => "A don't care `_`",
+            };
+            diags.push(new_error(
+                range,
+                "entry-match-form",
+                format!(
+                    "{description} can't be used on a `{}` key (`{element}`).",
+                    key.match_kind
+                ),
+            ));
+        }
+    }
+
+    diags
+}
+
+fn action_name(text: &str) -> String {
+    let text = utils::normalize(text);
+    let text = text.trim_end_matches(';');
+    match text.split_once('(') {
+        Some((name, _)) => name.to_string(),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Position, Url};
+
+    use super::{get_default_action_fix, Table};
+    use crate::features::code_actions;
+    use crate::features::diagnostics::diagnostics::DiagnosticProvider;
+    use crate::file::File;
+    use crate::settings::Settings;
+    use crate::utils;
+
+    /// The code and line of each table diagnostic of `source_code`.
+    fn get_codes(source_code: &str) -> Vec<(String, u32)> {
+        let file = utils::parse_file(source_code);
+        Table::get_diagnostics(&file.ast_manager, &file.symbol_table_manager)
+            .iter()
+            .map(|diagnostic: &Diagnostic| {
+                let code = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => code.clone(),
+                    _ => String::new(),
+                };
+                (code, diagnostic.range.start.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_undefined_action() {
+        let source = r#"
+control C(inout bit<8> x) {
+    action set() { x = 1; }
+    table t {
+        key = { x: exact; }
+        actions = { set; sett; NoAction; }
+        default_action = NoAction();
+    }
+    apply { t.apply(); }
+}
+"#;
+        assert_eq!(get_codes(source), vec![("undefined-action".to_string(), 5)]);
+    }
+
+    #[test]
+    fn test_undefined_match_kind() {
+        let source = r#"
+match_kind { custom }
+control C(inout bit<8> x, inout bit<8> y) {
+    table t {
+        key = { x: custom; y: exakt; }
+        actions = { NoAction; }
+        default_action = NoAction();
+    }
+    apply { t.apply(); }
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![("undefined-match-kind".to_string(), 4)]
+        );
+    }
+
+    #[test]
+    fn test_included_names() {
+        let directory = std::env::temp_dir().join(format!("p4-table-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("common.p4"),
+            "match_kind { custom }\naction drop_packet() {}\n",
+        )
+        .unwrap();
+
+        let source = r#"#include "common.p4"
+control C(inout bit<8> x, inout bit<8> y) {
+    table t {
+        key = { x: custom; y: exakt; }
+        actions = { drop_packet; drop_pakcet; }
+    }
+    apply { t.apply(); }
+}
+"#;
+        let uri = Url::from_file_path(directory.join("main.p4")).unwrap();
+        let mut file = File::new(uri, source, &Some(utils::parse(source)));
+        file.update_settings(Settings::default());
+
+        // Only the names the included file doesn't declare are reported.
+        let undefined: Vec<(Option<NumberOrString>, u32)> = file
+            .get_quick_diagnostics(false)
+            .into_iter()
+            .filter(|diagnostic| diagnostic.severity == Some(DiagnosticSeverity::ERROR))
+            .map(|diagnostic| (diagnostic.code, diagnostic.range.start.line))
+            .collect();
+        assert_eq!(
+            undefined,
+            vec![
+                (
+                    Some(NumberOrString::String("undefined-match-kind".to_string())),
+                    3
+                ),
+                (
+                    Some(NumberOrString::String("undefined-action".to_string())),
+                    4
+                ),
+            ]
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_entry_arity() {
+        let source = r#"
+control C(inout bit<8> x, inout bit<8> y) {
+    action set() { x = 1; }
+    table t {
+        key = { x: exact; y: ternary; }
+        actions = { set; NoAction; }
+        default_action = NoAction();
+        const entries = {
+            (1, 2 &&& 3): set();
+            (1): set();
+            _: NoAction();
+            (1 &&& 1, 2): set();
+        }
+    }
+    apply { t.apply(); }
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("entry-arity".to_string(), 9),
+                ("entry-match-form".to_string(), 11),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_size() {
+        let source = r#"
+const bit<32> SIZE = 1024;
+control C(inout bit<8> x) {
+    table a { actions = { NoAction; } size = SIZE * 2; }
+    table b { actions = { NoAction; } size = 0; }
+    table c { actions = { NoAction; } size = 4 - 8; }
+    table d { actions = { NoAction; } size = TABLE_SIZE; }
+    table e { actions = { NoAction; } size = "big"; }
+    apply {}
+}
+"#;
+        assert_eq!(
+            get_codes(source),
+            vec![
+                ("invalid-table-size".to_string(), 4),
+                ("invalid-table-size".to_string(), 5),
+                ("invalid-table-size".to_string(), 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declare_action_fix() {
+        let source = "control C(inout bit<8> x) {\n\ttable t {\n\t\tactions = { sett; }\n\t}\n\tapply { t.apply(); }\n}\n";
+        let file = utils::parse_file(source);
+        let fix = Table::get_diagnostics(&file.ast_manager, &file.symbol_table_manager)
+            .iter()
+            .flat_map(code_actions::get_fixes)
+            .find(|fix| fix.title == "Declare action `sett`")
+            .unwrap();
+
+        assert_eq!(
+            utils::apply_edits(source, &fix.edits),
+            "control C(inout bit<8> x) {\n\taction sett() {\n\t}\n\n\ttable t {\n\t\tactions = { sett; }\n\t}\n\tapply { t.apply(); }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_default_action_fix() {
+        let source = r#"
+control C(inout bit<8> x) {
+    action set() { x = 1; }
+    table t {
+        actions = {
+            set;
+        }
+    }
+    apply { t.apply(); }
+}
+"#;
+        let file = utils::parse_file(source);
+        // Tables without a default action aren't reported.
+        assert_eq!(get_codes(source), vec![]);
+
+        let fix = get_default_action_fix(Position::new(3, 10), &file.ast_manager).unwrap();
+        assert_eq!(
+            utils::apply_edits(source, &fix.edits),
+            r#"
+control C(inout bit<8> x) {
+    action set() { x = 1; }
+    table t {
+        actions = {
+            set;
+            NoAction;
+        }
+        default_action = NoAction();
+    }
+    apply { t.apply(); }
+}
+"#
+        );
+        assert!(get_default_action_fix(Position::new(2, 12), &file.ast_manager).is_none());
+    }
+}
@@ -0,0 +1,147 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{Diagnostic, Range, TextEdit};
+
+use crate::features::code_actions::{self, QuickFix};
+use crate::features::members::{self, ExprType, Segment};
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, Visitable};
+
+use super::diagnostics::{new_error, DiagnosticProvider};
+
+pub struct Width {}
+
+impl DiagnosticProvider for Width {
+    fn get_diagnostics(
+        ast_query: &Arc<Mutex<impl AstQuery>>,
+        symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+    ) -> Vec<Diagnostic> {
+        // Resolving the types locks the AST again, so the assignments are
+        // collected first.
+        get_assignments(ast_query)
+            .iter()
+            .filter_map(|assignment| check_assignment(assignment, ast_query, symbol_table_query))
+            .collect()
+    }
+}
+
+enum Target {
+    Expression(Vec<Segment>),
+    Type(String),
+}
+
+/// An assignment, or an initialized variable declaration, of a plain
+/// expression like `hdr.ipv4.ttl`. Only those have a type known here.
+struct Assignment {
+    target: Target,
+    value: Vec<Segment>,
+    text: String,
+    range: Range,
+}
+
+fn get_assignments(ast_query: &Arc<Mutex<impl AstQuery>>) -> Vec<Assignment> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    let mut assignments = vec![];
+    for node in root.get_descendants() {
+        let target = match node.get().kind {
+            NodeKind::Assignment => match node
+                .get_child_of_kind(NodeKind::NameStatement)
+                .and_then(|name| members::get_path_segments(&name))
+            {
+                Some(segments) => Target::Expression(segments),
+                None => continue,
+            },
+            NodeKind::VariableDec => match node.get_type_node() {
+                Some(type_node) => Target::Type(type_node.get().content.clone()),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let Some(value) = node.get_value_node() else {
+            continue;
+        };
+
+        if let Some(segments) = members::get_path_segments(&value) {
+            assignments.push(Assignment {
+                target,
+                value: segments,
+                text: value.get().content.trim().to_string(),
+                range: value.get().range,
+            });
+        }
+    }
+
+    assignments
+}
+
+fn check_assignment(
+    assignment: &Assignment,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Diagnostic> {
+    let position = assignment.range.start;
+    let target_type = match &assignment.target {
+        Target::Expression(segments) => {
+            members::get_type(segments, position, ast_query, symbol_table_query)?
+        }
+        Target::Type(text) => members::resolve_type(text, ast_query),
+    };
+    let value_type = members::get_type(&assignment.value, position, ast_query, symbol_table_query)?;
+
+    let (ExprType::Sized(target_type), ExprType::Sized(value_type)) = (target_type, value_type)
+    else {
+        return None;
+    };
+    if target_type == value_type {
+        return None;
+    }
+
+    let diagnostic = new_error(
+        assignment.range,
+        "width-mismatch",
+        format!(
+            "`{}` is a {value_type} and needs a cast to be assigned to a {target_type}.",
+            assignment.text
+        ),
+    );
+    Some(code_actions::with_fixes(
+        diagnostic,
+        vec![QuickFix::new(
+            format!("Cast to {target_type}"),
+            vec![TextEdit::new(
+                assignment.range,
+                format!("({target_type}){}", assignment.text),
+            )],
+        )],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Width;
+    use crate::features::code_actions;
+    use crate::features::diagnostics::diagnostics::DiagnosticProvider;
+    use crate::utils;
+
+    #[test]
+    fn test_cast_fix() {
+        let source = r#"
+header h_t { bit<8> a; bit<16> b; }
+struct headers { h_t h; }
+control C(inout headers hdr) {
+    apply {
+        hdr.h.a = hdr.h.b;
+    }
+}
+"#;
+        let file = utils::parse_file(source);
+        let fixes: Vec<_> = Width::get_diagnostics(&file.ast_manager, &file.symbol_table_manager)
+            .iter()
+            .flat_map(code_actions::get_fixes)
+            .collect();
+        assert_eq!(fixes.len(), 1);
+        assert_eq!(fixes[0].title, "Cast to bit<8>");
+        assert!(utils::apply_edits(source, &fixes[0].edits).contains("hdr.h.a = (bit<8>)hdr.h.b;"));
+    }
+}
@@ -0,0 +1,228 @@
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+use tree_sitter::Tree;
+
+// The syntax nodes folded when they span several lines.
+const FOLDED_KINDS: [&str; 19] = [
+    "action_declaration",
+    "action_table",
+    "block_statement",
+    "control_declaration",
+    "entries_table",
+    "enum_declaration",
+    "error_declaration",
+    "extern_declaration",
+    "function_declaration",
+    "header_type_declaration",
+    "header_union_declaration",
+    "keys_table",
+    "match_kind_declaration",
+    "parser_block_statement",
+    "parser_declaration",
+    "parser_state",
+    "struct_type_declaration",
+    "switch_statement",
+    "table_declaration",
+];
+
+fn new_range(start_line: usize, end_line: usize, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line: start_line as u32,
+        start_character: None,
+        end_line: end_line as u32,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    }
+}
+
+fn get_syntax_ranges(tree: &Tree) -> Vec<FoldingRange> {
+    let mut ranges = vec![];
+    let mut stack = vec![tree.root_node()];
+
+    while let Some(node) = stack.pop() {
+        let (start, end) = (node.start_position().row, node.end_position().row);
+        if end <= start {
+            continue;
+        }
+        if FOLDED_KINDS.contains(&node.kind()) {
+            ranges.push(new_range(start, end, None));
+        }
+
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+
+    ranges
+}
+
+/// Folds a group of consecutive lines, if there are several.
+fn close_group(
+    group: &mut Option<(usize, usize)>,
+    kind: FoldingRangeKind,
+    ranges: &mut Vec<FoldingRange>,
+) {
+    if let Some((start, end)) = group.take() {
+        if end > start {
+            ranges.push(new_range(start, end, Some(kind)));
+        }
+    }
+}
+
+/// Comments, groups of `#include` and conditional regions, found in the text
+/// as the preprocessor sees it.
+fn get_text_ranges(source_code: &str) -> Vec<FoldingRange> {
+    let mut ranges = vec![];
+    let mut block_comment: Option<usize> = None;
+    let mut line_comments: Option<(usize, usize)> = None;
+    let mut includes: Option<(usize, usize)> = None;
+    let mut conditionals: Vec<usize> = vec![];
+
+    for (index, line) in source_code.lines().enumerate() {
+        let text = line.trim();
+
+        if let Some(start) = block_comment {
+            if text.contains("*/") {
+                block_comment = None;
+                if index > start {
+                    ranges.push(new_range(start, index, Some(FoldingRangeKind::Comment)));
+                }
+            }
+            continue;
+        }
+
+        if text.starts_with("//") {
+            line_comments = Some(line_comments.map_or((index, index), |(start, _)| (start, index)));
+        } else {
+            close_group(&mut line_comments, FoldingRangeKind::Comment, &mut ranges);
+        }
+        if let Some(start) = text.find("/*") {
+            if !text[start..].contains("*/") {
+                block_comment = Some(index);
+            }
+        }
+
+        let directive = text
+            .strip_prefix('#')
+            .map(|directive| directive.split_whitespace().next().unwrap_or(""));
+        if directive == Some("include") {
+            includes = Some(includes.map_or((index, index), |(start, _)| (start, index)));
+        } else {
+            close_group(&mut includes, FoldingRangeKind::Imports, &mut ranges);
+        }
+
+        // Each branch of a conditional folds up to the next directive.
+        match directive {
+            Some("if" | "ifdef" | "ifndef") => conditionals.push(index),
+            Some("elif" | "else" | "endif") => {
+                if let Some(start) = conditionals.pop() {
+                    if index > start + 1 {
+                        ranges.push(new_range(start, index - 1, Some(FoldingRangeKind::Region)));
+                    }
+                }
+                if directive != Some("endif") {
+                    conditionals.push(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    close_group(&mut line_comments, FoldingRangeKind::Comment, &mut ranges);
+    close_group(&mut includes, FoldingRangeKind::Imports, &mut ranges);
+
+    ranges
+}
+
+pub fn get_folding_ranges(source_code: &str, tree: &Tree) -> Vec<FoldingRange> {
+    let mut ranges = get_syntax_ranges(tree);
+    ranges.extend(get_text_ranges(source_code));
+
+    // Nodes wrapping one another, like a type declaration and its header,
+    // would fold the same lines.
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges.dedup_by_key(|range| range.start_line);
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::FoldingRangeKind;
+
+    use super::{get_folding_ranges, get_text_ranges};
+    use crate::utils;
+
+    #[test]
+    fn test_get_text_ranges() {
+        let source = "#include <core.p4>\n#include <v1model.p4>\n\n/*\n * Headers\n */\n// a\n// b\n#ifdef IPV6\nconst bit<8> A = 1;\nconst bit<8> B = 1;\n#else\nconst bit<8> A = 2;\n#endif\n";
+
+        let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> = get_text_ranges(source)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 1, Some(FoldingRangeKind::Imports)),
+                (3, 5, Some(FoldingRangeKind::Comment)),
+                (6, 7, Some(FoldingRangeKind::Comment)),
+                (8, 10, Some(FoldingRangeKind::Region)),
+                (11, 12, Some(FoldingRangeKind::Region)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_folding_ranges() {
+        let source = "header h_t {
+    bit<8> a;
+}
+struct s_t {
+    h_t h;
+}
+parser P(packet_in pkt, out s_t hdr) {
+    state start {
+        transition accept;
+    }
+}
+control C(inout s_t hdr) {
+    action a() {}
+    table t {
+        key = {
+            hdr.h.a: exact;
+        }
+        actions = {
+            a;
+        }
+        const entries = {
+            1: a();
+        }
+    }
+    apply {
+        t.apply();
+    }
+}
+";
+
+        // The declarations and their bodies, which start on the same line, fold
+        // once.
+        let ranges: Vec<(u32, u32, Option<FoldingRangeKind>)> =
+            get_folding_ranges(source, &utils::parse(source))
+                .into_iter()
+                .map(|range| (range.start_line, range.end_line, range.kind))
+                .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 2, None),
+                (3, 5, None),
+                (6, 10, None),
+                (7, 9, None),
+                (11, 27, None),
+                (13, 23, None),
+                (14, 16, None),
+                (17, 19, None),
+                (20, 22, None),
+                (24, 26, None),
+            ]
+        );
+    }
+}
@@ -0,0 +1,989 @@
+use std::ops::{Range as Span, RangeInclusive};
+
+use tower_lsp::lsp_types::{FormattingOptions, Position, Range, TextEdit};
+use tree_sitter::Tree;
+
+use crate::utils;
+
+// Keywords spaced from the parenthesis following them.
+const SPACED_KEYWORDS: [&str; 5] = ["for", "if", "return", "switch", "while"];
+// The base types a parenthesized cast starts with.
+const CAST_TYPES: [&str; 4] = ["bit", "bool", "int", "varbit"];
+const BINARY_OPERATORS: [&str; 24] = [
+    "=", "+", "-", "*", "/", "%", "<<", ">>", "<", ">", "<=", ">=", "==", "!=", "&", "|", "^",
+    "&&", "||", "&&&", "|+|", "|-|", "++", "?",
+];
+const TYPE_BODIES: [&str; 3] = ["header", "header_union", "struct"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Code,
+    Comment,
+    // A preprocessor line, kept as it is written.
+    Directive,
+}
+
+#[derive(Debug)]
+struct Token<'a> {
+    text: &'a str,
+    span: Span<usize>,
+    kind: TokenKind,
+    // The line breaks between the token and the previous one in the source.
+    newlines_before: usize,
+    // Whether the token is an operator of an expression rather than part of
+    // a type, to tell comparisons from type arguments.
+    in_expression: bool,
+}
+
+/// How a token lays out, found from the tokens around it.
+#[derive(Debug, Default, Clone)]
+struct Layout {
+    // The matching bracket, for brackets.
+    pair: Option<usize>,
+    // Braces of a block, rather than of a list expression.
+    block: bool,
+    binary: bool,
+    unary: bool,
+    // The angle brackets of type arguments.
+    type_angle: bool,
+    // The parenthesis closing a cast.
+    cast: bool,
+    annotation_end: bool,
+    // A `;` separating clauses within parentheses.
+    nested: bool,
+    // Spaces added before the token to align it with the lines around.
+    padding: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Separator {
+    None,
+    Space,
+    Line,
+}
+
+fn is_word(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '"')
+}
+
+/// The comments and preprocessor lines, as byte spans. Preprocessor lines
+/// span from the start of the line to keep their indentation.
+fn get_trivia(source_code: &str) -> Vec<(Span<usize>, TokenKind)> {
+    let bytes = source_code.as_bytes();
+    let mut trivia = vec![];
+    let mut index = 0;
+    let mut line_start = true;
+
+    while index < bytes.len() {
+        let rest = &source_code[index..];
+        let line_end = rest.find('\n').map_or(bytes.len(), |end| index + end);
+
+        if line_start && bytes[index] == b'#' {
+            let start = source_code[..index]
+                .rfind('\n')
+                .map_or(0, |start| start + 1);
+            let mut end = line_end;
+            while source_code[..end].trim_end().ends_with('\\') && end < bytes.len() {
+                end = source_code[end + 1..]
+                    .find('\n')
+                    .map_or(bytes.len(), |next| end + 1 + next);
+            }
+            let end = start + source_code[start..end].trim_end().len();
+            trivia.push((start..end, TokenKind::Directive));
+            index = end;
+            continue;
+        }
+
+        match bytes[index] {
+            b'\n' => {
+                line_start = true;
+                index += 1;
+                continue;
+            }
+            b' ' | b'\t' | b'\r' => {
+                index += 1;
+                continue;
+            }
+            _ if rest.starts_with("//") => {
+                let end = index + source_code[index..line_end].trim_end().len();
+                trivia.push((index..end, TokenKind::Comment));
+                index = end;
+            }
+            _ if rest.starts_with("/*") => {
+                let end = rest[2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| index + 2 + end + 2);
+                trivia.push((index..end, TokenKind::Comment));
+                index = end;
+            }
+            b'"' => {
+                let mut end = index + 1;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                index = end + 1;
+            }
+            _ => index += 1,
+        }
+        line_start = false;
+    }
+
+    trivia
+}
+
+fn is_in_trivia(trivia: &[(Span<usize>, TokenKind)], byte: usize) -> bool {
+    trivia.iter().any(|(span, _)| span.contains(&byte))
+}
+
+/// The tokens of the code, as the leaves of the tree outside the trivia,
+/// with whether their parent is an expression. `None` if the code has a
+/// syntax error, which the preprocessor lines alone may cause.
+fn get_leaves(
+    source_code: &str,
+    tree: &Tree,
+    trivia: &[(Span<usize>, TokenKind)],
+) -> Option<Vec<(Span<usize>, bool)>> {
+    let mut leaves = vec![];
+    let mut stack = vec![tree.root_node()];
+
+    while let Some(node) = stack.pop() {
+        let span = node.start_byte()..node.end_byte();
+        if node.is_missing() {
+            return None;
+        }
+        if node.is_error()
+            && span.clone().any(|byte| {
+                !is_in_trivia(trivia, byte) && !source_code.as_bytes()[byte].is_ascii_whitespace()
+            })
+        {
+            return None;
+        }
+
+        let is_string = source_code[span.clone()].starts_with('"');
+        if node.child_count() == 0 || is_string {
+            if !span.is_empty() && !is_in_trivia(trivia, span.start) {
+                let in_expression = node
+                    .parent()
+                    .is_some_and(|parent| parent.kind() == "expression");
+                leaves.push((span, in_expression));
+            }
+            continue;
+        }
+
+        let mut cursor = node.walk();
+        let children: Vec<_> = node.children(&mut cursor).collect();
+        stack.extend(children.into_iter().rev());
+    }
+
+    Some(leaves)
+}
+
+fn get_tokens<'a>(
+    source_code: &'a str,
+    leaves: Vec<(Span<usize>, bool)>,
+    trivia: Vec<(Span<usize>, TokenKind)>,
+) -> Vec<Token<'a>> {
+    let mut spans: Vec<(Span<usize>, TokenKind, bool)> = leaves
+        .into_iter()
+        .map(|(span, in_expression)| (span, TokenKind::Code, in_expression))
+        .chain(trivia.into_iter().map(|(span, kind)| (span, kind, false)))
+        .collect();
+    spans.sort_by_key(|(span, _, _)| span.start);
+
+    let mut end = 0;
+    spans
+        .into_iter()
+        .map(|(span, kind, in_expression)| {
+            let newlines_before = source_code[end.min(span.start)..span.start]
+                .matches('\n')
+                .count();
+            end = span.end;
+            Token {
+                text: &source_code[span.clone()],
+                span,
+                kind,
+                newlines_before,
+                in_expression,
+            }
+        })
+        .collect()
+}
+
+fn ends_operand(token: &Token, layout: &Layout) -> bool {
+    (is_word(token.text) && !SPACED_KEYWORDS.contains(&token.text))
+        || matches!(token.text, ")" | "]")
+        || (layout.type_angle && token.text.ends_with('>'))
+}
+
+fn get_layouts(tokens: &[Token]) -> Vec<Layout> {
+    let mut layouts = vec![Layout::default(); tokens.len()];
+    let code: Vec<usize> = (0..tokens.len())
+        .filter(|index| tokens[*index].kind == TokenKind::Code)
+        .collect();
+
+    let mut brackets: Vec<usize> = vec![];
+    let mut questions: Vec<usize> = vec![];
+    // The braces holding statements, which are blocks.
+    let mut with_statements = vec![false; tokens.len()];
+    for (position, &index) in code.iter().enumerate() {
+        let token = &tokens[index];
+        let previous = position.checked_sub(1).map(|position| code[position]);
+
+        match token.text {
+            "(" | "[" | "{" => brackets.push(index),
+            ")" | "]" | "}" => {
+                if let Some(open) = brackets.pop() {
+                    layouts[open].pair = Some(index);
+                    layouts[index].pair = Some(open);
+                }
+            }
+            ";" => match brackets.last() {
+                Some(&open) if tokens[open].text == "{" => with_statements[open] = true,
+                Some(_) => layouts[index].nested = true,
+                None => {}
+            },
+            "?" => questions.push(brackets.len()),
+            ":" if questions.last() == Some(&brackets.len()) => {
+                questions.pop();
+                layouts[index].binary = true;
+            }
+            _ => {}
+        }
+
+        let after_operand =
+            previous.is_some_and(|previous| ends_operand(&tokens[previous], &layouts[previous]));
+        match token.text {
+            "<" | ">" | "<<" | ">>" if !token.in_expression => layouts[index].type_angle = true,
+            "!" | "~" => layouts[index].unary = true,
+            "-" | "+" if !after_operand => layouts[index].unary = true,
+            text if BINARY_OPERATORS.contains(&text) => layouts[index].binary = true,
+            _ => {}
+        }
+    }
+
+    for (position, &index) in code.iter().enumerate() {
+        let token = &tokens[index];
+        let previous = position.checked_sub(1).map(|position| code[position]);
+        let next = code.get(position + 1).copied();
+
+        match token.text {
+            "{" => {
+                let block = with_statements[index]
+                    || previous.is_none_or(|previous| {
+                        ends_operand(&tokens[previous], &layouts[previous])
+                            && tokens[previous].text != "]"
+                    });
+                if let Some(close) = layouts[index].pair {
+                    layouts[index].block = block;
+                    layouts[close].block = block;
+                }
+            }
+            ")" => {
+                let Some(open) = layouts[index].pair else {
+                    continue;
+                };
+                let open_position = code.binary_search(&open).unwrap();
+                let is_call = open_position.checked_sub(1).is_some_and(|previous| {
+                    let previous = code[previous];
+                    ends_operand(&tokens[previous], &layouts[previous])
+                        || SPACED_KEYWORDS.contains(&tokens[previous].text)
+                });
+                let is_type = code
+                    .get(open_position + 1)
+                    .is_some_and(|first| CAST_TYPES.contains(&tokens[*first].text));
+                let before_operand =
+                    next.is_some_and(|next| is_word(tokens[next].text) || tokens[next].text == "(");
+                layouts[index].cast = !is_call && is_type && before_operand;
+            }
+            "@" => {
+                let Some(&name) = code.get(position + 1) else {
+                    continue;
+                };
+                let end = match code.get(position + 2) {
+                    Some(&open) if tokens[open].text == "(" => layouts[open].pair.unwrap_or(name),
+                    _ => name,
+                };
+                layouts[end].annotation_end = true;
+            }
+            _ => {}
+        }
+    }
+
+    layouts
+}
+
+fn get_separator(
+    previous: &Token,
+    previous_layout: &Layout,
+    next: &Token,
+    next_layout: &Layout,
+) -> Separator {
+    use Separator::*;
+
+    if previous.kind == TokenKind::Directive
+        || next.kind == TokenKind::Directive
+        || previous.text.starts_with("//")
+    {
+        return Line;
+    }
+    if previous.kind == TokenKind::Comment || next.kind == TokenKind::Comment {
+        return if next.newlines_before == 0 {
+            Space
+        } else {
+            Line
+        };
+    }
+
+    let (previous_text, next_text) = (previous.text, next.text);
+    if next_text == "{" && next_layout.block {
+        return Space;
+    }
+    if previous_text == "{" && previous_layout.block {
+        return if next_text == "}" { Space } else { Line };
+    }
+    if next_text == "}" && next_layout.block {
+        return Line;
+    }
+    if previous_text == "}" && previous_layout.block {
+        return match next_text {
+            "else" => Space,
+            ";" | "," | ")" => None,
+            _ => Line,
+        };
+    }
+    if previous_text == ";" {
+        return if previous_layout.nested { Space } else { Line };
+    }
+
+    if matches!(previous_text, "@" | "." | "(" | "[" | "..") || next_text == ".." {
+        return None;
+    }
+    if matches!(next_text, ";" | "," | "." | ")" | "]" | "[") {
+        return None;
+    }
+    if previous_layout.annotation_end || previous_text == "{" || next_text == "}" {
+        return Space;
+    }
+    if next_layout.type_angle || (previous_layout.type_angle && previous_text.starts_with('<')) {
+        return None;
+    }
+    if previous_layout.type_angle {
+        return if next_text == "(" { None } else { Space };
+    }
+    if previous_layout.unary || previous_layout.cast {
+        return None;
+    }
+    if next_text == "(" {
+        return if ends_operand(previous, previous_layout) {
+            None
+        } else {
+            Space
+        };
+    }
+    if previous_layout.binary || next_layout.binary {
+        return Space;
+    }
+    if next_text == ":" {
+        return None;
+    }
+
+    Space
+}
+
+/// Whether a line break of the source between the tokens is kept.
+fn can_break(
+    previous: &Token,
+    previous_layout: &Layout,
+    next: &Token,
+    next_layout: &Layout,
+) -> bool {
+    !(matches!(next.text, ";" | "," | "." | "[" | "..")
+        || matches!(previous.text, "." | "@" | "..")
+        || previous_layout.unary
+        || previous_layout.cast
+        || previous_layout.type_angle
+        || next_layout.type_angle
+        || (next.text == "(" && ends_operand(previous, previous_layout))
+        || (next.text == "{" && next_layout.block)
+        || (previous.text == "}" && previous_layout.block))
+}
+
+/// The width of the tokens of `span` on a line.
+fn get_width(tokens: &[Token], layouts: &[Layout], span: Span<usize>) -> usize {
+    let mut width = 0;
+    for index in span.clone() {
+        if index > span.start {
+            let separator = get_separator(
+                &tokens[index - 1],
+                &layouts[index - 1],
+                &tokens[index],
+                &layouts[index],
+            );
+            width += usize::from(separator != Separator::None) + layouts[index].padding;
+        }
+        width += tokens[index].text.chars().count();
+    }
+    width
+}
+
+/// The statements of the block opened at `open`, in groups not separated by
+/// blank lines, comments or preprocessor lines.
+fn get_statement_groups(tokens: &[Token], open: usize, close: usize) -> Vec<Vec<Span<usize>>> {
+    let mut groups = vec![];
+    let mut group = vec![];
+    let mut start = None;
+
+    for (index, token) in tokens.iter().enumerate().take(close).skip(open + 1) {
+        match token.kind {
+            // A comment after a statement, on its line.
+            TokenKind::Comment if start.is_none() && token.newlines_before == 0 => {}
+            TokenKind::Code => {
+                if start.is_none() && token.newlines_before > 1 {
+                    groups.push(std::mem::take(&mut group));
+                }
+                let statement_start = *start.get_or_insert(index);
+                if token.text == ";" {
+                    group.push(statement_start..index + 1);
+                    start = None;
+                }
+            }
+            _ => {
+                groups.push(std::mem::take(&mut group));
+                start = None;
+            }
+        }
+    }
+    groups.push(group);
+
+    groups.retain(|group| !group.is_empty());
+    groups
+}
+
+/// Whether the statement is on one line, without annotations or blocks.
+fn is_on_one_line(tokens: &[Token], span: &Span<usize>) -> bool {
+    let tokens = &tokens[span.clone()];
+    tokens[1..].iter().all(|token| token.newlines_before == 0)
+        && !tokens.iter().any(|token| matches!(token.text, "@" | "{"))
+}
+
+/// Aligns the names of fields declared one after the other.
+fn align_fields(tokens: &[Token], layouts: &mut [Layout], group: &[Span<usize>]) {
+    let runs = group.split(|statement| statement.len() < 3 || !is_on_one_line(tokens, statement));
+    for run in runs {
+        let widths: Vec<usize> = run
+            .iter()
+            .map(|field| get_width(tokens, layouts, field.start..field.end - 2))
+            .collect();
+        let Some(&max_width) = widths.iter().max() else {
+            continue;
+        };
+        for (field, width) in run.iter().zip(widths) {
+            layouts[field.end - 2].padding = max_width - width;
+        }
+    }
+}
+
+/// The key set of a table entry, its elements and the action it runs.
+struct Entry {
+    key_set: Span<usize>,
+    elements: Vec<Span<usize>>,
+    action: usize,
+}
+
+fn get_entry(tokens: &[Token], layouts: &[Layout], statement: &Span<usize>) -> Option<Entry> {
+    if !is_on_one_line(tokens, statement) {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut colon = None;
+    for index in statement.clone() {
+        match tokens[index].text {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            ":" if depth == 0 && !layouts[index].binary => {
+                colon = Some(index);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let colon = colon?;
+    if colon + 2 >= statement.end {
+        return None;
+    }
+
+    let key_set = statement.start..colon;
+    let mut elements = vec![];
+    if tokens[key_set.start].text == "(" && layouts[key_set.start].pair == Some(colon - 1) {
+        let mut start = key_set.start + 1;
+        let mut depth = 0;
+        for (index, token) in tokens
+            .iter()
+            .enumerate()
+            .take(colon - 1)
+            .skip(key_set.start + 1)
+        {
+            match token.text {
+                "(" | "[" | "{" => depth += 1,
+                ")" | "]" | "}" => depth -= 1,
+                "," if depth == 0 => {
+                    elements.push(start..index);
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+        elements.push(start..colon - 1);
+    }
+
+    Some(Entry {
+        key_set,
+        elements,
+        action: colon + 1,
+    })
+}
+
+/// Aligns the columns of the key sets of entries, and the actions after them.
+fn align_entries(tokens: &[Token], layouts: &mut [Layout], group: &[Span<usize>]) {
+    let entries: Vec<Option<Entry>> = group
+        .iter()
+        .map(|statement| get_entry(tokens, layouts, statement))
+        .collect();
+
+    for run in entries.split(Option::is_none) {
+        let run: Vec<&Entry> = run.iter().flatten().collect();
+        let arity = run[0].elements.len();
+        if arity > 1 && run.iter().all(|entry| entry.elements.len() == arity) {
+            for column in 0..arity - 1 {
+                let widths: Vec<usize> = run
+                    .iter()
+                    .map(|entry| get_width(tokens, layouts, entry.elements[column].clone()))
+                    .collect();
+                let max_width = widths.iter().copied().max().unwrap_or_default();
+                for (entry, width) in run.iter().zip(widths) {
+                    layouts[entry.elements[column + 1].start].padding = max_width - width;
+                }
+            }
+        }
+
+        let widths: Vec<usize> = run
+            .iter()
+            .map(|entry| get_width(tokens, layouts, entry.key_set.clone()))
+            .collect();
+        let max_width = widths.iter().copied().max().unwrap_or_default();
+        for (entry, width) in run.iter().zip(widths) {
+            layouts[entry.action].padding = max_width - width;
+        }
+    }
+}
+
+fn align(tokens: &[Token], layouts: &mut [Layout]) {
+    for open in 0..tokens.len() {
+        let (Some(close), true) = (
+            layouts[open].pair,
+            tokens[open].text == "{" && layouts[open].block,
+        ) else {
+            continue;
+        };
+
+        let declaration: Vec<&str> = tokens[..open]
+            .iter()
+            .rev()
+            .take_while(|token| {
+                token.kind == TokenKind::Code && !matches!(token.text, ";" | "{" | "}")
+            })
+            .map(|token| token.text)
+            .collect();
+        let groups = get_statement_groups(tokens, open, close);
+
+        if declaration.iter().any(|text| TYPE_BODIES.contains(text)) {
+            for group in &groups {
+                align_fields(tokens, layouts, group);
+            }
+        } else if declaration.starts_with(&["=", "entries"]) {
+            for group in &groups {
+                align_entries(tokens, layouts, group);
+            }
+        }
+    }
+}
+
+/// A bracket left open at some point of the output.
+struct Bracket {
+    close: usize,
+    // The indentation, in levels then spaces, of the line opening it.
+    open_indent: (usize, usize),
+    // The indentation of the lines within it.
+    indent: (usize, usize),
+}
+
+/// The whitespace to lay out before each token.
+fn format_tokens(tokens: &[Token], layouts: &[Layout], options: &FormattingOptions) -> Vec<String> {
+    let indent_unit = if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
+    } else {
+        "\t".to_string()
+    };
+
+    let mut gaps = vec![];
+    let mut level = 0;
+    let mut indent = (0, 0);
+    let mut column = 0;
+    let mut brackets: Vec<Bracket> = vec![];
+    let mut in_statement = false;
+
+    for (index, token) in tokens.iter().enumerate() {
+        let layout = &layouts[index];
+        let is_code = token.kind == TokenKind::Code;
+        let closes_block = is_code && token.text == "}" && layout.block;
+        if closes_block {
+            level = usize::max(level, 1) - 1;
+        }
+
+        let mut gap = String::new();
+        if index > 0 {
+            let (previous, previous_layout) = (&tokens[index - 1], &layouts[index - 1]);
+            let mut separator = get_separator(previous, previous_layout, token, layout);
+            if separator != Separator::Line
+                && token.newlines_before > 0
+                && can_break(previous, previous_layout, token, layout)
+            {
+                separator = Separator::Line;
+            }
+
+            match separator {
+                Separator::Line => {
+                    let opens_block = previous.text == "{" && previous_layout.block;
+                    if token.newlines_before > 1
+                        && brackets.is_empty()
+                        && !in_statement
+                        && !opens_block
+                        && !closes_block
+                    {
+                        gap.push('\n');
+                    }
+                    gap.push('\n');
+
+                    indent = match brackets.last_mut() {
+                        Some(bracket) if bracket.close == index => bracket.open_indent,
+                        Some(bracket) => {
+                            // Lines start under the first element, unless
+                            // it starts a line itself.
+                            if previous_layout.pair == Some(bracket.close) {
+                                bracket.indent = (bracket.open_indent.0 + 1, bracket.open_indent.1);
+                            }
+                            bracket.indent
+                        }
+                        None if in_statement && !closes_block => (level + 1, 0),
+                        None => (level, 0),
+                    };
+                    if token.kind != TokenKind::Directive {
+                        gap.push_str(&indent_unit.repeat(indent.0));
+                        gap.push_str(&" ".repeat(indent.1));
+                    }
+                    column = 0;
+                }
+                Separator::Space => {
+                    gap.push(' ');
+                    column += 1;
+                }
+                Separator::None => {}
+            }
+            if separator != Separator::Line {
+                gap.push_str(&" ".repeat(layout.padding));
+                column += layout.padding;
+            }
+        }
+
+        gaps.push(gap);
+        column = match token.text.rfind('\n') {
+            Some(line_start) => token.text[line_start + 1..].chars().count(),
+            None => column + token.text.chars().count(),
+        };
+        if !is_code {
+            continue;
+        }
+
+        if brackets
+            .last()
+            .is_some_and(|bracket| bracket.close == index)
+        {
+            brackets.pop();
+        }
+        match (token.text, layout.pair) {
+            ("{", Some(_)) if layout.block => level += 1,
+            ("(" | "[" | "{", Some(close)) => {
+                let inner_column = column + usize::from(token.text == "{");
+                brackets.push(Bracket {
+                    close,
+                    open_indent: indent,
+                    indent: (indent.0, indent.1 + inner_column),
+                });
+            }
+            _ => {}
+        }
+
+        in_statement = !((token.text == ";" && !layout.nested)
+            || (matches!(token.text, "{" | "}") && layout.block)
+            || (token.text == "," && brackets.is_empty())
+            || layout.annotation_end);
+    }
+
+    gaps
+}
+
+/// The tokens of the source code and the whitespace to lay out before
+/// each, or `None` if the code has syntax errors.
+fn layout<'a>(
+    source_code: &'a str,
+    tree: &Tree,
+    options: &FormattingOptions,
+) -> Option<(Vec<Token<'a>>, Vec<String>)> {
+    let trivia = get_trivia(source_code);
+    let leaves = get_leaves(source_code, tree, &trivia)?;
+    let tokens = get_tokens(source_code, leaves, trivia);
+
+    let mut layouts = get_layouts(&tokens);
+    align(&tokens, &mut layouts);
+    let gaps = format_tokens(&tokens, &layouts, options);
+
+    Some((tokens, gaps))
+}
+
+/// The whitespace ending the formatted code.
+fn get_end_gap(source_code: &str, tokens: &[Token], options: &FormattingOptions) -> String {
+    let keep_end = options.insert_final_newline != Some(false) || source_code.ends_with('\n');
+    if keep_end && !tokens.is_empty() {
+        "\n".to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Converts byte offsets to positions, with the line starts of the source.
+struct Lines(Vec<usize>);
+
+impl Lines {
+    fn new(source_code: &str) -> Lines {
+        let starts = std::iter::once(0)
+            .chain(source_code.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Lines(starts)
+    }
+
+    fn get_position(&self, byte: usize) -> Position {
+        let line = self.0.partition_point(|start| *start <= byte) - 1;
+        Position::new(line as u32, (byte - self.0[line]) as u32)
+    }
+}
+
+/// The edits changing the whitespace before the tokens starting on `lines`,
+/// or on any line, to lay out the code as formatted.
+fn get_edits(
+    source_code: &str,
+    (tokens, gaps): (Vec<Token>, Vec<String>),
+    options: &FormattingOptions,
+    lines: Option<RangeInclusive<u32>>,
+) -> Vec<TextEdit> {
+    let positions = Lines::new(source_code);
+
+    let end_gap = get_end_gap(source_code, &tokens, options);
+    let gaps = tokens
+        .iter()
+        .map(|token| token.span.start)
+        .chain(std::iter::once(source_code.len()))
+        .zip(gaps.iter().chain(std::iter::once(&end_gap)));
+
+    let mut edits = vec![];
+    let mut end = 0;
+    for (index, (start, gap)) in gaps.enumerate() {
+        let position = positions.get_position(start);
+        let in_lines = lines
+            .as_ref()
+            .is_none_or(|lines| lines.contains(&position.line));
+        if in_lines && source_code[end..start] != *gap {
+            let range = Range::new(positions.get_position(end), position);
+            edits.push(TextEdit::new(range, gap.clone()));
+        }
+        end = tokens.get(index).map_or(start, |token| token.span.end);
+    }
+
+    edits
+}
+
+/// The edits formatting the document, or `None` if it has syntax errors.
+pub fn get_formatting_edits(
+    source_code: &str,
+    tree: &Tree,
+    options: &FormattingOptions,
+) -> Option<Vec<TextEdit>> {
+    let layout = layout(source_code, tree, options)?;
+
+    Some(get_edits(source_code, layout, options, None))
+}
+
+/// The edits formatting the lines of `range`.
+pub fn get_range_formatting_edits(
+    source_code: &str,
+    tree: &Tree,
+    options: &FormattingOptions,
+    range: Range,
+) -> Option<Vec<TextEdit>> {
+    let layout = layout(source_code, tree, options)?;
+    let lines = range.start.line..=range.end.line;
+
+    Some(get_edits(source_code, layout, options, Some(lines)))
+}
+
+/// The edits formatting what typing `character` before `position`
+/// completed: the statement ended by a `;`, or the line of a `}`.
+pub fn get_on_type_formatting_edits(
+    source_code: &str,
+    tree: &Tree,
+    options: &FormattingOptions,
+    position: Position,
+    character: &str,
+) -> Option<Vec<TextEdit>> {
+    let layout = layout(source_code, tree, options)?;
+
+    let mut first_line = position.line;
+    if character == ";" {
+        let end = utils::pos_to_byte(position, source_code);
+        // The statement starts after the previous `;` or brace.
+        let start = layout
+            .0
+            .iter()
+            .filter(|token| token.kind == TokenKind::Code && token.span.end < end)
+            .rev()
+            .take_while(|token| !matches!(token.text, ";" | "{" | "}"))
+            .last();
+        if let Some(start) = start {
+            first_line = Lines::new(source_code).get_position(start.span.start).line;
+        }
+    }
+    let lines = first_line..=position.line;
+
+    Some(get_edits(source_code, layout, options, Some(lines)))
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{FormattingOptions, Position, Range};
+
+    use super::{get_formatting_edits, get_on_type_formatting_edits, get_range_formatting_edits};
+    use crate::utils::{apply_edits, parse};
+
+    fn format_source(source: &str, options: &FormattingOptions) -> Option<String> {
+        let edits = get_formatting_edits(source, &parse(source), options)?;
+        Some(apply_edits(source, &edits))
+    }
+
+    #[test]
+    fn test_format_examples() {
+        let options = FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        };
+        let examples = [
+            (
+                include_str!("../../examples/firewall.p4"),
+                include_str!("snapshots/firewall.p4"),
+            ),
+            (
+                include_str!("../../examples/test.p4"),
+                include_str!("snapshots/test.p4"),
+            ),
+        ];
+
+        for (source, snapshot) in examples {
+            let formatted = format_source(source, &options).unwrap();
+            assert_eq!(formatted, snapshot);
+            assert_eq!(
+                format_source(&formatted, &options).as_deref(),
+                Some(snapshot)
+            );
+        }
+    }
+
+    #[test]
+    fn test_format_with_tabs() {
+        let options = FormattingOptions {
+            tab_size: 2,
+            insert_spaces: false,
+            ..Default::default()
+        };
+        let source =
+            "control C(inout bit<8> x,\n inout bit<8> y) {\n  apply { if (x>y){ x = y; } }\n}\n";
+
+        assert_eq!(
+            format_source(source, &options).as_deref(),
+            Some("control C(inout bit<8> x,\n          inout bit<8> y) {\n\tapply {\n\t\tif (x > y) {\n\t\t\tx = y;\n\t\t}\n\t}\n}\n")
+        );
+    }
+
+    #[test]
+    fn test_format_range() {
+        let options = FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        };
+        let source = "control C(inout bit<8> x) {\n  action a() { x=1; }\n  action b() {\n  x  =  2;\n  }\n  apply {}\n}\n";
+        let range = Range::new(Position::new(2, 0), Position::new(4, 3));
+
+        let edits = get_range_formatting_edits(source, &parse(source), &options, range).unwrap();
+        assert!(edits
+            .iter()
+            .all(|edit| (2..=4).contains(&edit.range.end.line)));
+        assert_eq!(
+            apply_edits(source, &edits),
+            "control C(inout bit<8> x) {\n  action a() { x=1; }\n    action b() {\n        x = 2;\n    }\n  apply {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_on_type() {
+        let options = FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        };
+        let format = |source: &str, position, character| {
+            let edits =
+                get_on_type_formatting_edits(source, &parse(source), &options, position, character)
+                    .unwrap();
+            let formatted = apply_edits(source, &edits);
+            (edits, formatted)
+        };
+
+        // A `;` formats the whole statement, back to the line it starts on.
+        let source = "control C(inout bit<8> x) {\n  action a() { x=1; }\n  apply {\n    x  =  x +\n        2;\n    x=3;\n  }\n}\n";
+        let (edits, formatted) = format(source, Position::new(4, 10), ";");
+        assert!(edits
+            .iter()
+            .all(|edit| (3..=4).contains(&edit.range.end.line)));
+        let lines: Vec<&str> = formatted.lines().collect();
+        assert_eq!(lines[1], "  action a() { x=1; }");
+        assert_eq!(lines[3], "        x = x +");
+        assert_eq!(lines[5], "    x=3;");
+
+        // A `}` only formats its own line.
+        let source = "control C(inout bit<8> x) {\n    apply {\n        if (x==1) {\n        x=2;\n           }\n    }\n}\n";
+        let (edits, formatted) = format(source, Position::new(4, 12), "}");
+        assert!(edits.iter().all(|edit| edit.range.end.line == 4));
+        assert_eq!(
+            formatted,
+            "control C(inout bit<8> x) {\n    apply {\n        if (x==1) {\n        x=2;\n        }\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_with_errors() {
+        // The example has a stray `ad` between two declarations.
+        let source = include_str!("../../examples/basic.p4");
+
+        assert_eq!(format_source(source, &FormattingOptions::default()), None);
+    }
+}
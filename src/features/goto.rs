@@ -1,13 +1,46 @@
 use std::sync::{Arc, Mutex};
 
-use crate::metadata::{AstQuery, SymbolTableQuery, Visitable};
+use crate::features::completion::{self, Context};
+use crate::features::members::{self, ExprType};
+use crate::metadata::{
+    AstQuery, Direction, NodeKind, SymbolTableQuery, TypeDecType, VisitNode, Visitable,
+};
+use crate::utils;
 use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::Tree;
 
+/// The declaration of the name at `position`, resolved by its role: a
+/// member through the type of its receiver, an action through the control
+/// using it, a state within its parser. Other names, and those not found
+/// by their role, are looked up among the symbols in scope.
 pub fn get_definition_range(
     ast_query: &Arc<Mutex<impl AstQuery>>,
     symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
     position: Position,
+    source_code: &str,
+    tree: &Tree,
 ) -> Option<Range> {
+    let word = get_word(source_code, position);
+    let node =
+        word.and_then(|(_, start, end)| tree.root_node().descendant_for_byte_range(start, end));
+    if let (Some((word, _, _)), Some(node)) = (word, node) {
+        let range = match members::get_receiver(node) {
+            Some(receiver) => get_member_definition(
+                word,
+                receiver,
+                source_code,
+                position,
+                ast_query,
+                symbol_table_query,
+            ),
+            None => get_role(node, source_code)
+                .and_then(|role| get_scoped_definition(word, position, role, ast_query)),
+        };
+        if range.is_some() {
+            return range;
+        }
+    }
+
     let ast_query = ast_query.lock().unwrap();
     let root_visit = ast_query.visit_root();
     let node = root_visit.get_node_at_position(position)?;
@@ -17,3 +50,525 @@ pub fn get_definition_range(
 
     Some(symbol.get_definition_range())
 }
+
+/// What a name refers to, from the syntax around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Role {
+    State,
+    Action,
+}
+
+// The role of the name `node`: a state in a transition, or an action listed
+// in a table or called.
+fn get_role(node: tree_sitter::Node, source_code: &str) -> Option<Role> {
+    match completion::get_slot_context(node, source_code) {
+        Some(Context::State) => return Some(Role::State),
+        Some(Context::TableAction) => return Some(Role::Action),
+        _ => {}
+    }
+
+    // The callee is followed by its arguments, or by its type arguments.
+    let callee = members::get_expression(node);
+    callee
+        .next_sibling()
+        .is_some_and(|next| matches!(next.kind(), "(" | "<"))
+        .then_some(Role::Action)
+}
+
+fn get_member_definition(
+    name: &str,
+    receiver: tree_sitter::Node,
+    source_code: &str,
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Range> {
+    let type_ = members::get_expression_type(receiver, source_code, position, ast_query, st_query)?;
+    members::get_type_members(&type_, ast_query)
+        .into_iter()
+        .find(|member| member.name == name)?
+        .range
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+fn find_child<'a>(parent: &'a VisitNode, kind: NodeKind, name: &str) -> Option<VisitNode<'a>> {
+    parent
+        .get_children()
+        .into_iter()
+        .find(|child| child.get().kind == kind && utils::get_name(child).as_deref() == Some(name))
+}
+
+/// States named in the transitions of a parser, and actions used by the
+/// tables or called in a control.
+fn get_scoped_definition(
+    name: &str,
+    position: Position,
+    role: Role,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Option<Range> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    for node in root.get_children() {
+        if !contains(&node.get().range, position) {
+            continue;
+        }
+        let Some(body) = node.get_child_of_kind(NodeKind::Body) else {
+            continue;
+        };
+
+        // Actions may also be declared at the top level.
+        let declaration = match (&node.get().kind, role) {
+            (NodeKind::ParserDec, Role::State) => find_child(&body, NodeKind::StateParser, name),
+            (NodeKind::ControlDec, Role::Action) => {
+                find_child(&body, NodeKind::ControlAction, name)
+                    .or_else(|| find_child(&root, NodeKind::ControlAction, name))
+            }
+            _ => None,
+        };
+        return declaration.as_ref().and_then(get_name_range);
+    }
+
+    None
+}
+
+/// The parser, control or package type declared as `name`, to find what
+/// implements it.
+#[derive(Debug, Clone)]
+pub struct Prototype {
+    kind: TypeDecType,
+    name: String,
+    type_params: Vec<String>,
+    params: Vec<(Option<Direction>, String)>,
+}
+
+/// The identifier at `position`, with its byte offsets.
+pub fn get_word(source_code: &str, position: Position) -> Option<(&str, usize, usize)> {
+    let byte = utils::pos_to_byte(position, source_code).min(source_code.len());
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let start = source_code[..byte]
+        .rfind(|c: char| !is_word(c))
+        .map_or(0, |index| index + 1);
+    let end = source_code[byte..]
+        .find(|c: char| !is_word(c))
+        .map_or(source_code.len(), |index| byte + index);
+
+    if start == end {
+        None
+    } else {
+        Some((&source_code[start..end], start, end))
+    }
+}
+
+fn get_name_range(node: &VisitNode) -> Option<Range> {
+    node.get_child_of_kind(NodeKind::Name)
+        .map(|name| name.get().range)
+}
+
+/// The name of the type of the expression at `position`, a header or struct
+/// for fields and variables.
+pub fn get_type_name(
+    position: Position,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<String> {
+    let (_, start, end) = get_word(source_code, position)?;
+    let node = tree.root_node().descendant_for_byte_range(start, end)?;
+    let expression = members::get_expression(node);
+
+    match members::get_expression_type(expression, source_code, position, ast_query, st_query)? {
+        ExprType::Named(name) | ExprType::TypeName(name) | ExprType::HeaderStack(name) => {
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// The range of the type declared as `name`.
+pub fn find_type_declaration(name: &str, ast_query: &Arc<Mutex<impl AstQuery>>) -> Option<Range> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    root.get_descendants()
+        .iter()
+        .filter(|node| matches!(node.get().kind, NodeKind::TypeDec(_) | NodeKind::Extern))
+        .find(|node| utils::get_name(node).as_deref() == Some(name))
+        .and_then(get_name_range)
+}
+
+/// The function called at `position`: the extern type for a method, and the
+/// function's name.
+pub fn get_callee(
+    position: Position,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<(Option<String>, String)> {
+    let (name, start, end) = get_word(source_code, position)?;
+    let node = tree.root_node().descendant_for_byte_range(start, end)?;
+    let Some(receiver) = members::get_receiver(node) else {
+        return Some((None, name.to_string()));
+    };
+
+    match members::get_expression_type(receiver, source_code, position, ast_query, st_query)? {
+        ExprType::Named(type_name) => Some((Some(type_name), name.to_string())),
+        _ => None,
+    }
+}
+
+/// The range of the method `name` of the extern `extern_name`, or of the
+/// extern function `name`.
+pub fn find_method_declaration(
+    extern_name: Option<&str>,
+    name: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Option<Range> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    for node in root.get_descendants() {
+        if node.get().kind != NodeKind::Extern {
+            continue;
+        }
+        let functions: Vec<VisitNode> = match extern_name {
+            Some(extern_name) if utils::get_name(&node).as_deref() == Some(extern_name) => node
+                .get_descendants()
+                .into_iter()
+                .filter(|function| function.get().kind == NodeKind::FunctionName)
+                .collect(),
+            Some(_) => continue,
+            None => node
+                .get_child_of_kind(NodeKind::FunctionName)
+                .into_iter()
+                .collect(),
+        };
+
+        if let Some(function) = functions
+            .iter()
+            .find(|function| utils::get_name(function).as_deref() == Some(name))
+        {
+            return get_name_range(function);
+        }
+    }
+
+    None
+}
+
+fn get_params(node: &VisitNode) -> Vec<(Option<Direction>, String)> {
+    let Some(params) = node.get_child_of_kind(NodeKind::Params) else {
+        return vec![];
+    };
+
+    params
+        .get_children()
+        .iter()
+        .filter(|param| param.get().kind == NodeKind::Param)
+        .map(|param| {
+            let direction = param
+                .get_children()
+                .iter()
+                .find_map(|child| match &child.get().kind {
+                    NodeKind::Direction(direction) => Some(direction.clone()),
+                    _ => None,
+                });
+            let type_name = param
+                .get_type_node()
+                .map(|type_node| type_node.get().content.split_whitespace().collect())
+                .unwrap_or_default();
+            (direction, type_name)
+        })
+        .collect()
+}
+
+/// The parser, control or package type declared as `name`.
+pub fn get_prototype(name: &str, ast_query: &Arc<Mutex<impl AstQuery>>) -> Option<Prototype> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    root.get_descendants().iter().find_map(|node| {
+        let NodeKind::TypeDec(
+            kind @ (TypeDecType::Parser | TypeDecType::Control | TypeDecType::Package),
+        ) = node.get().kind.clone()
+        else {
+            return None;
+        };
+        if utils::get_name(node).as_deref() != Some(name) {
+            return None;
+        }
+
+        let type_params = node
+            .get_child_of_kind(NodeKind::ParamType)
+            .map(|param_type| {
+                param_type
+                    .get_children()
+                    .iter()
+                    .map(|param| param.get().content.trim().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Prototype {
+            kind,
+            name: name.to_string(),
+            type_params,
+            params: get_params(node),
+        })
+    })
+}
+
+impl Prototype {
+    /// Whether parameters fit the prototype's, with its type parameters
+    /// standing for any type.
+    fn matches(&self, params: &[(Option<Direction>, String)]) -> bool {
+        self.params.len() == params.len()
+            && self.params.iter().zip(params).all(
+                |((direction, type_name), (other_direction, other_type_name))| {
+                    direction == other_direction
+                        && (type_name == other_type_name || self.type_params.contains(type_name))
+                },
+            )
+    }
+}
+
+/// The parsers or controls implementing the `prototype` type, or the
+/// instantiations of the `prototype` package.
+pub fn get_implementation_ranges(
+    prototype: &Prototype,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Vec<Range> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    root.get_descendants()
+        .iter()
+        .filter(|node| match (&prototype.kind, &node.get().kind) {
+            (TypeDecType::Parser, NodeKind::ParserDec)
+            | (TypeDecType::Control, NodeKind::ControlDec) => prototype.matches(&get_params(node)),
+            (TypeDecType::Package, NodeKind::Instantiation) => {
+                node.get_type_node().is_some_and(|type_node| {
+                    type_node.get().content.split('<').next().map(str::trim)
+                        == Some(prototype.name.as_str())
+                })
+            }
+            _ => false,
+        })
+        .filter_map(get_name_range)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use super::{
+        find_method_declaration, find_type_declaration, get_callee, get_definition_range,
+        get_implementation_ranges, get_prototype, get_type_name, get_word,
+    };
+    use crate::utils;
+
+    const SOURCE: &str = "header ipv4_t { bit<8> ttl; }
+struct headers { ipv4_t ipv4; }
+action NoAction() {}
+parser P(inout headers hdr) {
+    state start { transition parse_ipv4; }
+    state parse_ipv4 { transition accept; }
+}
+parser Q(inout headers hdr) {
+    state start { transition parse_ipv4; }
+    state parse_ipv4 { transition accept; }
+}
+control C(inout headers hdr) {
+    action drop() {}
+    table t {
+        actions = { drop; }
+        default_action = NoAction;
+    }
+    apply { hdr.ipv4.ttl = 0; }
+}
+control D(inout headers hdr) {
+    action drop() {}
+    action NoAction() {}
+    table t {
+        actions = { drop; }
+        default_action = NoAction;
+    }
+    apply {}
+}";
+
+    const TYPES_SOURCE: &str = "header ipv4_t { bit<8> ttl; }
+typedef ipv4_t ip_t;
+struct headers { ip_t ipv4; }
+extern Checksum {
+    Checksum();
+    bit<8> get(in bit<8> data);
+}
+extern void mark_to_drop();
+parser Parse<H>(packet_in pkt, out H hdr);
+control Ingress<H>(inout H hdr);
+package Switch<H>(Parse<H> p, Ingress<H> ig);
+parser MyParser(packet_in pkt, out headers hdr) {
+    state start { transition accept; }
+}
+control MyIngress(inout headers hdr) {
+    Checksum() ck;
+    apply {
+        hdr.ipv4.ttl = ck.get(hdr.ipv4.ttl);
+        mark_to_drop();
+    }
+}
+control Other(in headers hdr) { apply {} }
+Switch(MyParser(), MyIngress()) main;";
+
+    // The position of the `occurrence`th `word` in `source`.
+    fn position_of(source: &str, word: &str, occurrence: usize) -> Position {
+        let (byte, _) = source.match_indices(word).nth(occurrence).unwrap();
+        let line = source[..byte].matches('\n').count();
+        let column = byte - source[..byte].rfind('\n').map_or(0, |index| index + 1);
+        Position::new(line as u32, column as u32)
+    }
+
+    // The range of the declaration the `occurrence`th `word` refers to, and
+    // the line it is declared on.
+    fn definition_of(word: &str, occurrence: usize) -> Option<(u32, u32)> {
+        let file = utils::parse_file(SOURCE);
+
+        let range: Range = get_definition_range(
+            &file.ast_manager,
+            &file.symbol_table_manager,
+            position_of(SOURCE, word, occurrence),
+            SOURCE,
+            file.tree.as_ref().unwrap(),
+        )?;
+        Some((range.start.line, range.start.character))
+    }
+
+    #[test]
+    fn test_member_definition() {
+        // `hdr.ipv4.ttl`
+        assert_eq!(definition_of("ttl", 1), Some((0, 23)));
+        assert_eq!(definition_of("ipv4.", 0), Some((1, 24)));
+    }
+
+    #[test]
+    fn test_table_action_definition() {
+        // `actions = { drop; }`, in each control.
+        assert_eq!(definition_of("drop;", 0), Some((12, 11)));
+        assert_eq!(definition_of("drop;", 1), Some((20, 11)));
+    }
+
+    #[test]
+    fn test_transition_definition() {
+        // `transition parse_ipv4`, in each parser.
+        assert_eq!(definition_of("parse_ipv4;", 0), Some((5, 10)));
+        assert_eq!(definition_of("parse_ipv4;", 1), Some((9, 10)));
+    }
+
+    #[test]
+    fn test_default_action_definition() {
+        // The top level action, unless the control declares its own.
+        assert_eq!(definition_of("NoAction;", 0), Some((2, 7)));
+        assert_eq!(definition_of("NoAction;", 1), Some((21, 11)));
+    }
+
+    #[test]
+    fn test_type_definition() {
+        // `hdr.ipv4`, an `ip_t`, which is a typedef of `ipv4_t`.
+        let file = utils::parse_file(TYPES_SOURCE);
+        let type_name = get_type_name(
+            position_of(TYPES_SOURCE, "ipv4.", 0),
+            TYPES_SOURCE,
+            file.tree.as_ref().unwrap(),
+            &file.ast_manager,
+            &file.symbol_table_manager,
+        );
+        assert_eq!(type_name.as_deref(), Some("ipv4_t"));
+
+        let range = find_type_declaration("ipv4_t", &file.ast_manager).unwrap();
+        assert_eq!(range.start, position_of(TYPES_SOURCE, "ipv4_t", 0));
+    }
+
+    #[test]
+    fn test_method_declaration() {
+        let file = utils::parse_file(TYPES_SOURCE);
+        let callee = |position| {
+            get_callee(
+                position,
+                TYPES_SOURCE,
+                file.tree.as_ref().unwrap(),
+                &file.ast_manager,
+                &file.symbol_table_manager,
+            )
+        };
+
+        // `ck.get`, a method of the `Checksum` extern.
+        let (extern_name, name) = callee(position_of(TYPES_SOURCE, "get(", 1)).unwrap();
+        assert_eq!(
+            (extern_name.as_deref(), name.as_str()),
+            (Some("Checksum"), "get")
+        );
+        let range = find_method_declaration(Some("Checksum"), "get", &file.ast_manager);
+        assert_eq!(
+            range.map(|range| range.start),
+            Some(position_of(TYPES_SOURCE, "get(", 0))
+        );
+
+        // `mark_to_drop`, an extern function.
+        let (extern_name, name) = callee(position_of(TYPES_SOURCE, "mark_to_drop", 1)).unwrap();
+        assert_eq!((extern_name, name.as_str()), (None, "mark_to_drop"));
+        let range = find_method_declaration(None, "mark_to_drop", &file.ast_manager);
+        assert_eq!(
+            range.map(|range| range.start),
+            Some(position_of(TYPES_SOURCE, "mark_to_drop", 0))
+        );
+        assert_eq!(
+            find_method_declaration(None, "get", &file.ast_manager),
+            None
+        );
+    }
+
+    #[test]
+    fn test_implementations() {
+        let file = utils::parse_file(TYPES_SOURCE);
+        let implementations = |name| {
+            let prototype = get_prototype(name, &file.ast_manager).unwrap();
+            get_implementation_ranges(&prototype, &file.ast_manager)
+                .into_iter()
+                .map(|range| range.start)
+                .collect::<Vec<_>>()
+        };
+
+        // The type parameter stands for `headers`; `Other` takes `in` headers.
+        assert_eq!(
+            implementations("Parse"),
+            vec![position_of(TYPES_SOURCE, "MyParser", 0)]
+        );
+        assert_eq!(
+            implementations("Ingress"),
+            vec![position_of(TYPES_SOURCE, "MyIngress", 0)]
+        );
+        assert_eq!(
+            implementations("Switch"),
+            vec![position_of(TYPES_SOURCE, "main", 0)]
+        );
+        assert!(get_prototype("headers", &file.ast_manager).is_none());
+    }
+
+    #[test]
+    fn test_get_word() {
+        let source = "hdr.ipv4.ttl = 0;\nreg.read(x, 0);";
+
+        assert_eq!(get_word(source, Position::new(0, 6)), Some(("ipv4", 4, 8)));
+        assert_eq!(
+            get_word(source, Position::new(1, 4)),
+            Some(("read", 22, 26))
+        );
+        assert_eq!(get_word(source, Position::new(0, 13)), None);
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{
+    DocumentHighlight, DocumentHighlightKind, ParameterLabel, Position, Range,
+};
+use tree_sitter::Tree;
+
+use crate::features::signature_help;
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, Visitable};
+use crate::utils;
+
+fn contains(outer: &Range, inner: &Range) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+// The argument `range` is passed as, innermost when calls are nested.
+fn get_argument<'a>(
+    range: &Range,
+    source_code: &str,
+    tree: &'a Tree,
+) -> Option<tree_sitter::Node<'a>> {
+    let start = utils::pos_to_byte(range.start, source_code);
+    let end = utils::pos_to_byte(range.end, source_code);
+    let mut node = tree.root_node().descendant_for_byte_range(start, end)?;
+    while node.kind() != "argument" {
+        node = node.parent()?;
+    }
+    Some(node)
+}
+
+// Whether `argument` is passed to an `out` or `inout` parameter, in a call
+// statement or in a call within an expression.
+fn is_written(
+    argument: tree_sitter::Node,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> bool {
+    let Some((callee, index)) = signature_help::get_call(source_code, tree, argument.start_byte())
+    else {
+        return false;
+    };
+    let position = utils::point_to_pos(argument.start_position());
+    let signatures =
+        signature_help::get_signatures(&callee, position, source_code, ast_query, st_query);
+    let Some(signature) = signatures.iter().find(|signature| {
+        signature
+            .parameters
+            .as_ref()
+            .is_some_and(|parameters| parameters.len() > index as usize)
+    }) else {
+        return false;
+    };
+    let parameters: Vec<&str> = signature
+        .parameters
+        .iter()
+        .flatten()
+        .filter_map(|parameter| match &parameter.label {
+            ParameterLabel::Simple(text) => Some(text.as_str()),
+            ParameterLabel::LabelOffsets(_) => None,
+        })
+        .collect();
+
+    let parameter = match argument.child_by_field_name("name") {
+        Some(name) => {
+            let name = utils::get_node_text(&name, source_code);
+            parameters.into_iter().find(|parameter| {
+                signature_help::get_parameter_name(parameter) == Some(name.trim())
+            })
+        }
+        None => parameters.get(index as usize).copied(),
+    };
+    parameter
+        .is_some_and(|parameter| parameter.starts_with("out ") || parameter.starts_with("inout "))
+}
+
+/// The ranges written among the statements containing one of `ranges`:
+/// assignment targets and arguments for `out` or `inout` parameters.
+fn get_write_ranges(
+    ranges: &[Range],
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<Range> {
+    let mut writes = vec![];
+    {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+        for statement in root.get_descendants() {
+            let statement_range = statement.get().range;
+            if statement.get().kind == NodeKind::Assignment
+                && statement.get_value_node().is_some()
+                && ranges.iter().any(|range| contains(&statement_range, range))
+            {
+                writes.extend(
+                    statement
+                        .get_child_of_kind(NodeKind::NameStatement)
+                        .map(|name| name.get().range),
+                );
+            }
+        }
+    }
+
+    // The signatures are looked up once the AST is released.
+    for range in ranges {
+        let Some(argument) = get_argument(range, source_code, tree) else {
+            continue;
+        };
+        if is_written(argument, source_code, tree, ast_query, st_query) {
+            writes.push(utils::ts_range_to_lsp_range(argument.range()));
+        }
+    }
+
+    writes
+}
+
+/// The declaration and the uses of the symbol at `position`, as writes when
+/// they are assigned or passed to an `out` parameter and as reads otherwise.
+pub fn get_highlights(
+    position: Position,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Vec<DocumentHighlight>> {
+    let name = {
+        let ast_query = ast_query.lock().unwrap();
+        let root = ast_query.visit_root();
+        let node = root.get_node_at_position(position)?;
+        node.get().content.clone()
+    };
+    let ranges: Vec<Range> = {
+        let st_query = st_query.lock().unwrap();
+        let symbol = st_query.get_symbol_at_pos(name, position)?;
+        std::iter::once(symbol.get_definition_range())
+            .chain(symbol.get_usages().iter().copied())
+            .collect()
+    };
+
+    let writes = get_write_ranges(&ranges, source_code, tree, ast_query, st_query);
+
+    Some(
+        ranges
+            .into_iter()
+            .map(|range| {
+                let kind = if writes.iter().any(|write| contains(write, &range)) {
+                    DocumentHighlightKind::WRITE
+                } else {
+                    DocumentHighlightKind::READ
+                };
+                DocumentHighlight {
+                    range,
+                    kind: Some(kind),
+                }
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{DocumentHighlightKind, Position};
+
+    use super::get_highlights;
+    use crate::utils;
+
+    #[test]
+    fn test_read_write() {
+        let source_code = "extern bool get(out bit<8> value);
+action read(out bit<8> value) { value = 1; }
+control C() {
+    apply {
+        bit<8> x = 0;
+        x = 1;
+        read(x);
+        if (get(x)) {}
+        bit<8> y = x + 1;
+    }
+}";
+        let file = utils::parse_file(source_code);
+        let highlights = get_highlights(
+            Position::new(5, 8),
+            source_code,
+            file.tree.as_ref().unwrap(),
+            &file.ast_manager,
+            &file.symbol_table_manager,
+        )
+        .unwrap();
+
+        let kinds: Vec<(u32, DocumentHighlightKind)> = highlights
+            .iter()
+            .map(|highlight| (highlight.range.start.line, highlight.kind.unwrap()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (4, DocumentHighlightKind::READ),
+                (5, DocumentHighlightKind::WRITE),
+                (6, DocumentHighlightKind::WRITE),
+                (7, DocumentHighlightKind::WRITE),
+                (8, DocumentHighlightKind::READ),
+            ]
+        );
+    }
+}
@@ -1,6 +1,8 @@
 #![allow(unused)]
 
-use tower_lsp::lsp_types::{HoverContents, MarkedString};
+use tower_lsp::lsp_types::{HoverContents, LanguageString, MarkedString};
+
+use crate::features::constant_folding::Constant;
 
 pub struct HoverContentBuilder {
     items: Vec<MarkedString>,
@@ -17,6 +19,22 @@ impl HoverContentBuilder {
         self
     }
 
+    pub fn add_code(mut self, code: &str) -> HoverContentBuilder {
+        self.items
+            .push(MarkedString::LanguageString(LanguageString {
+                language: "p4".to_string(),
+                value: code.to_string(),
+            }));
+
+        self
+    }
+
+    pub fn add_constant(self, constant: Constant) -> HoverContentBuilder {
+        self.add_text(&format!("dec: {}", constant.to_decimal()))
+            .add_text(&format!("hex: {}", constant.to_hex()))
+            .add_text(&format!("bin: {}", constant.to_binary()))
+    }
+
     pub fn build(self) -> HoverContents {
         HoverContents::Array(self.items)
     }
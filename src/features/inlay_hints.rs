@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{
+    InlayHint, InlayHintKind, InlayHintLabel, InlayHintTooltip, ParameterLabel, Position, Range,
+};
+use tree_sitter::Tree;
+
+use crate::features::constant_folding;
+use crate::features::preprocessor::Preprocessor;
+use crate::features::signature_help;
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, TypeDecType, Visitable};
+use crate::utils;
+
+// The categories, as named in the `inlay_hints` setting.
+pub const TYPES: &str = "types";
+pub const PARAMETER_NAMES: &str = "parameter_names";
+pub const CONTROL_PLANE: &str = "control_plane";
+pub const DEFINE_VALUES: &str = "define_values";
+
+const MAX_TYPEDEF_DEPTH: usize = 8;
+
+fn new_hint(position: Position, label: String, kind: Option<InlayHintKind>) -> InlayHint {
+    InlayHint {
+        position,
+        label: InlayHintLabel::String(label),
+        kind,
+        text_edits: None,
+        tooltip: None,
+        padding_left: None,
+        padding_right: None,
+        data: None,
+    }
+}
+
+fn contains(range: &Range, position: Position) -> bool {
+    position >= range.start && position <= range.end
+}
+
+/// The base type of typedef'd types, after their uses.
+pub fn get_type_hints(range: Range, ast_query: &Arc<Mutex<impl AstQuery>>) -> Vec<InlayHint> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let nodes = root.get_descendants();
+
+    let typedefs: HashMap<String, String> = nodes
+        .iter()
+        .filter(|node| node.get().kind == NodeKind::TypeDec(TypeDecType::TypeDef))
+        .filter_map(|node| {
+            let type_node = node.get_type_node()?;
+            Some((
+                utils::get_name(node)?,
+                type_node.get().content.trim().to_string(),
+            ))
+        })
+        .collect();
+    if typedefs.is_empty() {
+        return vec![];
+    }
+
+    nodes
+        .iter()
+        .filter(|node| {
+            matches!(node.get().kind, NodeKind::Type(_)) && contains(&range, node.get().range.end)
+        })
+        .filter_map(|node| {
+            let mut base = typedefs.get(node.get().content.trim())?;
+            for _ in 0..MAX_TYPEDEF_DEPTH {
+                match typedefs.get(base) {
+                    Some(next) => base = next,
+                    None => break,
+                }
+            }
+
+            let mut hint = new_hint(
+                node.get().range.end,
+                format!("= {base}"),
+                Some(InlayHintKind::TYPE),
+            );
+            hint.padding_left = Some(true);
+            Some(hint)
+        })
+        .collect()
+}
+
+fn get_arguments<'a>(
+    tree: &'a Tree,
+    start_byte: usize,
+    end_byte: usize,
+) -> Vec<tree_sitter::Node<'a>> {
+    let mut arguments = vec![];
+    let mut stack = vec![tree.root_node()];
+
+    while let Some(node) = stack.pop() {
+        if node.end_byte() < start_byte || node.start_byte() > end_byte {
+            continue;
+        }
+        if node.kind() == "argument" {
+            arguments.push(node);
+        }
+        let mut cursor = node.walk();
+        stack.extend(node.named_children(&mut cursor));
+    }
+
+    arguments.sort_by_key(|argument| argument.start_byte());
+    arguments
+}
+
+/// The names of the parameters the arguments of calls are passed to, unless
+/// the argument already is named or spells out the name.
+pub fn get_parameter_hints(
+    range: Range,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<InlayHint> {
+    let arguments = get_arguments(
+        tree,
+        utils::pos_to_byte(range.start, source_code),
+        utils::pos_to_byte(range.end, source_code),
+    );
+
+    // The arguments of a call share their parent.
+    let mut calls: Vec<(usize, Vec<tree_sitter::Node>)> = vec![];
+    for argument in arguments {
+        let Some(parent) = argument.parent() else {
+            continue;
+        };
+        match calls.iter_mut().find(|(id, _)| *id == parent.id()) {
+            Some((_, call_arguments)) => call_arguments.push(argument),
+            None => calls.push((parent.id(), vec![argument])),
+        }
+    }
+
+    let mut hints = vec![];
+    for (_, call_arguments) in calls {
+        let first = call_arguments[0];
+        let Some((callee, _)) = signature_help::get_call(source_code, tree, first.start_byte())
+        else {
+            continue;
+        };
+
+        let position = utils::point_to_pos(first.start_position());
+        let signatures =
+            signature_help::get_signatures(&callee, position, source_code, ast_query, st_query);
+        let Some(signature) = signatures.iter().find(|signature| {
+            signature
+                .parameters
+                .as_ref()
+                .is_some_and(|parameters| parameters.len() >= call_arguments.len())
+        }) else {
+            continue;
+        };
+
+        for (argument, parameter) in call_arguments
+            .iter()
+            .zip(signature.parameters.iter().flatten())
+        {
+            if argument.child_by_field_name("name").is_some() {
+                continue;
+            }
+            let ParameterLabel::Simple(label) = &parameter.label else {
+                continue;
+            };
+            let Some(name) = signature_help::get_parameter_name(label) else {
+                continue;
+            };
+
+            let text = utils::get_node_text(argument, source_code);
+            if text == name || text.ends_with(&format!(".{name}")) {
+                continue;
+            }
+
+            let mut hint = new_hint(
+                utils::point_to_pos(argument.start_position()),
+                format!("{name}:"),
+                Some(InlayHintKind::PARAMETER),
+            );
+            hint.padding_right = Some(true);
+            hints.push(hint);
+        }
+    }
+
+    hints
+}
+
+/// Marks the directionless parameters of actions, which the control plane
+/// provides when the action runs from a table.
+pub fn get_control_plane_hints(
+    range: Range,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Vec<InlayHint> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let mut hints = vec![];
+
+    for action in root.get_descendants() {
+        if action.get().kind != NodeKind::ControlAction {
+            continue;
+        }
+        let Some(params) = action.get_child_of_kind(NodeKind::Params) else {
+            continue;
+        };
+
+        for param in params.get_children() {
+            let directionless = !param
+                .get_children()
+                .iter()
+                .any(|child| matches!(child.get().kind, NodeKind::Direction(_)));
+            if param.get().kind != NodeKind::Param || !directionless {
+                continue;
+            }
+
+            let position = param
+                .get_type_node()
+                .map_or(param.get().range.start, |type_node| {
+                    type_node.get().range.start
+                });
+            if !contains(&range, position) {
+                continue;
+            }
+
+            let mut hint = new_hint(position, "control-plane".to_string(), None);
+            hint.padding_right = Some(true);
+            hint.tooltip = Some(InlayHintTooltip::String(
+                "Set by the control plane when the action runs from a table entry.".to_string(),
+            ));
+            hints.push(hint);
+        }
+    }
+
+    hints
+}
+
+/// The value of the constants `#define`d, after their uses.
+pub fn get_define_hints(range: Range, preprocessor: &Preprocessor) -> Vec<InlayHint> {
+    preprocessor
+        .get_usages()
+        .filter(|(usage, definition)| definition.params.is_none() && contains(&range, usage.end))
+        .filter_map(|(usage, definition)| {
+            let expansion = preprocessor.expand(&definition.name);
+            let constant = constant_folding::evaluate(&expansion, &HashMap::new())?;
+            let value = constant.to_decimal();
+            // Literals are their own value.
+            if expansion.trim() == value {
+                return None;
+            }
+
+            let mut hint = new_hint(usage.end, format!("= {value}"), None);
+            hint.padding_left = Some(true);
+            Some(hint)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{InlayHintLabel, Position, Range};
+
+    use super::{CONTROL_PLANE, DEFINE_VALUES, PARAMETER_NAMES, TYPES};
+    use crate::settings::Settings;
+    use crate::utils;
+
+    const SOURCE: &str = r"#define PORTS 1 << 4
+typedef bit<9> port_t;
+typedef port_t egress_t;
+control c(inout bit<9> p) {
+    action set(egress_t port, in bit<8> ttl) {
+        p = port;
+    }
+    apply {
+        set(PORTS, 8);
+    }
+}
+";
+
+    /// The hints of `SOURCE` of the only `category` enabled, or of none.
+    fn get_hints(category: Option<&str>) -> Vec<(Position, String)> {
+        let mut file = utils::parse_file(SOURCE);
+        let mut settings = Settings::default();
+        for other in [TYPES, PARAMETER_NAMES, CONTROL_PLANE, DEFINE_VALUES] {
+            settings
+                .inlay_hints
+                .insert(other.to_string(), Some(other) == category);
+        }
+        file.update_settings(settings);
+
+        file.get_inlay_hints(Range::new(Position::new(0, 0), Position::new(11, 0)))
+            .into_iter()
+            .map(|hint| match hint.label {
+                InlayHintLabel::String(label) => (hint.position, label),
+                InlayHintLabel::LabelParts(_) => (hint.position, String::new()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_type_hints() {
+        assert_eq!(
+            get_hints(Some(TYPES)),
+            vec![
+                (Position::new(2, 14), "= bit<9>".to_string()),
+                (Position::new(4, 23), "= bit<9>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameter_hints() {
+        assert_eq!(
+            get_hints(Some(PARAMETER_NAMES)),
+            vec![
+                (Position::new(8, 12), "port:".to_string()),
+                (Position::new(8, 19), "ttl:".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_control_plane_hints() {
+        assert_eq!(
+            get_hints(Some(CONTROL_PLANE)),
+            vec![(Position::new(4, 15), "control-plane".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_define_hints() {
+        assert_eq!(
+            get_hints(Some(DEFINE_VALUES)),
+            vec![(Position::new(8, 17), "= 16".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_disabled_hints() {
+        assert_eq!(get_hints(None), vec![]);
+    }
+}
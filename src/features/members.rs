@@ -0,0 +1,637 @@
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::features::constant_folding::ConstantType;
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, TypeDecType, VisitNode, Visitable};
+use crate::utils;
+
+lazy_static! {
+    static ref SIZED_TYPE: Regex = Regex::new(r"^(bit|int)\s*<\s*(\d+)\s*>$").unwrap();
+}
+
+const PACKET_IN_METHODS: [(&str, &str); 4] = [
+    ("extract", "void extract<T>(out T hdr)"),
+    ("lookahead", "T lookahead<T>()"),
+    ("advance", "void advance(in bit<32> sizeInBits)"),
+    ("length", "bit<32> length()"),
+];
+const PACKET_OUT_METHODS: [(&str, &str); 1] = [("emit", "void emit<T>(in T hdr)")];
+const HEADER_METHODS: [(&str, &str); 3] = [
+    ("isValid", "bool isValid()"),
+    ("setValid", "void setValid()"),
+    ("setInvalid", "void setInvalid()"),
+];
+const HEADER_STACK_METHODS: [(&str, &str); 2] = [
+    ("push_front", "void push_front(int count)"),
+    ("pop_front", "void pop_front(int count)"),
+];
+const CORE_ERRORS: [&str; 7] = [
+    "NoError",
+    "PacketTooShort",
+    "NoMatch",
+    "StackOutOfBounds",
+    "HeaderTooShort",
+    "ParserTimeout",
+    "ParserInvalidArgument",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MemberKind {
+    Field,
+    Method,
+}
+
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub kind: MemberKind,
+    /// The type of a field or the signature of a method.
+    pub detail: String,
+    /// The declaration, when it is in the document.
+    pub range: Option<Range>,
+}
+
+impl Member {
+    fn builtin(kind: MemberKind, (name, detail): (&str, &str)) -> Member {
+        Member {
+            name: name.to_string(),
+            kind,
+            detail: detail.to_string(),
+            range: None,
+        }
+    }
+}
+
+/// The type of an expression, as far as its members are concerned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprType {
+    /// A declared or builtin type, by name.
+    Named(String),
+    /// The type itself, as in `MyEnum.A` or `error.NoMatch`.
+    TypeName(String),
+    HeaderStack(String),
+    /// A fixed-width integer type, like `bit<8>`.
+    Sized(ConstantType),
+    Table,
+    ApplyResult,
+    Other,
+}
+
+/// A step of an expression like `hdr.mpls[0].label`: a name, an index or
+/// a call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Name(String),
+    Index,
+    Call,
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// The node filling the same range as `node` the highest in the tree, like
+// the expression wrapping a name.
+fn get_outermost(mut node: tree_sitter::Node) -> tree_sitter::Node {
+    while let Some(parent) = node.parent() {
+        if parent.byte_range() != node.byte_range() {
+            break;
+        }
+        node = parent;
+    }
+    node
+}
+
+/// The receiver of the member `node`, or of the member after the `.` token
+/// `node`: the expression left of the `.`, like `hdr.mpls[0]` or
+/// `t.apply()`.
+pub fn get_receiver(node: tree_sitter::Node) -> Option<tree_sitter::Node> {
+    let dot = match node.kind() {
+        "." => node,
+        _ => get_outermost(node)
+            .prev_sibling()
+            .filter(|previous| previous.kind() == ".")?,
+    };
+    dot.prev_sibling()
+}
+
+/// The expression the name `node` ends: the member access when it is a
+/// member, like `hdr.ipv4` for `ipv4`, or else the name itself.
+pub fn get_expression(node: tree_sitter::Node) -> tree_sitter::Node {
+    let node = get_outermost(node);
+    match (node.prev_sibling(), node.parent()) {
+        (Some(previous), Some(parent)) if previous.kind() == "." => parent,
+        _ => node,
+    }
+}
+
+/// The steps of the expression `node`, from its member accesses, indices
+/// and calls. None for other expressions, like operations.
+pub fn get_segments(node: tree_sitter::Node, source_code: &str) -> Option<Vec<Segment>> {
+    let mut cursor = node.walk();
+    let children: Vec<tree_sitter::Node> = node
+        .children(&mut cursor)
+        .filter(|child| !child.is_extra())
+        .collect();
+
+    match children.as_slice() {
+        [] => {
+            let text = utils::normalize(&utils::get_node_text(&node, source_code));
+            is_identifier(&text).then(|| vec![Segment::Name(text)])
+        }
+        [child] => get_segments(*child, source_code),
+        [open, inner, close] if open.kind() == "(" && close.kind() == ")" => {
+            get_segments(*inner, source_code)
+        }
+        [receiver, token, rest @ ..] if receiver.is_named() => {
+            let mut segments = get_segments(*receiver, source_code)?;
+            match token.kind() {
+                "." => {
+                    let member = rest.first()?;
+                    let name = utils::normalize(&utils::get_node_text(member, source_code));
+                    if !is_identifier(&name) {
+                        return None;
+                    }
+                    segments.push(Segment::Name(name));
+                }
+                "[" => segments.push(Segment::Index),
+                "(" => segments.push(Segment::Call),
+                // Type arguments, of a call like `lookahead<T>()` or of a
+                // type like `register<bit<32>>`.
+                "<" if rest.iter().any(|child| child.kind() == "(") => segments.push(Segment::Call),
+                "<" if rest.last().is_some_and(|last| last.kind() == ">") => {}
+                _ => return None,
+            }
+            Some(segments)
+        }
+        _ => None,
+    }
+}
+
+// The steps of the groups `text` follows a name with, like `[0]` or `()`.
+fn get_group_segments(text: &str) -> Option<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' | '[' | '<' if depth == 0 => {
+                depth += 1;
+                match c {
+                    '[' => segments.push(Segment::Index),
+                    '(' => segments.push(Segment::Call),
+                    // Type arguments, before the call.
+                    _ => {}
+                }
+            }
+            '(' | '[' | '<' => depth += 1,
+            ')' | ']' | '>' if depth > 0 => depth -= 1,
+            _ if depth > 0 => {}
+            _ => return None,
+        }
+    }
+    (depth == 0).then_some(segments)
+}
+
+/// The steps of the AST expression `node`, like the value or the target of
+/// an assignment, when it is a member access like `hdr.mpls[0].label`.
+pub fn get_path_segments(node: &VisitNode) -> Option<Vec<Segment>> {
+    let content = &node.get().content;
+    let start = node.get().range.start;
+    let offset = |position| utils::get_offset(content, start, position);
+
+    let paths = utils::get_value_paths(node);
+    let path = paths.first()?;
+    if !content[..offset(path[0].1.start)].trim().is_empty() {
+        return None;
+    }
+
+    let mut segments = vec![];
+    for (index, (name, range)) in path.iter().enumerate() {
+        segments.push(Segment::Name(name.clone()));
+        let end = path
+            .get(index + 1)
+            .map_or(content.len(), |(_, next)| offset(next.start));
+        let mut between = utils::normalize(content.get(offset(range.end)..end)?);
+        if index + 1 < path.len() {
+            between = between.strip_suffix('.')?.to_string();
+        }
+        segments.extend(get_group_segments(&between)?);
+    }
+
+    Some(segments)
+}
+
+fn find_declaration<'a>(nodes: &'a [VisitNode<'a>], name: &str) -> Option<&'a VisitNode<'a>> {
+    nodes.iter().find(|node| {
+        matches!(node.get().kind, NodeKind::TypeDec(_) | NodeKind::Extern)
+            && utils::get_name(node).as_deref() == Some(name)
+    })
+}
+
+/// The type of a type as written, following typedefs.
+fn parse_type(nodes: &[VisitNode], text: &str, depth: usize) -> ExprType {
+    let text = text.trim();
+    if let Some(element) = text
+        .strip_suffix(']')
+        .and_then(|text| text.split('[').next())
+    {
+        return ExprType::HeaderStack(element.trim().to_string());
+    }
+    if let Some(captures) = SIZED_TYPE.captures(text) {
+        if let Ok(width) = captures[2].parse() {
+            return ExprType::Sized(match &captures[1] {
+                "bit" => ConstantType::Bit(width),
+                _ => ConstantType::Int(width),
+            });
+        }
+    }
+    let name = text
+        .split('<')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    if let Some(declaration) = find_declaration(nodes, &name) {
+        if declaration.get().kind == NodeKind::TypeDec(TypeDecType::TypeDef) && depth < 8 {
+            if let Some(type_node) = declaration.get_type_node() {
+                return parse_type(nodes, &type_node.get().content, depth + 1);
+            }
+        }
+    }
+
+    ExprType::Named(name)
+}
+
+fn get_fields(declaration: &VisitNode) -> Vec<Member> {
+    let mut fields = vec![];
+    if let Some(fields_node) = declaration.get_child_of_kind(NodeKind::Fields) {
+        for field in fields_node.get_children() {
+            if field.get().kind != NodeKind::Field {
+                continue;
+            }
+            if let Some(name_node) = field.get_child_of_kind(NodeKind::Name) {
+                fields.push(Member {
+                    name: name_node.get().content.clone(),
+                    kind: MemberKind::Field,
+                    detail: field
+                        .get_type_node()
+                        .map(|type_node| type_node.get().content.clone())
+                        .unwrap_or_default(),
+                    range: Some(name_node.get().range),
+                });
+            }
+        }
+    }
+
+    fields
+}
+
+fn get_options(declaration: &VisitNode, type_name: &str) -> Vec<Member> {
+    declaration
+        .get_descendants()
+        .iter()
+        .filter(|node| node.get().kind == NodeKind::Option)
+        .filter_map(|option| {
+            let name_node = option.get_child_of_kind(NodeKind::Name)?;
+            Some(Member {
+                name: name_node.get().content.trim().to_string(),
+                kind: MemberKind::Field,
+                detail: type_name.to_string(),
+                range: Some(name_node.get().range),
+            })
+        })
+        .collect()
+}
+
+fn get_methods(declaration: &VisitNode) -> Vec<Member> {
+    declaration
+        .get_descendants()
+        .iter()
+        .filter(|node| node.get().kind == NodeKind::FunctionName)
+        .filter_map(|function| {
+            let name_node = function.get_child_of_kind(NodeKind::Name)?;
+            Some(Member {
+                name: name_node.get().content.clone(),
+                kind: MemberKind::Method,
+                detail: function
+                    .get()
+                    .content
+                    .trim()
+                    .trim_end_matches(';')
+                    .to_string(),
+                range: Some(name_node.get().range),
+            })
+        })
+        .collect()
+}
+
+/// The return type of the method `name` called on an expression of type
+/// `type_`.
+fn get_call_type(nodes: &[VisitNode], type_: &ExprType, name: &str) -> ExprType {
+    match type_ {
+        ExprType::Table if name == "apply" => ExprType::ApplyResult,
+        ExprType::Named(type_name) => {
+            let Some(declaration) = find_declaration(nodes, type_name) else {
+                return ExprType::Other;
+            };
+            declaration
+                .get_descendants()
+                .iter()
+                .filter(|node| node.get().kind == NodeKind::FunctionName)
+                .find(|function| utils::get_name(function).as_deref() == Some(name))
+                .and_then(|function| function.get_type_node())
+                .map(|type_node| parse_type(nodes, &type_node.get().content, 0))
+                .unwrap_or(ExprType::Other)
+        }
+        _ => ExprType::Other,
+    }
+}
+
+fn get_field_type(nodes: &[VisitNode], type_: &ExprType, name: &str) -> ExprType {
+    match type_ {
+        ExprType::HeaderStack(element) => match name {
+            "next" | "last" => ExprType::Named(element.clone()),
+            _ => ExprType::Other,
+        },
+        ExprType::Named(type_name) => find_declaration(nodes, type_name)
+            .map(get_fields)
+            .unwrap_or_default()
+            .into_iter()
+            .find(|field| field.name == name)
+            .map(|field| parse_type(nodes, &field.detail, 0))
+            .unwrap_or(ExprType::Other),
+        _ => ExprType::Other,
+    }
+}
+
+/// The type of the expression of `segments`, at `position`. Each step is
+/// typed from the declaration of the previous one.
+pub fn get_type(
+    segments: &[Segment],
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<ExprType> {
+    let Some(Segment::Name(first)) = segments.first() else {
+        return None;
+    };
+
+    let type_name = st_query
+        .lock()
+        .unwrap()
+        .get_symbol_at_pos(first.clone(), position)
+        .and_then(|symbol| symbol.get_type_name());
+
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let nodes = root.get_descendants();
+
+    let mut type_ = match type_name {
+        Some(type_name) => parse_type(&nodes, &type_name, 0),
+        None if nodes.iter().any(|node| {
+            node.get().kind == NodeKind::ControlTable
+                && utils::get_name(node).as_deref() == Some(first)
+        }) =>
+        {
+            ExprType::Table
+        }
+        None if first == "error" || find_declaration(&nodes, first).is_some() => {
+            ExprType::TypeName(first.clone())
+        }
+        None => return None,
+    };
+
+    let mut index = 1;
+    while index < segments.len() {
+        type_ = match (&segments[index], segments.get(index + 1)) {
+            (Segment::Name(name), Some(Segment::Call)) => {
+                index += 1;
+                get_call_type(&nodes, &type_, name)
+            }
+            (Segment::Name(name), _) => get_field_type(&nodes, &type_, name),
+            (Segment::Index, _) => match &type_ {
+                ExprType::HeaderStack(element) => ExprType::Named(element.clone()),
+                _ => ExprType::Other,
+            },
+            (Segment::Call, _) => ExprType::Other,
+        };
+        index += 1;
+    }
+
+    Some(type_)
+}
+
+/// The type of the expression `node` of the syntax tree, at `position`.
+pub fn get_expression_type(
+    node: tree_sitter::Node,
+    source_code: &str,
+    position: Position,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<ExprType> {
+    get_type(
+        &get_segments(node, source_code)?,
+        position,
+        ast_query,
+        st_query,
+    )
+}
+
+/// The type named by `text`, with typedefs resolved.
+pub fn resolve_type(text: &str, ast_query: &Arc<Mutex<impl AstQuery>>) -> ExprType {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    parse_type(&root.get_descendants(), text, 0)
+}
+
+/// The members of an expression of type `type_`.
+pub fn get_type_members(type_: &ExprType, ast_query: &Arc<Mutex<impl AstQuery>>) -> Vec<Member> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let nodes = root.get_descendants();
+
+    match type_ {
+        ExprType::Table => vec![Member::builtin(
+            MemberKind::Method,
+            ("apply", "apply_result apply()"),
+        )],
+        ExprType::ApplyResult => vec![
+            Member::builtin(MemberKind::Field, ("hit", "bool")),
+            Member::builtin(MemberKind::Field, ("miss", "bool")),
+            Member::builtin(MemberKind::Field, ("action_run", "action_list")),
+        ],
+        ExprType::HeaderStack(element) => {
+            let mut members = vec![
+                Member::builtin(MemberKind::Field, ("next", element.as_str())),
+                Member::builtin(MemberKind::Field, ("last", element.as_str())),
+                Member::builtin(MemberKind::Field, ("lastIndex", "bit<32>")),
+                Member::builtin(MemberKind::Field, ("size", "bit<32>")),
+            ];
+            members.extend(
+                HEADER_STACK_METHODS
+                    .into_iter()
+                    .map(|method| Member::builtin(MemberKind::Method, method)),
+            );
+            members
+        }
+        ExprType::TypeName(type_name) => {
+            let mut members: Vec<Member> = match type_name.as_str() {
+                "error" => nodes
+                    .iter()
+                    .filter(|node| node.get().kind == NodeKind::ErrorCst)
+                    .flat_map(|node| get_options(node, "error"))
+                    .collect(),
+                _ => find_declaration(&nodes, type_name)
+                    .filter(|node| node.get().kind == NodeKind::TypeDec(TypeDecType::Enum))
+                    .map(|node| get_options(node, type_name))
+                    .unwrap_or_default(),
+            };
+            if type_name == "error" {
+                for name in CORE_ERRORS {
+                    if !members.iter().any(|member| member.name == name) {
+                        members.push(Member::builtin(MemberKind::Field, (name, "error")));
+                    }
+                }
+            }
+            members
+        }
+        ExprType::Named(type_name) => match find_declaration(&nodes, type_name) {
+            Some(declaration) => match &declaration.get().kind {
+                NodeKind::TypeDec(TypeDecType::HeaderType) => {
+                    let mut members = get_fields(declaration);
+                    members.extend(
+                        HEADER_METHODS
+                            .into_iter()
+                            .map(|method| Member::builtin(MemberKind::Method, method)),
+                    );
+                    members
+                }
+                NodeKind::TypeDec(TypeDecType::HeaderUnion) => {
+                    let mut members = get_fields(declaration);
+                    members.push(Member::builtin(MemberKind::Method, HEADER_METHODS[0]));
+                    members
+                }
+                NodeKind::TypeDec(TypeDecType::Struct) => get_fields(declaration),
+                NodeKind::Extern => get_methods(declaration),
+                _ => vec![],
+            },
+            // `core.p4` is usually not part of the workspace.
+            None => match type_name.as_str() {
+                "packet_in" => PACKET_IN_METHODS
+                    .into_iter()
+                    .map(|method| Member::builtin(MemberKind::Method, method))
+                    .collect(),
+                "packet_out" => PACKET_OUT_METHODS
+                    .into_iter()
+                    .map(|method| Member::builtin(MemberKind::Method, method))
+                    .collect(),
+                _ => vec![],
+            },
+        },
+        ExprType::Sized(_) | ExprType::Other => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::{get_expression, get_receiver, get_segments, get_type, ExprType, Segment};
+    use crate::features::constant_folding::ConstantType;
+    use crate::utils;
+
+    const SOURCE: &str = "header mpls_t { bit<20> label; }
+header ipv4_t { bit<8> ttl; }
+struct headers { ipv4_t ipv4; mpls_t[4] mpls; }
+control C(inout headers hdr) {
+    table t { actions = { NoAction; } }
+    apply {
+        hdr.mpls[hdr.ipv4.ttl].label = 0;
+        if (t.apply().hit) {}
+    }
+}";
+
+    fn name(text: &str) -> Segment {
+        Segment::Name(text.to_string())
+    }
+
+    // The segments of the expression ending with the `occurrence`th `word`.
+    fn segments_at(word: &str, occurrence: usize) -> Option<Vec<Segment>> {
+        let tree = utils::parse(SOURCE);
+        let start = SOURCE.match_indices(word).nth(occurrence).unwrap().0;
+        let node = tree
+            .root_node()
+            .descendant_for_byte_range(start, start + word.len())
+            .unwrap();
+        get_segments(get_expression(node), SOURCE)
+    }
+
+    #[test]
+    fn test_get_segments() {
+        assert_eq!(
+            segments_at("label", 1),
+            Some(vec![
+                name("hdr"),
+                name("mpls"),
+                Segment::Index,
+                name("label")
+            ])
+        );
+        assert_eq!(
+            segments_at("ttl", 1),
+            Some(vec![name("hdr"), name("ipv4"), name("ttl")])
+        );
+        assert_eq!(
+            segments_at("hit", 0),
+            Some(vec![name("t"), name("apply"), Segment::Call, name("hit")])
+        );
+    }
+
+    #[test]
+    fn test_get_receiver() {
+        let tree = utils::parse(SOURCE);
+        let start = SOURCE.find(".hit").unwrap();
+        let dot = tree
+            .root_node()
+            .descendant_for_byte_range(start, start + 1)
+            .unwrap();
+        let receiver = get_receiver(dot).unwrap();
+        assert_eq!(utils::get_node_text(&receiver, SOURCE), "t.apply()");
+    }
+
+    #[test]
+    fn test_get_type() {
+        let file = utils::parse_file(SOURCE);
+        let position = Position::new(6, 8);
+        let type_of = |segments: Vec<Segment>| {
+            get_type(
+                &segments,
+                position,
+                &file.ast_manager,
+                &file.symbol_table_manager,
+            )
+        };
+
+        assert_eq!(
+            type_of(vec![
+                name("hdr"),
+                name("mpls"),
+                Segment::Index,
+                name("label")
+            ]),
+            Some(ExprType::Sized(ConstantType::Bit(20)))
+        );
+        assert_eq!(
+            type_of(vec![name("hdr"), name("mpls")]),
+            Some(ExprType::HeaderStack("mpls_t".to_string()))
+        );
+        assert_eq!(
+            type_of(vec![name("t"), name("apply"), Segment::Call]),
+            Some(ExprType::ApplyResult)
+        );
+    }
+}
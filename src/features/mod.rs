@@ -1,6 +1,20 @@
+pub mod call_hierarchy;
+pub mod code_actions;
 pub mod completion;
+pub mod constant_folding;
+pub mod control_graph;
 pub mod diagnostics;
+pub mod folding;
+pub mod formatting;
 pub mod goto;
+pub mod highlight;
 pub mod hover;
+pub mod inlay_hints;
+pub mod members;
+pub mod preprocessor;
+pub mod refactoring;
 pub mod rename;
+pub mod selection;
 pub mod semantic_tokens;
+pub mod signature_help;
+pub mod snippets;
@@ -0,0 +1,758 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use regex::Regex;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticSeverity, DiagnosticTag, Location, NumberOrString, Position, Range, Url,
+};
+
+use crate::features::constant_folding;
+
+lazy_static! {
+    static ref DIRECTIVE: Regex = Regex::new(r"^\s*#\s*(\w+)\s*(.*)$").unwrap();
+    static ref DEFINE: Regex = Regex::new(r"^([A-Za-z_]\w*)(\(([^)]*)\))?\s*(.*)$").unwrap();
+    static ref INCLUDE: Regex = Regex::new(r#"^(?:"([^"]+)"|<([^>]+)>)"#).unwrap();
+    static ref DEFINED: Regex =
+        Regex::new(r"\bdefined\s*(?:\(\s*([A-Za-z_]\w*)\s*\)|([A-Za-z_]\w*))").unwrap();
+    static ref IDENTIFIER: Regex = Regex::new(r"\b[A-Za-z_]\w*").unwrap();
+}
+
+// Guards against include cycles the visited set can't catch, like a file
+// including itself through different paths.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Macro {
+    pub name: String,
+    pub params: Option<Vec<String>>,
+    pub value: String,
+    // `None` for macros defined in the settings rather than in a file.
+    pub location: Option<Location>,
+}
+
+impl Macro {
+    pub fn get_declaration(&self) -> String {
+        match &self.params {
+            Some(params) => format!(
+                "#define {}({}) {}",
+                self.name,
+                params.join(", "),
+                self.value
+            ),
+            None => format!("#define {} {}", self.name, self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MacroUsage {
+    location: Location,
+    definition: Macro,
+}
+
+#[derive(Debug, Clone)]
+struct Conditional {
+    parent_active: bool,
+    taken: bool,
+    active: bool,
+    branch_start: usize,
+}
+
+#[derive(Debug, Clone)]
+struct CachedInclude {
+    modified: SystemTime,
+    uri: Url,
+    source_code: Arc<str>,
+}
+
+/// The included files read by a document's preprocessor, kept until they are
+/// modified so that edits of the document don't read them again.
+#[derive(Debug, Clone, Default)]
+pub struct IncludeCache {
+    files: HashMap<PathBuf, CachedInclude>,
+}
+
+impl IncludeCache {
+    fn read(&mut self, path: &Path) -> Option<(Url, Arc<str>)> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()?;
+        if let Some(cached) = self.files.get(path) {
+            if cached.modified == modified {
+                return Some((cached.uri.clone(), cached.source_code.clone()));
+            }
+        }
+
+        let cached = CachedInclude {
+            modified,
+            uri: Url::from_file_path(path).ok()?,
+            source_code: fs::read_to_string(path).ok()?.into(),
+        };
+        let result = (cached.uri.clone(), cached.source_code.clone());
+        self.files.insert(path.to_path_buf(), cached);
+        Some(result)
+    }
+}
+
+/// A model of the C preprocessor as run before the P4 compiler. Only the
+/// directives are interpreted, the document itself isn't rewritten.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    macros: HashMap<String, Macro>,
+    definitions: Vec<Macro>,
+    usages: Vec<MacroUsage>,
+    inactive_regions: Vec<Range>,
+    included_files: HashSet<PathBuf>,
+    // The identifiers of the included files, which aren't parsed.
+    included_names: HashSet<String>,
+    has_unresolved_includes: bool,
+}
+
+impl Preprocessor {
+    pub fn new(
+        uri: &Url,
+        source_code: &str,
+        include_paths: &[PathBuf],
+        defines: &[(String, String)],
+        cache: &mut IncludeCache,
+    ) -> Preprocessor {
+        let mut scanner = Scanner {
+            include_paths,
+            cache,
+            visited: HashSet::new(),
+            preprocessor: Preprocessor::default(),
+        };
+
+        for (name, value) in defines {
+            scanner.preprocessor.macros.insert(
+                name.clone(),
+                Macro {
+                    name: name.clone(),
+                    params: None,
+                    value: value.clone(),
+                    location: None,
+                },
+            );
+        }
+
+        let directory = uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.parent().map(Path::to_path_buf));
+        scanner.scan(uri, source_code, directory.as_deref(), true, 0);
+
+        scanner.preprocessor.included_files = scanner.visited;
+        scanner.preprocessor
+    }
+
+    /// Whether the file is included by the document, directly or not.
+    pub fn is_included(&self, path: &Path) -> bool {
+        let path = path.canonicalize().unwrap_or(path.to_path_buf());
+        self.included_files.contains(&path)
+    }
+
+    /// Whether a file named `file_name` may be included by the document. An
+    /// include that couldn't be resolved may include it too.
+    pub fn may_include(&self, file_name: &str) -> bool {
+        self.has_unresolved_includes
+            || self
+                .included_files
+                .iter()
+                .any(|path| path.file_name().is_some_and(|name| name == file_name))
+    }
+
+    /// Whether `name` may be declared by an included file: it is written in
+    /// one of them, or an include couldn't be resolved.
+    pub fn may_declare(&self, name: &str) -> bool {
+        self.has_unresolved_includes || self.included_names.contains(name)
+    }
+
+    /// The macro defined or used at `position` in the document.
+    pub fn get_macro_at(&self, position: Position) -> Option<&Macro> {
+        let contains = |range: &Range| position >= range.start && position <= range.end;
+
+        self.usages
+            .iter()
+            .find(|usage| contains(&usage.location.range))
+            .map(|usage| &usage.definition)
+            .or_else(|| {
+                self.definitions.iter().find(|definition| {
+                    definition
+                        .location
+                        .as_ref()
+                        .is_some_and(|location| contains(&location.range))
+                })
+            })
+    }
+
+    /// Locations of the usages of `name` in the document, and optionally of
+    /// its definitions.
+    pub fn get_references(&self, name: &str, include_declaration: bool) -> Vec<Location> {
+        let mut locations = vec![];
+
+        if include_declaration {
+            locations.extend(
+                self.definitions
+                    .iter()
+                    .chain(self.macros.get(name))
+                    .filter(|definition| definition.name == name)
+                    .filter_map(|definition| definition.location.clone()),
+            );
+            locations.dedup();
+        }
+        locations.extend(
+            self.usages
+                .iter()
+                .filter(|usage| usage.definition.name == name)
+                .map(|usage| usage.location.clone()),
+        );
+
+        locations
+    }
+
+    /// The macros used in the document, with where they are used.
+    pub fn get_usages(&self) -> impl Iterator<Item = (Range, &Macro)> {
+        self.usages
+            .iter()
+            .map(|usage| (usage.location.range, &usage.definition))
+    }
+
+    /// Recursively replaces the macros in `text` by their value.
+    pub fn expand(&self, text: &str) -> String {
+        expand_text(&self.macros, text, &mut vec![])
+    }
+
+    pub fn get_diagnostics(&self) -> Vec<Diagnostic> {
+        self.inactive_regions
+            .iter()
+            .map(|range| {
+                Diagnostic::new(
+                    *range,
+                    Some(DiagnosticSeverity::HINT),
+                    Some(NumberOrString::String("inactive-region".to_string())),
+                    Some("preprocessor".to_string()),
+                    "Code is inactive due to preprocessor conditionals.".to_string(),
+                    None,
+                    Some(vec![DiagnosticTag::UNNECESSARY]),
+                )
+            })
+            .collect()
+    }
+}
+
+struct Scanner<'a> {
+    include_paths: &'a [PathBuf],
+    cache: &'a mut IncludeCache,
+    visited: HashSet<PathBuf>,
+    preprocessor: Preprocessor,
+}
+
+impl Scanner<'_> {
+    /// Interprets the directives of `source_code`. Usages and inactive regions
+    /// are only recorded for the main document, not for included files.
+    fn scan(
+        &mut self,
+        uri: &Url,
+        source_code: &str,
+        directory: Option<&Path>,
+        is_main: bool,
+        depth: usize,
+    ) {
+        if !is_main {
+            self.preprocessor.included_names.extend(
+                IDENTIFIER
+                    .find_iter(source_code)
+                    .map(|found| found.as_str().to_string()),
+            );
+        }
+
+        let lines: Vec<&str> = source_code.split('\n').collect();
+        let mut conditionals: Vec<Conditional> = vec![];
+        let mut in_comment = false;
+
+        let mut index = 0;
+        while index < lines.len() {
+            let start = index;
+            let mut line = lines[index].trim_end_matches('\r').to_string();
+            // Directives can be continued on the next line with a backslash.
+            while line.ends_with('\\')
+                && index + 1 < lines.len()
+                && DIRECTIVE.is_match(lines[start])
+            {
+                line.pop();
+                index += 1;
+                line.push_str(lines[index].trim_end_matches('\r'));
+            }
+            index += 1;
+            let line = strip_comments(&line, &mut in_comment);
+
+            let active = conditionals.last().is_none_or(|c| c.active);
+            let captures = match DIRECTIVE.captures(&line) {
+                Some(captures) => captures,
+                None => {
+                    if active && is_main {
+                        self.record_usages(uri, &line, start, 0);
+                    }
+                    continue;
+                }
+            };
+            let directive = captures[1].to_string();
+            let argument = captures[2].trim().to_string();
+            let argument_column = captures.get(2).unwrap().start();
+
+            match directive.as_str() {
+                "if" | "ifdef" | "ifndef" => {
+                    let condition = active && {
+                        if is_main {
+                            self.record_usages(uri, &line, start, argument_column);
+                        }
+                        match directive.as_str() {
+                            "ifdef" => self.preprocessor.macros.contains_key(&argument),
+                            "ifndef" => !self.preprocessor.macros.contains_key(&argument),
+                            _ => self.evaluate(&argument),
+                        }
+                    };
+                    conditionals.push(Conditional {
+                        parent_active: active,
+                        taken: condition,
+                        active: condition,
+                        branch_start: index,
+                    });
+                }
+                "elif" | "else" | "endif" => {
+                    let conditional = match conditionals.last_mut() {
+                        Some(conditional) => conditional,
+                        None => continue,
+                    };
+                    if conditional.parent_active && !conditional.active && is_main {
+                        push_region(
+                            &mut self.preprocessor.inactive_regions,
+                            &lines,
+                            conditional.branch_start,
+                            start,
+                        );
+                    }
+
+                    match directive.as_str() {
+                        "endif" => {
+                            conditionals.pop();
+                        }
+                        _ => {
+                            let can_take = conditional.parent_active && !conditional.taken;
+                            let condition = match directive.as_str() {
+                                "else" => can_take,
+                                _ => {
+                                    can_take && {
+                                        if is_main {
+                                            self.record_usages(uri, &line, start, argument_column);
+                                        }
+                                        self.evaluate(&argument)
+                                    }
+                                }
+                            };
+                            conditional.taken |= condition;
+                            conditional.active = condition;
+                            conditional.branch_start = index;
+                        }
+                    }
+                }
+                "define" if active => {
+                    if let Some(definition) = parse_define(uri, &line, start, argument_column) {
+                        if is_main {
+                            let body_column = definition
+                                .location
+                                .as_ref()
+                                .map_or(0, |location| location.range.end.character as usize);
+                            self.record_usages(uri, &line, start, body_column);
+                            self.preprocessor.definitions.push(definition.clone());
+                        }
+                        self.preprocessor
+                            .macros
+                            .insert(definition.name.clone(), definition);
+                    }
+                }
+                "undef" if active => {
+                    if is_main {
+                        self.record_usages(uri, &line, start, argument_column);
+                    }
+                    self.preprocessor.macros.remove(&argument);
+                }
+                "include" if active && depth < MAX_INCLUDE_DEPTH => {
+                    self.include(&argument, directory, depth);
+                }
+                _ => {}
+            }
+        }
+
+        // Unterminated conditionals extend to the end of the document.
+        if is_main {
+            for conditional in conditionals {
+                if conditional.parent_active && !conditional.active {
+                    push_region(
+                        &mut self.preprocessor.inactive_regions,
+                        &lines,
+                        conditional.branch_start,
+                        lines.len(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn include(&mut self, argument: &str, directory: Option<&Path>, depth: usize) {
+        let captures = match INCLUDE.captures(argument) {
+            Some(captures) => captures,
+            None => return,
+        };
+
+        // Quoted includes are looked up next to the including file first.
+        let (name, mut candidates) = match (captures.get(1), captures.get(2)) {
+            (Some(name), _) => (
+                name.as_str(),
+                directory.into_iter().map(Path::to_path_buf).collect(),
+            ),
+            (_, Some(name)) => (name.as_str(), vec![]),
+            _ => return,
+        };
+        candidates.extend(self.include_paths.iter().cloned());
+        candidates.extend(directory.map(Path::to_path_buf));
+
+        let path = match candidates
+            .into_iter()
+            .map(|candidate| candidate.join(name))
+            .find(|path| path.is_file())
+        {
+            Some(path) => path,
+            None => {
+                self.preprocessor.has_unresolved_includes = true;
+                return;
+            }
+        };
+        let path = path.canonicalize().unwrap_or(path);
+        if !self.visited.insert(path.clone()) {
+            return;
+        }
+
+        let Some((uri, source_code)) = self.cache.read(&path) else {
+            return;
+        };
+        self.scan(&uri, &source_code, path.parent(), false, depth + 1);
+    }
+
+    fn evaluate(&self, condition: &str) -> bool {
+        let macros = &self.preprocessor.macros;
+        let condition = DEFINED.replace_all(condition, |captures: &regex::Captures| {
+            let name = captures.get(1).or(captures.get(2)).unwrap().as_str();
+            if macros.contains_key(name) {
+                "1"
+            } else {
+                "0"
+            }
+        });
+        let expanded = expand_text(macros, &condition, &mut vec![]);
+        // Identifiers left after expansion evaluate to 0, like in C.
+        let expanded =
+            IDENTIFIER.replace_all(&expanded, |captures: &regex::Captures| match &captures[0] {
+                "true" | "false" => captures[0].to_string(),
+                _ => "0".to_string(),
+            });
+
+        constant_folding::evaluate(&expanded, &HashMap::new())
+            .is_some_and(|constant| constant.value != 0)
+    }
+
+    fn record_usages(&mut self, uri: &Url, line: &str, line_number: usize, from: usize) {
+        for found in IDENTIFIER.find_iter(line) {
+            if found.start() < from || in_string(line, found.start()) {
+                continue;
+            }
+            if let Some(definition) = self.preprocessor.macros.get(found.as_str()) {
+                self.preprocessor.usages.push(MacroUsage {
+                    location: Location::new(
+                        uri.clone(),
+                        line_range(line_number, found.start(), found.end()),
+                    ),
+                    definition: definition.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn parse_define(uri: &Url, line: &str, line_number: usize, column: usize) -> Option<Macro> {
+    let argument = line[column..].trim_end();
+    let captures = DEFINE.captures(argument)?;
+    let name = captures.get(1)?;
+
+    Some(Macro {
+        name: name.as_str().to_string(),
+        params: captures.get(3).map(|params| {
+            params
+                .as_str()
+                .split(',')
+                .map(|param| param.trim().to_string())
+                .filter(|param| !param.is_empty())
+                .collect()
+        }),
+        value: captures[4].trim().to_string(),
+        location: Some(Location::new(
+            uri.clone(),
+            line_range(line_number, column + name.start(), column + name.end()),
+        )),
+    })
+}
+
+fn expand_text(macros: &HashMap<String, Macro>, text: &str, expanding: &mut Vec<String>) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+
+    while let Some(found) = IDENTIFIER.find(rest) {
+        result.push_str(&rest[..found.start()]);
+        let name = found.as_str();
+        let after = &rest[found.end()..];
+        rest = after;
+
+        // A macro isn't expanded again inside its own expansion.
+        let definition = match macros.get(name) {
+            Some(definition) if !expanding.iter().any(|n| n == name) => definition,
+            _ => {
+                result.push_str(name);
+                continue;
+            }
+        };
+
+        let body = match &definition.params {
+            None => definition.value.clone(),
+            Some(params) => match parse_call_args(after) {
+                Some((args, consumed)) => {
+                    rest = &after[consumed..];
+                    substitute(&definition.value, params, &args)
+                }
+                // A function-like macro without arguments isn't expanded.
+                None => {
+                    result.push_str(name);
+                    continue;
+                }
+            },
+        };
+
+        expanding.push(name.to_string());
+        result.push_str(&expand_text(macros, &body, expanding));
+        expanding.pop();
+    }
+    result.push_str(rest);
+
+    result
+}
+
+fn parse_call_args(text: &str) -> Option<(Vec<String>, usize)> {
+    let offset = text.len() - text.trim_start().len();
+    if !text[offset..].starts_with('(') {
+        return None;
+    }
+
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+    for (index, c) in text[offset..].char_indices() {
+        match c {
+            '(' => {
+                depth += 1;
+                if depth == 1 {
+                    continue;
+                }
+            }
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    if !current.trim().is_empty() || !args.is_empty() {
+                        args.push(current.trim().to_string());
+                    }
+                    return Some((args, offset + index + 1));
+                }
+            }
+            ',' if depth == 1 => {
+                args.push(std::mem::take(&mut current).trim().to_string());
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+
+    None
+}
+
+fn substitute(body: &str, params: &[String], args: &[String]) -> String {
+    IDENTIFIER
+        .replace_all(body, |captures: &regex::Captures| {
+            match params.iter().position(|param| param == &captures[0]) {
+                Some(index) => args.get(index).cloned().unwrap_or_default(),
+                None => captures[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+fn push_region(regions: &mut Vec<Range>, lines: &[&str], start: usize, end: usize) {
+    // `end` is the line of the directive closing the region, which stays active.
+    if start >= end {
+        return;
+    }
+    let last_line = end - 1;
+    regions.push(Range::new(
+        Position::new(start as u32, 0),
+        Position::new(
+            last_line as u32,
+            lines[last_line].trim_end_matches('\r').len() as u32,
+        ),
+    ));
+}
+
+fn line_range(line: usize, start: usize, end: usize) -> Range {
+    Range::new(
+        Position::new(line as u32, start as u32),
+        Position::new(line as u32, end as u32),
+    )
+}
+
+/// `line` without its comments: line comments are cut and block comments
+/// blanked out, keeping the columns of the code. `in_comment` carries whether
+/// a block comment is left open from one line to the next.
+fn strip_comments(line: &str, in_comment: &mut bool) -> String {
+    let mut code = String::with_capacity(line.len());
+    let mut in_string = false;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        if *in_comment {
+            if c == '*' && next == Some('/') {
+                chars.next();
+                *in_comment = false;
+                code.push_str("  ");
+            } else {
+                code.push_str(&" ".repeat(c.len_utf8()));
+            }
+            continue;
+        }
+
+        match (c, next) {
+            ('"', _) => in_string = !in_string,
+            ('/', Some('/')) if !in_string => {
+                code.truncate(index);
+                return code;
+            }
+            ('/', Some('*')) if !in_string => {
+                chars.next();
+                *in_comment = true;
+                code.push_str("  ");
+                continue;
+            }
+            _ => {}
+        }
+        code.push(c);
+    }
+
+    code
+}
+
+fn in_string(line: &str, index: usize) -> bool {
+    line[..index].matches('"').count() % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, Url};
+
+    use super::{IncludeCache, Preprocessor};
+
+    fn preprocess(source_code: &str) -> Preprocessor {
+        let uri = Url::parse("file:///tmp/test.p4").unwrap();
+        Preprocessor::new(
+            &uri,
+            source_code,
+            &[],
+            &[("TOFINO".to_string(), "1".to_string())],
+            &mut IncludeCache::default(),
+        )
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let preprocessor = preprocess(
+            "#define WIDTH 16\n#if WIDTH > 8 && defined(TOFINO)\na\n#else\nb\n#endif\n#ifndef TOFINO\nc\n#endif",
+        );
+
+        assert_eq!(
+            preprocessor.inactive_regions,
+            vec![
+                Range::new(Position::new(4, 0), Position::new(4, 1)),
+                Range::new(Position::new(7, 0), Position::new(7, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_usages_and_expansion() {
+        let preprocessor = preprocess(
+            "#define BASE 0x800\n#define TYPE(offset) (BASE + offset)\nconst bit<16> T = TYPE(1);",
+        );
+
+        let definition = preprocessor.get_macro_at(Position::new(2, 19)).unwrap();
+        assert_eq!(definition.name, "TYPE");
+        assert_eq!(preprocessor.expand("TYPE(1)"), "(0x800 + 1)");
+        assert_eq!(preprocessor.get_references("BASE", true).len(), 2);
+    }
+
+    #[test]
+    fn test_block_comments() {
+        let preprocessor = preprocess(
+            "/* disabled:\n#define A 1\n*/ #define B 2 /* two */\n#define C /* ignored\n#define D\n*/ 3",
+        );
+
+        let names: Vec<&str> = preprocessor
+            .definitions
+            .iter()
+            .map(|definition| definition.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["B", "C"]);
+        assert_eq!(preprocessor.expand("B"), "2");
+    }
+
+    #[test]
+    fn test_include_cache() {
+        let directory = std::env::temp_dir().join(format!("p4-include-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let header = directory.join("header.p4");
+        std::fs::write(&header, "#define WIDTH 8\n").unwrap();
+
+        let uri = Url::from_file_path(directory.join("main.p4")).unwrap();
+        let source_code = "#include \"header.p4\"\nconst bit<8> W = WIDTH;";
+        let mut cache = IncludeCache::default();
+        let preprocessor = Preprocessor::new(&uri, source_code, &[], &[], &mut cache);
+        assert_eq!(preprocessor.expand("WIDTH"), "8");
+
+        // Usages in the document are reported in the document, not the header.
+        let references = preprocessor.get_references("WIDTH", false);
+        assert_eq!(references.len(), 1);
+        assert_eq!(references[0].uri, uri);
+
+        // The header is read again once modified.
+        std::fs::write(&header, "#define WIDTH 16\n").unwrap();
+        let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&header)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        let preprocessor = Preprocessor::new(&uri, source_code, &[], &[], &mut cache);
+        assert_eq!(preprocessor.expand("WIDTH"), "16");
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
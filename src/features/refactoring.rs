@@ -0,0 +1,947 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::features::constant_folding::{self, Constant};
+use crate::features::preprocessor::Preprocessor;
+use crate::metadata::{AstQuery, BaseType, NodeKind, SymbolTableQuery, Type, VisitNode, Visitable};
+use crate::utils;
+
+// The statements an `apply` block is made of, as far as the AST keeps them.
+const STATEMENT_KINDS: [NodeKind; 8] = [
+    NodeKind::Assignment,
+    NodeKind::DirectApplication,
+    NodeKind::Conditional,
+    NodeKind::Block,
+    NodeKind::Switch,
+    NodeKind::Return,
+    NodeKind::VariableDec,
+    NodeKind::ConstantDec,
+];
+
+struct Refactoring {
+    title: String,
+    kind: CodeActionKind,
+    edits: Vec<TextEdit>,
+}
+
+/// The refactorings available for the selection `range`. `is_included`
+/// tells whether another document includes this one.
+pub fn get_refactorings(
+    uri: &Url,
+    range: Range,
+    source_code: &str,
+    preprocessor: &Preprocessor,
+    is_included: bool,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<CodeActionOrCommand> {
+    [
+        extract_action(range, source_code, ast_query, st_query),
+        inline_constant(
+            range.start,
+            uri,
+            source_code,
+            preprocessor,
+            is_included,
+            ast_query,
+            st_query,
+        ),
+        introduce_typedef(range.start, source_code, ast_query),
+        convert_to_table(range.start, source_code, ast_query),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|refactoring| {
+        CodeActionOrCommand::CodeAction(CodeAction {
+            title: refactoring.title,
+            kind: Some(refactoring.kind),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), refactoring.edits)])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+    })
+    .collect()
+}
+
+/// Moves the statements of an `apply` block overlapping `range` into a new
+/// action of the control. The variables declared earlier in the block aren't
+/// visible from the action, so they become its parameters.
+fn extract_action(
+    range: Range,
+    source_code: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Refactoring> {
+    if range.start == range.end {
+        return None;
+    }
+
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let (_, apply) = get_apply_block(&root, range.start)?;
+    let apply_range = apply.get().range;
+    if !contains(apply_range, range.end) {
+        return None;
+    }
+
+    // The innermost list of statements around the selection comes last.
+    let descendants = apply.get_descendants();
+    let list = descendants.iter().rev().find(|node| {
+        matches!(
+            node.get().kind,
+            NodeKind::Block | NodeKind::BodyIf | NodeKind::BodyElse
+        ) && contains(node.get().range, range.start)
+            && contains(node.get().range, range.end)
+    })?;
+    let statements: Vec<VisitNode> = list
+        .get_children()
+        .into_iter()
+        .filter(|child| STATEMENT_KINDS.contains(&child.get().kind))
+        .filter(|child| {
+            let statement = child.get().range;
+            statement.end > range.start && statement.start < range.end
+        })
+        .collect();
+    let start = statements.first()?.get().range.start;
+    let end = statements.last()?.get().range.end;
+
+    // Actions can neither apply tables nor return from the control.
+    if statements.iter().any(|statement| {
+        matches!(statement.get().kind, NodeKind::Return | NodeKind::Switch)
+            || is_application(statement)
+    }) {
+        return None;
+    }
+
+    // The symbols of the scopes around the selection used by it.
+    let selection = Range::new(start, end);
+    let written = get_written_ranges(&statements);
+    let st_query = st_query.lock().unwrap();
+    let symbols = st_query.get_symbols_at_pos(end);
+    let mut parameters: Vec<(Position, String, String)> = vec![];
+    for symbol in symbols.constants.iter().chain(&symbols.variables) {
+        let usages: Vec<Range> = symbol
+            .get_usages()
+            .iter()
+            .filter(|usage| contains(selection, usage.start))
+            .copied()
+            .collect();
+        let Some(first) = usages.iter().map(|usage| usage.start).min() else {
+            continue;
+        };
+        let definition = symbol.get_definition_range();
+
+        if contains(selection, definition.start) {
+            // Declared by the extracted statements, so it can't be used after.
+            if symbol.get_usages().iter().any(|usage| usage.start > end) {
+                return None;
+            }
+        } else if contains(apply_range, definition.start) {
+            let is_written = usages
+                .iter()
+                .any(|usage| written.iter().any(|range| contains(*range, usage.start)));
+            let direction = if is_written { "inout" } else { "in" };
+            let type_name = symbol.get_type_name()?;
+            parameters.push((
+                first,
+                symbol.get_name(),
+                format!("{direction} {}", type_name.trim()),
+            ));
+        }
+    }
+    parameters.sort_by_key(|(first, _, _)| *first);
+
+    let mut name = "extracted_action".to_string();
+    let mut suffix = 1;
+    while st_query
+        .get_symbol_at_pos(name.clone(), apply_range.start)
+        .is_some()
+    {
+        suffix += 1;
+        name = format!("extracted_action{suffix}");
+    }
+
+    let apply_line = apply_range.start.line;
+    let indent = get_indent(source_code, apply_line);
+    let body = reindent(
+        get_text(source_code, Range::new(Position::new(start.line, 0), end))?,
+        &format!("{indent}{}", get_indent_unit(indent)),
+    );
+    let declaration = format!(
+        "{indent}action {name}({}) {{\n{body}\n{indent}}}\n\n",
+        parameters
+            .iter()
+            .map(|(_, parameter, type_)| format!("{type_} {parameter}"))
+            .collect::<Vec<String>>()
+            .join(", ")
+    );
+    let call = format!(
+        "{name}({});",
+        parameters
+            .iter()
+            .map(|(_, parameter, _)| parameter.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+
+    let insert_position = Position::new(apply_line, 0);
+    Some(Refactoring {
+        title: "Extract into action".to_string(),
+        kind: CodeActionKind::REFACTOR_EXTRACT,
+        edits: vec![
+            TextEdit::new(Range::new(insert_position, insert_position), declaration),
+            TextEdit::new(Range::new(start, end), call),
+        ],
+    })
+}
+
+/// Whether `statement` applies a table or another block, like `t.apply();`
+/// or `if (t.apply().hit) {}`.
+fn is_application(statement: &VisitNode) -> bool {
+    let is_apply = |path: &[(String, Range)]| path.iter().skip(1).any(|(name, _)| name == "apply");
+
+    statement
+        .get_descendants()
+        .iter()
+        .any(|node| match node.get().kind {
+            NodeKind::DirectApplication => true,
+            NodeKind::NameStatement => is_apply(&utils::get_lvalue_path(node)),
+            NodeKind::Value => utils::get_value_paths(node)
+                .iter()
+                .any(|path| is_apply(path)),
+            _ => false,
+        })
+}
+
+/// The ranges the statements may write to: the variables they assign and the
+/// arguments of their calls, which may be bound to `out` parameters.
+fn get_written_ranges(statements: &[VisitNode]) -> Vec<Range> {
+    statements
+        .iter()
+        .flat_map(|statement| statement.get_descendants())
+        .filter(|node| node.get().kind == NodeKind::Assignment)
+        .flat_map(|node| {
+            let mut ranges = vec![];
+            if node.get_value_node().is_some() {
+                ranges.extend(
+                    node.get_child_of_kind(NodeKind::NameStatement)
+                        .and_then(|name| Some(utils::get_lvalue_path(&name).first()?.1)),
+                );
+            }
+            ranges.extend(
+                node.get_child_of_kind(NodeKind::Args)
+                    .map(|args| args.get().range),
+            );
+            ranges
+        })
+        .collect()
+}
+
+/// Replaces the uses of the `const` or `#define` at `position` with its value
+/// and removes the declaration. When other documents include this one, they
+/// may use it too, so only the use at `position` is replaced.
+fn inline_constant(
+    position: Position,
+    uri: &Url,
+    source_code: &str,
+    preprocessor: &Preprocessor,
+    is_included: bool,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Refactoring> {
+    if let Some(definition) = preprocessor.get_macro_at(position) {
+        if definition.params.is_some() || definition.value.trim().is_empty() {
+            return None;
+        }
+        let value = parenthesize(definition.value.trim());
+        let usages: Vec<Range> = preprocessor
+            .get_references(&definition.name, false)
+            .into_iter()
+            .filter(|location| location.uri == *uri)
+            .map(|location| location.range)
+            .collect();
+
+        // Definitions continued over several lines are left alone.
+        let mut removal = None;
+        if let Some(location) = definition.location.as_ref().filter(|l| l.uri == *uri) {
+            let line = location.range.start.line;
+            if get_line(source_code, line)?.trim_end().ends_with('\\') {
+                return None;
+            }
+            removal = Some(Range::new(
+                Position::new(line, 0),
+                Position::new(line + 1, 0),
+            ));
+        }
+
+        return inline_usages(
+            &definition.name,
+            value,
+            &usages,
+            removal,
+            position,
+            is_included,
+        );
+    }
+
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let node = root.get_node_at_position(position)?;
+    let name = node.get().content.trim().to_string();
+
+    let st_query = st_query.lock().unwrap();
+    let symbol = st_query.get_symbol_at_pos(name.clone(), position)?;
+    let definition = symbol.get_definition_range();
+    let descendants = root.get_descendants();
+    let declaration = descendants.iter().find(|node| {
+        node.get().kind == NodeKind::ConstantDec
+            && node
+                .get_child_of_kind(NodeKind::Name)
+                .is_some_and(|name_node| name_node.get().range == definition)
+    })?;
+    let value = parenthesize(declaration.get_value_node()?.get().content.trim());
+    let removal = get_removal_range(source_code, declaration.get().range);
+
+    inline_usages(
+        &name,
+        value,
+        symbol.get_usages(),
+        Some(removal),
+        position,
+        is_included,
+    )
+}
+
+/// Replaces `usages` with `value` and removes the declaration at `removal`,
+/// or replaces only the usage at `position` when the declaration may be used
+/// by other documents.
+fn inline_usages(
+    name: &str,
+    value: String,
+    usages: &[Range],
+    removal: Option<Range>,
+    position: Position,
+    is_included: bool,
+) -> Option<Refactoring> {
+    if is_included {
+        let usage = usages.iter().find(|usage| contains(**usage, position))?;
+        return Some(Refactoring {
+            title: format!("Inline `{name}` here"),
+            kind: CodeActionKind::REFACTOR_INLINE,
+            edits: vec![TextEdit::new(*usage, value)],
+        });
+    }
+    if usages.is_empty() {
+        return None;
+    }
+
+    let mut edits: Vec<TextEdit> = usages
+        .iter()
+        .map(|usage| TextEdit::new(*usage, value.clone()))
+        .collect();
+    edits.extend(removal.map(|range| TextEdit::new(range, String::new())));
+
+    Some(Refactoring {
+        title: format!("Inline `{name}`"),
+        kind: CodeActionKind::REFACTOR_INLINE,
+        edits,
+    })
+}
+
+struct SizedDeclaration {
+    type_text: String,
+    type_range: Range,
+    meaning: String,
+    top_level_line: u32,
+}
+
+/// Names the `bit<N>` or `int<N>` type at `position` with a typedef, where it
+/// is used by declarations meaning the same thing, like `srcAddr` and
+/// `dstAddr`.
+fn introduce_typedef(
+    position: Position,
+    source_code: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Option<Refactoring> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+
+    let mut declarations: Vec<SizedDeclaration> = vec![];
+    let mut declared_names: Vec<String> = vec![];
+    for top_level in root.get_children() {
+        declared_names.extend(
+            top_level
+                .get_child_of_kind(NodeKind::Name)
+                .map(|name| name.get().content.trim().to_string()),
+        );
+
+        for node in top_level.get_descendants() {
+            if !matches!(
+                node.get().kind,
+                NodeKind::Field | NodeKind::Param | NodeKind::VariableDec | NodeKind::ConstantDec
+            ) {
+                continue;
+            }
+            let (Some(type_node), Some(name_node)) =
+                (node.get_type_node(), node.get_child_of_kind(NodeKind::Name))
+            else {
+                continue;
+            };
+            if !matches!(
+                type_node.get_type(),
+                Some(Type::Base(
+                    BaseType::SizedBit(Some(_)) | BaseType::SizedInt(Some(_))
+                ))
+            ) {
+                continue;
+            }
+
+            declarations.push(SizedDeclaration {
+                type_text: type_node.get().content.split_whitespace().collect(),
+                type_range: type_node.get().range,
+                meaning: get_meaning(name_node.get().content.trim()),
+                top_level_line: top_level.get().range.start.line,
+            });
+        }
+    }
+
+    let selected = declarations.iter().find(|declaration| {
+        contains(declaration.type_range, position) || declaration.type_range.end == position
+    })?;
+    let matches: Vec<&SizedDeclaration> = declarations
+        .iter()
+        .filter(|declaration| {
+            declaration.type_text == selected.type_text && declaration.meaning == selected.meaning
+        })
+        .collect();
+    if matches.len() < 2 {
+        return None;
+    }
+
+    let mut name = format!("{}_t", selected.meaning);
+    if declared_names.contains(&name) {
+        let width: String = selected
+            .type_text
+            .chars()
+            .filter(char::is_ascii_digit)
+            .collect();
+        name = format!("{}{width}_t", selected.meaning);
+    }
+    if declared_names.contains(&name) {
+        return None;
+    }
+
+    // Before the first declaration using it, and the comments above it.
+    let mut line = matches.iter().map(|m| m.top_level_line).min()?;
+    while line > 0
+        && get_line(source_code, line - 1).is_some_and(|text| text.trim_start().starts_with("//"))
+    {
+        line -= 1;
+    }
+    let insert_position = Position::new(line, 0);
+
+    let mut edits = vec![TextEdit::new(
+        Range::new(insert_position, insert_position),
+        format!("typedef {} {name};\n\n", selected.type_text),
+    )];
+    edits.extend(
+        matches
+            .iter()
+            .map(|declaration| TextEdit::new(declaration.type_range, name.clone())),
+    );
+
+    Some(Refactoring {
+        title: format!("Introduce typedef `{name}` for `{}`", selected.type_text),
+        kind: CodeActionKind::REFACTOR_EXTRACT,
+        edits,
+    })
+}
+
+/// The last word of a declaration's name, which tells what it holds:
+/// `addr` for `dstAddr` or `src_addr`.
+fn get_meaning(name: &str) -> String {
+    let mut words: Vec<String> = vec![];
+    let mut word = String::new();
+    let mut previous_lower = false;
+
+    for c in name.chars() {
+        if c == '_' || (c.is_ascii_uppercase() && previous_lower) {
+            words.extend((!word.is_empty()).then(|| std::mem::take(&mut word)));
+        }
+        if c != '_' {
+            word.push(c.to_ascii_lowercase());
+        }
+        previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+    }
+    words.extend((!word.is_empty()).then_some(word));
+
+    words.pop().unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Chain {
+    field: String,
+    cases: Vec<(String, String)>,
+    default: Option<String>,
+}
+
+/// Turns an `if` chain comparing a field with constants, each branch calling
+/// an action, into a table with constant entries.
+fn convert_to_table(
+    position: Position,
+    source_code: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Option<Refactoring> {
+    let ast_query = ast_query.lock().unwrap();
+    let root = ast_query.visit_root();
+    let (body, apply) = get_apply_block(&root, position)?;
+    let constants = constant_folding::get_constants(&root, position);
+
+    // Only actions bound by the control plane alone can be table entries.
+    let mut actions: HashMap<String, bool> = HashMap::from([("NoAction".to_string(), true)]);
+    for action in root
+        .get_children()
+        .into_iter()
+        .chain(body.get_children())
+        .filter(|child| child.get().kind == NodeKind::ControlAction)
+    {
+        let Some(name) = action.get_child_of_kind(NodeKind::Name) else {
+            continue;
+        };
+        let directionless = action
+            .get_child_of_kind(NodeKind::Params)
+            .map(|params| {
+                params
+                    .get_descendants()
+                    .iter()
+                    .all(|node| !matches!(node.get().kind, NodeKind::Direction(_)))
+            })
+            .unwrap_or(true);
+        actions.insert(name.get().content.trim().to_string(), directionless);
+    }
+    let context = ChainContext {
+        source_code,
+        actions: &actions,
+        constants: &constants,
+    };
+
+    let descendants = apply.get_descendants();
+    let (conditional, chain) = descendants
+        .iter()
+        .filter(|node| {
+            node.get().kind == NodeKind::Conditional && contains(node.get().range, position)
+        })
+        .find_map(|node| {
+            let mut chain = Chain::default();
+            get_chain(node, &context, &mut chain)?;
+            (chain.cases.len() >= 2).then_some((node, chain))
+        })?;
+
+    let mut table_name = format!(
+        "{}_table",
+        chain.field.rsplit('.').next().unwrap_or_default()
+    );
+    let mut suffix = 1;
+    while body.get_children().iter().any(|child| {
+        child
+            .get_child_of_kind(NodeKind::Name)
+            .is_some_and(|name| name.get().content.trim() == table_name)
+    }) {
+        suffix += 1;
+        table_name = format!(
+            "{}_table{suffix}",
+            chain.field.rsplit('.').next().unwrap_or_default()
+        );
+    }
+
+    let default = chain.default.unwrap_or("NoAction()".to_string());
+    let mut listed: Vec<String> = vec![];
+    for call in chain.cases.iter().map(|(_, call)| call).chain([&default]) {
+        let name = get_call_name(call);
+        if !listed.contains(&name) {
+            listed.push(name);
+        }
+    }
+
+    let apply_line = apply.get().range.start.line;
+    let indent = get_indent(source_code, apply_line);
+    let unit = get_indent_unit(indent);
+    let inner = format!("{indent}{unit}");
+    let item = format!("{indent}{unit}{unit}");
+
+    let mut table = format!("{indent}table {table_name} {{\n");
+    table.push_str(&format!(
+        "{inner}key = {{\n{item}{}: exact;\n{inner}}}\n",
+        chain.field
+    ));
+    table.push_str(&format!("{inner}actions = {{\n"));
+    for name in &listed {
+        table.push_str(&format!("{item}{name};\n"));
+    }
+    table.push_str(&format!("{inner}}}\n{inner}const entries = {{\n"));
+    for (key, call) in &chain.cases {
+        table.push_str(&format!("{item}{key}: {call};\n"));
+    }
+    table.push_str(&format!(
+        "{inner}}}\n{inner}default_action = {default};\n{indent}}}\n\n"
+    ));
+
+    let insert_position = Position::new(apply_line, 0);
+    Some(Refactoring {
+        title: format!("Convert to table `{table_name}`"),
+        kind: CodeActionKind::REFACTOR_REWRITE,
+        edits: vec![
+            TextEdit::new(Range::new(insert_position, insert_position), table),
+            TextEdit::new(conditional.get().range, format!("{table_name}.apply();")),
+        ],
+    })
+}
+
+struct ChainContext<'a> {
+    source_code: &'a str,
+    actions: &'a HashMap<String, bool>,
+    constants: &'a HashMap<String, Constant>,
+}
+
+impl ChainContext<'_> {
+    fn is_constant(&self, text: &str) -> bool {
+        matches!(text, "true" | "false")
+            || constant_folding::evaluate(text, self.constants).is_some()
+    }
+}
+
+/// Adds the cases of the chain starting at `conditional`, following the
+/// `else if` branches.
+fn get_chain(conditional: &VisitNode, context: &ChainContext, chain: &mut Chain) -> Option<()> {
+    let condition = conditional.get_value_node()?;
+    let (field, key) = get_comparison(&condition, context)?;
+    if chain.field.is_empty() {
+        chain.field = field;
+    } else if chain.field != field {
+        return None;
+    }
+    let body_if = conditional.get_child_of_kind(NodeKind::BodyIf)?;
+    chain.cases.push((key, get_branch_call(&body_if, context)?));
+
+    let Some(body_else) = conditional.get_child_of_kind(NodeKind::BodyElse) else {
+        return Some(());
+    };
+    let else_text = get_text(context.source_code, body_else.get().range)?.trim();
+    let else_if = body_else
+        .get_children()
+        .into_iter()
+        .find(|child| child.get().kind == NodeKind::Conditional);
+    match else_if {
+        Some(else_if) if else_text.starts_with("if") => get_chain(&else_if, context, chain),
+        _ => {
+            chain.default = Some(get_branch_call(&body_else, context)?);
+            Some(())
+        }
+    }
+}
+
+/// The field and the constant of a `field == constant` condition.
+fn get_comparison(condition: &VisitNode, context: &ChainContext) -> Option<(String, String)> {
+    // The member accesses of the condition, as written.
+    let fields: Vec<String> = utils::get_value_paths(condition)
+        .iter()
+        .filter_map(|path| {
+            let range = Range::new(path.first()?.1.start, path.last()?.1.end);
+            Some(utils::normalize(get_text(context.source_code, range)?))
+        })
+        .collect();
+    let is_field = |text: &String| fields.contains(text) && !context.is_constant(text);
+
+    let condition = condition.get().content.trim();
+    let condition = condition
+        .strip_prefix('(')
+        .and_then(|inner| inner.strip_suffix(')'))
+        .unwrap_or(condition);
+    if condition.contains("&&") || condition.contains("||") || condition.matches("==").count() != 1
+    {
+        return None;
+    }
+
+    let (left, right) = condition.split_once("==")?;
+    let (left, right) = (utils::normalize(left), utils::normalize(right));
+    if is_field(&left) && context.is_constant(&right) {
+        Some((left, right))
+    } else if is_field(&right) && context.is_constant(&left) {
+        Some((right, left))
+    } else {
+        None
+    }
+}
+
+/// The action call a branch consists of, `NoAction()` if it is empty.
+fn get_branch_call(branch: &VisitNode, context: &ChainContext) -> Option<String> {
+    // The AST drops statements like `exit`, so the text must hold nothing
+    // else than the call.
+    let text = get_text(context.source_code, branch.get().range)?.trim();
+    let text = text
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .unwrap_or(text)
+        .trim();
+    if text.is_empty() {
+        return Some("NoAction()".to_string());
+    }
+
+    let call = utils::normalize(text.strip_suffix(';')?);
+    let name = get_call_name(&call);
+    if !context.actions.get(&name).copied().unwrap_or(false) || call.contains(';') {
+        return None;
+    }
+    let arguments = call
+        .strip_prefix(&name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    if arguments
+        .split(',')
+        .filter(|argument| !argument.is_empty())
+        .all(|argument| context.is_constant(argument))
+    {
+        Some(call)
+    } else {
+        None
+    }
+}
+
+fn get_call_name(call: &str) -> String {
+    call.split('(').next().unwrap_or_default().to_string()
+}
+
+/// The body of the control around `position` and its `apply` block.
+fn get_apply_block<'a>(
+    root: &'a VisitNode<'a>,
+    position: Position,
+) -> Option<(VisitNode<'a>, VisitNode<'a>)> {
+    let (body_range, apply_range) = root
+        .get_children()
+        .iter()
+        .filter(|child| {
+            child.get().kind == NodeKind::ControlDec && contains(child.get().range, position)
+        })
+        .find_map(|control| {
+            let body = control.get_child_of_kind(NodeKind::Body)?;
+            let apply = body.get_child_of_kind(NodeKind::Block)?;
+            Some((body.get().range, apply.get().range))
+        })?;
+    if !contains(apply_range, position) {
+        return None;
+    }
+
+    let descendants = root.get_descendants();
+    let find = |kind: NodeKind, range: Range| {
+        descendants
+            .iter()
+            .find(|node| node.get().kind == kind && node.get().range == range)
+            .copied()
+    };
+    Some((
+        find(NodeKind::Body, body_range)?,
+        find(NodeKind::Block, apply_range)?,
+    ))
+}
+
+fn contains(range: Range, position: Position) -> bool {
+    range.start <= position && position <= range.end
+}
+
+fn get_text(source_code: &str, range: Range) -> Option<&str> {
+    source_code.get(
+        utils::pos_to_byte(range.start, source_code)..utils::pos_to_byte(range.end, source_code),
+    )
+}
+
+fn get_line(source_code: &str, line: u32) -> Option<&str> {
+    source_code.lines().nth(line as usize)
+}
+
+fn get_indent(source_code: &str, line: u32) -> &str {
+    let text = get_line(source_code, line).unwrap_or_default();
+    &text[..text.len() - text.trim_start().len()]
+}
+
+fn get_indent_unit(indent: &str) -> &'static str {
+    if indent.contains('\t') {
+        "\t"
+    } else {
+        "    "
+    }
+}
+
+/// `text` with the indentation of its first line replaced by `indent` on
+/// every line.
+fn reindent(text: &str, indent: &str) -> String {
+    let first = text.lines().next().unwrap_or_default();
+    let prefix = &first[..first.len() - first.trim_start().len()];
+
+    text.lines()
+        .map(|line| match line.strip_prefix(prefix) {
+            _ if line.trim().is_empty() => String::new(),
+            Some(rest) => format!("{indent}{rest}"),
+            None => format!("{indent}{}", line.trim_start()),
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The range removing the declaration at `range`, with its lines if it is
+/// alone on them.
+fn get_removal_range(source_code: &str, range: Range) -> Range {
+    let before = get_line(source_code, range.start.line)
+        .and_then(|line| line.get(..range.start.character as usize))
+        .unwrap_or_default();
+    let after = get_line(source_code, range.end.line)
+        .and_then(|line| line.get(range.end.character as usize..))
+        .unwrap_or_default();
+
+    if before.trim().is_empty() && after.trim().is_empty() {
+        Range::new(
+            Position::new(range.start.line, 0),
+            Position::new(range.end.line + 1, 0),
+        )
+    } else {
+        range
+    }
+}
+
+fn parenthesize(value: &str) -> String {
+    let is_name = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if value.split('.').all(is_name) {
+        value.to_string()
+    } else {
+        format!("({value})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range, Url};
+
+    use super::{
+        convert_to_table, extract_action, get_meaning, inline_constant, introduce_typedef,
+        parenthesize, reindent,
+    };
+    use crate::utils;
+
+    #[test]
+    fn test_extract_action() {
+        let source = "control c(inout bit<8> x) {\n    apply {\n        bit<8> y = x;\n        y = y + 1;\n        x = y;\n    }\n}\n";
+        let file = utils::parse_file(source);
+        let range = Range::new(Position::new(3, 8), Position::new(3, 18));
+
+        let refactoring =
+            extract_action(range, source, &file.ast_manager, &file.symbol_table_manager).unwrap();
+        assert_eq!(
+            utils::apply_edits(source, &refactoring.edits),
+            "control c(inout bit<8> x) {\n    action extracted_action(inout bit<8> y) {\n        y = y + 1;\n    }\n\n    apply {\n        bit<8> y = x;\n        extracted_action(y);\n        x = y;\n    }\n}\n"
+        );
+
+        // Tables can't be applied from an action.
+        let source = "control c(inout bit<8> x) {\n    table t {\n        actions = {}\n    }\n    apply {\n        t.apply();\n    }\n}\n";
+        let file = utils::parse_file(source);
+        let range = Range::new(Position::new(5, 8), Position::new(5, 18));
+        assert!(
+            extract_action(range, source, &file.ast_manager, &file.symbol_table_manager).is_none()
+        );
+    }
+
+    #[test]
+    fn test_inline_constant() {
+        let source = "const bit<8> LIMIT = 1 << 4;\ncontrol c(inout bit<8> x) {\n    apply {\n        x = LIMIT;\n        x = x + LIMIT;\n    }\n}\n";
+        let file = utils::parse_file(source);
+        let uri = Url::parse("file:///test.p4").unwrap();
+        let inline = |position, is_included| {
+            inline_constant(
+                position,
+                &uri,
+                source,
+                &file.preprocessor,
+                is_included,
+                &file.ast_manager,
+                &file.symbol_table_manager,
+            )
+        };
+
+        let refactoring = inline(Position::new(3, 12), false).unwrap();
+        assert_eq!(refactoring.title, "Inline `LIMIT`");
+        assert_eq!(
+            utils::apply_edits(source, &refactoring.edits),
+            "control c(inout bit<8> x) {\n    apply {\n        x = (1 << 4);\n        x = x + (1 << 4);\n    }\n}\n"
+        );
+
+        // Documents including this one may use the constant too.
+        let refactoring = inline(Position::new(4, 18), true).unwrap();
+        assert_eq!(refactoring.title, "Inline `LIMIT` here");
+        assert_eq!(
+            utils::apply_edits(source, &refactoring.edits),
+            "const bit<8> LIMIT = 1 << 4;\ncontrol c(inout bit<8> x) {\n    apply {\n        x = LIMIT;\n        x = x + (1 << 4);\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_introduce_typedef() {
+        let source = "header ethernet_t {\n    bit<48> dstAddr;\n    bit<48> srcAddr;\n    bit<16> etherType;\n}\n";
+        let file = utils::parse_file(source);
+
+        let refactoring =
+            introduce_typedef(Position::new(1, 6), source, &file.ast_manager).unwrap();
+        assert_eq!(
+            refactoring.title,
+            "Introduce typedef `addr_t` for `bit<48>`"
+        );
+        assert_eq!(
+            utils::apply_edits(source, &refactoring.edits),
+            "typedef bit<48> addr_t;\n\nheader ethernet_t {\n    addr_t dstAddr;\n    addr_t srcAddr;\n    bit<16> etherType;\n}\n"
+        );
+        assert!(introduce_typedef(Position::new(3, 6), source, &file.ast_manager).is_none());
+    }
+
+    #[test]
+    fn test_convert_to_table() {
+        let source = "control c(inout bit<16> t) {\n    action a() {}\n    action b(bit<8> v) {}\n    apply {\n        if (t == 1) {\n            a();\n        } else if (t == 2) {\n            b(3);\n        }\n    }\n}\n";
+        let file = utils::parse_file(source);
+
+        let refactoring = convert_to_table(Position::new(4, 8), source, &file.ast_manager).unwrap();
+        assert_eq!(refactoring.title, "Convert to table `t_table`");
+        assert_eq!(
+            utils::apply_edits(source, &refactoring.edits),
+            "control c(inout bit<16> t) {\n    action a() {}\n    action b(bit<8> v) {}\n    table t_table {\n        key = {\n            t: exact;\n        }\n        actions = {\n            a;\n            b;\n            NoAction;\n        }\n        const entries = {\n            1: a();\n            2: b(3);\n        }\n        default_action = NoAction();\n    }\n\n    apply {\n        t_table.apply();\n    }\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_get_meaning() {
+        assert_eq!(get_meaning("dstAddr"), "addr");
+        assert_eq!(get_meaning("src_addr"), "addr");
+        assert_eq!(get_meaning("egress_spec"), "spec");
+        assert_eq!(get_meaning("ttl"), "ttl");
+        assert_eq!(get_meaning("srcIPv4"), "ipv4");
+    }
+
+    #[test]
+    fn test_reindent() {
+        let text = "        meta.a = 1;\n        if (x) {\n            meta.b = 2;\n        }";
+        assert_eq!(
+            reindent(text, "\t\t"),
+            "\t\tmeta.a = 1;\n\t\tif (x) {\n\t\t    meta.b = 2;\n\t\t}"
+        );
+    }
+
+    #[test]
+    fn test_parenthesize() {
+        assert_eq!(parenthesize("0x0800"), "0x0800");
+        assert_eq!(parenthesize("hdr.ipv4.ttl"), "hdr.ipv4.ttl");
+        assert_eq!(parenthesize("1 << 4"), "(1 << 4)");
+    }
+}
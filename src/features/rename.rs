@@ -1,55 +1,304 @@
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
+use std::sync::{Arc, Mutex};
 
-use crate::metadata::{
-    AstQuery, Symbol, SymbolTableEdit, SymbolTableEditor, SymbolTableQuery, Visitable,
-};
-use tower_lsp::lsp_types::{Position, TextEdit, Url, WorkspaceEdit};
+use regex::Regex;
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
 
-pub fn rename(
-    ast_query: &Arc<Mutex<impl AstQuery>>,
-    symbol_table_query: &Arc<Mutex<impl SymbolTableQuery>>,
-    symbol_table_editor: &Arc<Mutex<impl SymbolTableEditor>>,
-    uri: Url,
-    new_name: String,
+use crate::metadata::{AstQuery, NodeKind, Symbol, SymbolTableQuery, VisitNode, Visitable};
+
+lazy_static! {
+    static ref IDENTIFIER: Regex = Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+}
+
+const KEYWORDS: [&str; 40] = [
+    "abstract",
+    "action",
+    "actions",
+    "apply",
+    "const",
+    "control",
+    "default",
+    "else",
+    "entries",
+    "enum",
+    "error",
+    "exit",
+    "extern",
+    "false",
+    "header",
+    "header_union",
+    "if",
+    "in",
+    "inout",
+    "key",
+    "list",
+    "match_kind",
+    "out",
+    "package",
+    "parser",
+    "priority",
+    "return",
+    "select",
+    "state",
+    "struct",
+    "switch",
+    "table",
+    "this",
+    "transition",
+    "true",
+    "tuple",
+    "type",
+    "typedef",
+    "value_set",
+    "_",
+];
+const BASE_TYPES: [&str; 6] = ["bit", "int", "varbit", "bool", "string", "void"];
+// Declared by core.p4 or built into the language.
+const BUILTINS: [&str; 31] = [
+    "NoAction",
+    "packet_in",
+    "packet_out",
+    "exact",
+    "ternary",
+    "lpm",
+    "accept",
+    "reject",
+    "verify",
+    "static_assert",
+    "NoError",
+    "PacketTooShort",
+    "NoMatch",
+    "StackOutOfBounds",
+    "HeaderTooShort",
+    "ParserTimeout",
+    "ParserInvalidArgument",
+    "extract",
+    "lookahead",
+    "advance",
+    "length",
+    "emit",
+    "isValid",
+    "setValid",
+    "setInvalid",
+    "push_front",
+    "pop_front",
+    "lastIndex",
+    "hit",
+    "miss",
+    "action_run",
+];
+
+/// Why `name` can't be renamed or used as a new name, if it is reserved.
+fn get_reserved_reason(name: &str) -> Option<String> {
+    if KEYWORDS.contains(&name) {
+        Some(format!("`{name}` is a keyword."))
+    } else if BASE_TYPES.contains(&name) {
+        Some(format!("`{name}` is a base type."))
+    } else if BUILTINS.contains(&name) {
+        Some(format!("`{name}` is built into P4."))
+    } else {
+        None
+    }
+}
+
+/// The name at `position` and its range, if it can be renamed.
+pub fn get_renameable_name(
     position: Position,
-) -> Option<WorkspaceEdit> {
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+) -> Result<Option<(String, Range)>, String> {
     let ast_query = ast_query.lock().unwrap();
     let root_visit = ast_query.visit_root();
-    let node = root_visit.get_node_at_position(position)?;
-    let previous_name = &node.get().content;
+    let Some(node) = root_visit.get_node_at_position(position) else {
+        return Ok(None);
+    };
+
+    let name = node.get().content.trim();
+    // Base types like `bit<8>` are named by what precedes the `<`.
+    let word = name.split('<').next().unwrap_or_default().trim();
+    if let Some(reason) = get_reserved_reason(word) {
+        return Err(format!("Can't rename: {reason}"));
+    }
+    if !IDENTIFIER.is_match(name) || is_keyword_node(&node) {
+        return Ok(None);
+    }
+
+    Ok(Some((name.to_string(), node.get().range)))
+}
 
-    debug!("previous name: {}", previous_name);
+fn is_keyword_node(node: &VisitNode) -> bool {
+    matches!(
+        node.get().kind,
+        NodeKind::KeyWord | NodeKind::TableKw | NodeKind::Direction(_)
+    )
+}
 
-    let symbol = {
-        let query = symbol_table_query.lock().unwrap();
-        query
-            .get_symbol_at_pos(previous_name.clone(), position)?
-            .clone()
-    };
+/// Checks `new_name` is an identifier free to use.
+pub fn validate_name(new_name: &str) -> Result<(), String> {
+    if !IDENTIFIER.is_match(new_name) {
+        return Err(format!("`{new_name}` is not a valid identifier."));
+    }
+    if let Some(reason) = get_reserved_reason(new_name) {
+        return Err(reason);
+    }
 
-    let mut editor = symbol_table_editor.lock().unwrap();
-    editor.new_edit(SymbolTableEdit::Rename {
-        symbol_id: symbol.get_id(),
-        new_name: new_name.clone(),
-    });
+    Ok(())
+}
 
-    Some(WorkspaceEdit::new(build_changes(uri, &symbol, new_name)))
+/// The symbol `name` visible at `position`.
+pub fn get_symbol(
+    name: &str,
+    position: Position,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Symbol> {
+    st_query
+        .lock()
+        .unwrap()
+        .get_symbol_at_pos(name.to_string(), position)
+        .cloned()
 }
 
-fn build_changes(uri: Url, symbol: &Symbol, new_name: String) -> HashMap<Url, Vec<TextEdit>> {
-    let mut edits: Vec<TextEdit> = Vec::new();
+/// The top-level declaration `name`, which other documents can use.
+pub fn get_top_level_symbol(
+    name: &str,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<Symbol> {
+    st_query
+        .lock()
+        .unwrap()
+        .get_top_level_symbols()?
+        .find(name)
+        .cloned()
+}
 
-    edits.push(TextEdit::new(
-        symbol.get_definition_range(),
-        new_name.clone(),
-    ));
+/// The edits renaming `symbol` in the document declaring it, or an error if
+/// `new_name` is already visible where the symbol is declared or used.
+pub fn rename(
+    symbol: &Symbol,
+    new_name: &str,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Result<Vec<TextEdit>, String> {
+    let ranges: Vec<Range> = std::iter::once(symbol.get_definition_range())
+        .chain(symbol.get_usages().iter().copied())
+        .collect();
 
-    for range in symbol.get_usages() {
-        edits.push(TextEdit::new(*range, new_name.clone()));
+    rename_ranges(&ranges, new_name, st_query)
+}
+
+/// The edits renaming the uses of `name`, declared in another document.
+pub fn rename_external(
+    name: &str,
+    new_name: &str,
+    source_code: &str,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Result<Vec<TextEdit>, String> {
+    let ranges: Vec<Range> = st_query
+        .lock()
+        .unwrap()
+        .get_undefined_ranges()
+        .into_iter()
+        .filter(|range| get_text(source_code, range) == Some(name))
+        .collect();
+
+    rename_ranges(&ranges, new_name, st_query)
+}
+
+fn rename_ranges(
+    ranges: &[Range],
+    new_name: &str,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Result<Vec<TextEdit>, String> {
+    let st_query = st_query.lock().unwrap();
+    for range in ranges {
+        if st_query
+            .get_symbol_at_pos(new_name.to_string(), range.start)
+            .is_some()
+        {
+            return Err(format!(
+                "`{new_name}` is already visible at line {}.",
+                range.start.line + 1
+            ));
+        }
+    }
+
+    Ok(ranges
+        .iter()
+        .map(|range| TextEdit::new(*range, new_name.to_string()))
+        .collect())
+}
+
+fn get_text<'a>(source_code: &'a str, range: &Range) -> Option<&'a str> {
+    if range.start.line != range.end.line {
+        return None;
     }
+    let line = source_code.lines().nth(range.start.line as usize)?;
+    line.get(range.start.character as usize..range.end.character as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::{get_renameable_name, get_symbol, rename, validate_name};
+    use crate::utils;
 
-    HashMap::from([(uri, edits)])
+    const SOURCE: &str = "extern Checksum {
+    Checksum();
+}
+const bit<8> LIMIT = 8;
+control C(inout bit<8> x) {
+    bit<8> y = LIMIT;
+    apply {
+        x = y;
+        NoAction();
+    }
+}";
+
+    #[test]
+    fn test_get_renameable_name() {
+        let file = utils::parse_file(SOURCE);
+        let name_at = |line, character| {
+            get_renameable_name(Position::new(line, character), &file.ast_manager)
+                .map(|name| name.map(|(name, _)| name))
+        };
+
+        assert_eq!(
+            name_at(0, 2),
+            Err("Can't rename: `extern` is a keyword.".to_string())
+        );
+        assert_eq!(
+            name_at(3, 7),
+            Err("Can't rename: `bit` is a base type.".to_string())
+        );
+        assert_eq!(
+            name_at(8, 9),
+            Err("Can't rename: `NoAction` is built into P4.".to_string())
+        );
+        assert_eq!(name_at(7, 12), Ok(Some("y".to_string())));
+    }
+
+    #[test]
+    fn test_rename_clash() {
+        let file = utils::parse_file(SOURCE);
+        let symbol = get_symbol("y", Position::new(7, 12), &file.symbol_table_manager).unwrap();
+
+        // The control's parameter `x` is visible where `y` is declared.
+        assert_eq!(
+            rename(&symbol, "x", &file.symbol_table_manager),
+            Err("`x` is already visible at line 6.".to_string())
+        );
+
+        let edits = rename(&symbol, "z", &file.symbol_table_manager).unwrap();
+        let lines: Vec<u32> = edits.iter().map(|edit| edit.range.start.line).collect();
+        assert_eq!(lines, vec![5, 7]);
+        assert!(edits.iter().all(|edit| edit.new_text == "z"));
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("ipv4_lpm").is_ok());
+        assert!(validate_name("2fast").is_err());
+        assert!(validate_name("my-table").is_err());
+        assert!(validate_name("transition").is_err());
+        assert!(validate_name("bit").is_err());
+        assert!(validate_name("NoAction").is_err());
+    }
 }
@@ -0,0 +1,123 @@
+use tower_lsp::lsp_types::{Position, Range, SelectionRange};
+use tree_sitter::Tree;
+
+use crate::utils;
+
+/// The ranges to select around `position`, from the innermost node to the
+/// whole document: an identifier, its member expression, the statement, the
+/// block, the state, action or table, then the parser or control.
+fn get_selection_range(position: Position, tree: &Tree) -> SelectionRange {
+    let point = utils::pos_to_point(position);
+    let mut ranges: Vec<Range> = vec![];
+
+    let mut node = tree
+        .root_node()
+        .named_descendant_for_point_range(point, point);
+    while let Some(current) = node {
+        let range = utils::ts_range_to_lsp_range(current.range());
+        // Nodes wrapping a single child span the same text.
+        if ranges.last() != Some(&range) {
+            ranges.push(range);
+        }
+        node = current.parent();
+    }
+
+    let mut selection: Option<SelectionRange> = None;
+    for range in ranges.into_iter().rev() {
+        selection = Some(SelectionRange {
+            range,
+            parent: selection.map(Box::new),
+        });
+    }
+
+    selection.unwrap_or(SelectionRange {
+        range: Range::new(position, position),
+        parent: None,
+    })
+}
+
+/// A selection range for each of `positions`, in the same order.
+pub fn get_selection_ranges(positions: &[Position], tree: &Tree) -> Vec<SelectionRange> {
+    positions
+        .iter()
+        .map(|position| get_selection_range(*position, tree))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, SelectionRange};
+
+    use super::get_selection_ranges;
+    use crate::utils;
+
+    const SOURCE: &str = "control C(inout headers hdr) {
+    action set_ttl() {
+        hdr.ipv4.ttl = 64;
+    }
+    apply {
+        if (hdr.ipv4.isValid()) {
+            hdr.ipv4.ttl = hdr.ipv4.ttl - 1;
+        }
+    }
+}";
+
+    // The text of each range, from the innermost.
+    fn get_texts(selection: &SelectionRange) -> Vec<&'static str> {
+        let mut texts = vec![];
+        let mut selection = Some(selection);
+        while let Some(current) = selection {
+            let start = utils::pos_to_byte(current.range.start, SOURCE);
+            let end = utils::pos_to_byte(current.range.end, SOURCE);
+            texts.push(&SOURCE[start..end]);
+            selection = current.parent.as_deref();
+        }
+        texts
+    }
+
+    // Whether `texts` go through identifier, member expression, statement,
+    // block and control, in this order.
+    fn is_chain(texts: &[&str], identifier: &str, member: &str, statement: &str) -> bool {
+        let find = |from: usize, matches: &dyn Fn(&str) -> bool| {
+            texts[from..]
+                .iter()
+                .position(|text| matches(text))
+                .map(|index| from + index + 1)
+        };
+
+        Some(0)
+            .and_then(|from| find(from, &|text| text == identifier))
+            .and_then(|from| find(from, &|text| text == member))
+            .and_then(|from| find(from, &|text| text == statement))
+            .and_then(|from| find(from, &|text| text.starts_with('{') && text.ends_with('}')))
+            .and_then(|from| find(from, &|text| text == SOURCE))
+            .is_some()
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let tree = utils::parse(SOURCE);
+        let positions = [Position::new(2, 13), Position::new(6, 32)];
+        let selections = get_selection_ranges(&positions, &tree);
+        assert_eq!(selections.len(), 2);
+
+        let texts = get_texts(&selections[0]);
+        assert!(
+            is_chain(&texts, "ipv4", "hdr.ipv4", "hdr.ipv4.ttl = 64;"),
+            "{texts:?}"
+        );
+
+        // The second cursor is on the `ipv4` of the value.
+        let texts = get_texts(&selections[1]);
+        assert!(
+            is_chain(
+                &texts,
+                "ipv4",
+                "hdr.ipv4.ttl",
+                "hdr.ipv4.ttl = hdr.ipv4.ttl - 1;"
+            ),
+            "{texts:?}"
+        );
+        assert!(!texts.contains(&"hdr.ipv4.ttl = 64;"));
+    }
+}
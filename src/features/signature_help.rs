@@ -0,0 +1,336 @@
+use std::sync::{Arc, Mutex};
+
+use tower_lsp::lsp_types::{
+    Documentation, ParameterInformation, ParameterLabel, Position, SignatureHelp,
+    SignatureInformation,
+};
+use tree_sitter::Tree;
+
+use crate::features::members::{self, MemberKind, Segment};
+use crate::metadata::{AstQuery, NodeKind, SymbolTableQuery, VisitNode, Visitable};
+use crate::utils;
+
+// The `,` separating the arguments before `byte` among `nodes`, the
+// arguments of a call or lists of them.
+fn count_separators(nodes: &[tree_sitter::Node], byte: usize) -> u32 {
+    nodes
+        .iter()
+        .filter(|node| node.start_byte() < byte)
+        .map(|node| match node.kind() {
+            "," => 1,
+            "argument" => 0,
+            _ if node.is_named() => {
+                let mut cursor = node.walk();
+                let children: Vec<tree_sitter::Node> = node.children(&mut cursor).collect();
+                count_separators(&children, byte)
+            }
+            _ => 0,
+        })
+        .sum()
+}
+
+// The callee and the arguments' position in `node`, if it is a call whose
+// parentheses contain `byte`.
+fn get_call_parts(
+    node: tree_sitter::Node,
+    byte: usize,
+) -> Option<(tree_sitter::Node, Vec<tree_sitter::Node>)> {
+    let mut cursor = node.walk();
+    let children: Vec<tree_sitter::Node> = node.children(&mut cursor).collect();
+
+    let open = children.iter().position(|child| child.kind() == "(")?;
+    let close = children[open..]
+        .iter()
+        .position(|child| child.kind() == ")")
+        .map_or(children.len(), |index| open + index);
+    let before_close = match children.get(close) {
+        Some(close) => close.is_missing() || byte <= close.start_byte(),
+        None => true,
+    };
+    if children[open].end_byte() > byte || !before_close || open == 0 {
+        return None;
+    }
+
+    // Type arguments, like `lookahead<T>(`, are not part of the callee, and
+    // keywords, like `if (`, aren't calls.
+    let mut callee = open - 1;
+    if children[callee].kind() == ">" {
+        callee = children[..callee]
+            .iter()
+            .rposition(|child| child.kind() == "<")?
+            .checked_sub(1)?;
+    }
+    if !children[callee].is_named() {
+        return None;
+    }
+
+    Some((children[callee], children[open + 1..close].to_vec()))
+}
+
+/// The call the cursor is in: the callee, as its steps, and the index of
+/// the argument being typed.
+pub fn get_call(source_code: &str, tree: &Tree, byte: usize) -> Option<(Vec<Segment>, u32)> {
+    let mut node = tree.root_node().descendant_for_byte_range(byte, byte)?;
+    loop {
+        if let Some((callee, arguments)) = get_call_parts(node, byte) {
+            let argument = count_separators(&arguments, byte);
+            return Some((members::get_segments(callee, source_code)?, argument));
+        }
+        node = node.parent()?;
+    }
+}
+
+/// Builds the signature from its text, like `void emit<T>(in T hdr)`.
+fn new_signature(label: &str, documentation: Option<String>) -> SignatureInformation {
+    let mut parameters = vec![];
+
+    if let (Some(open), Some(close)) = (label.find('('), label.rfind(')')) {
+        let mut depth = 0;
+        let mut start = open + 1;
+        for (index, c) in label[open + 1..=close].char_indices() {
+            let index = open + 1 + index;
+            match c {
+                '(' | '<' => depth += 1,
+                ')' | '>' if depth > 0 => depth -= 1,
+                ',' | ')' if depth == 0 => {
+                    // Offsets would have to count UTF-16 code units, the
+                    // text is matched in the label by the client instead.
+                    let text = label[start..index].trim();
+                    if !text.is_empty() {
+                        parameters.push(ParameterInformation {
+                            label: ParameterLabel::Simple(text.to_string()),
+                            documentation: None,
+                        });
+                    }
+                    start = index + 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    SignatureInformation {
+        label: label.to_string(),
+        documentation: documentation.map(Documentation::String),
+        parameters: Some(parameters),
+        active_parameter: None,
+    }
+}
+
+/// The name of a parameter from its declaration, like `in bit<32> index`.
+pub fn get_parameter_name(declaration: &str) -> Option<&str> {
+    let declaration = declaration.split('=').next()?.trim();
+    declaration.rsplit(char::is_whitespace).next()
+}
+
+fn get_action_label(name: &str, action: &VisitNode) -> String {
+    let params: Vec<String> = action
+        .get_child_of_kind(NodeKind::Params)
+        .map(|params| {
+            params
+                .get_children()
+                .iter()
+                .filter(|param| param.get().kind == NodeKind::Param)
+                .map(|param| param.get().content.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    format!("action {name}({})", params.join(", "))
+}
+
+/// The declarations callable as `name(...)`: actions, functions and the
+/// constructors of an extern object.
+fn get_declared_signatures(
+    name: &str,
+    root: &VisitNode,
+    source_code: &str,
+) -> Vec<SignatureInformation> {
+    let documentation = |node: &VisitNode| {
+        utils::get_doc_comment(source_code, node.get().range.start.line as usize)
+    };
+    let mut signatures = vec![];
+
+    for node in root.get_descendants() {
+        match node.get().kind {
+            NodeKind::ControlAction if utils::get_name(&node).as_deref() == Some(name) => {
+                signatures.push(new_signature(
+                    &get_action_label(name, &node),
+                    documentation(&node),
+                ));
+            }
+            NodeKind::Function | NodeKind::Extern => {
+                if let Some(function) = node.get_child_of_kind(NodeKind::FunctionName) {
+                    if utils::get_name(&function).as_deref() == Some(name) {
+                        signatures.push(new_signature(
+                            function.get().content.trim().trim_end_matches(';'),
+                            documentation(&node),
+                        ));
+                    }
+                }
+                if node.get().kind == NodeKind::Extern
+                    && utils::get_name(&node).as_deref() == Some(name)
+                {
+                    // Constructors are the methods without a return type.
+                    for method in node.get_descendants() {
+                        if method.get().kind == NodeKind::Method
+                            && method.get_child_of_kind(NodeKind::FunctionName).is_none()
+                        {
+                            signatures.push(new_signature(
+                                method.get().content.trim().trim_end_matches(';'),
+                                documentation(&method),
+                            ));
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    signatures
+}
+
+/// The signatures of `callee`, a function, action or method called at
+/// `position`.
+pub fn get_signatures(
+    callee: &[Segment],
+    position: Position,
+    source_code: &str,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Vec<SignatureInformation> {
+    match callee {
+        [Segment::Name(name)] => {
+            let ast_query = ast_query.lock().unwrap();
+            get_declared_signatures(name, &ast_query.visit_root(), source_code)
+        }
+        [receiver @ .., Segment::Name(method)] if !receiver.is_empty() => {
+            let Some(type_) = members::get_type(receiver, position, ast_query, st_query) else {
+                return vec![];
+            };
+            members::get_type_members(&type_, ast_query)
+                .into_iter()
+                .filter(|member| member.kind == MemberKind::Method && &member.name == method)
+                .map(|member| {
+                    let documentation = member.range.and_then(|range| {
+                        utils::get_doc_comment(source_code, range.start.line as usize)
+                    });
+                    new_signature(&member.detail, documentation)
+                })
+                .collect()
+        }
+        _ => vec![],
+    }
+}
+
+pub fn get_signature_help(
+    position: Position,
+    source_code: &str,
+    tree: &Tree,
+    ast_query: &Arc<Mutex<impl AstQuery>>,
+    st_query: &Arc<Mutex<impl SymbolTableQuery>>,
+) -> Option<SignatureHelp> {
+    let byte = utils::pos_to_byte(position, source_code);
+    let (callee, argument) = get_call(source_code, tree, byte)?;
+
+    let signatures = get_signatures(&callee, position, source_code, ast_query, st_query);
+    if signatures.is_empty() {
+        return None;
+    }
+
+    // The first overload taking enough arguments.
+    let active_signature = signatures
+        .iter()
+        .position(|signature| {
+            signature
+                .parameters
+                .as_ref()
+                .is_some_and(|parameters| parameters.len() > argument as usize)
+        })
+        .unwrap_or(0);
+
+    Some(SignatureHelp {
+        signatures,
+        active_signature: Some(active_signature as u32),
+        active_parameter: Some(argument),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::ParameterLabel;
+
+    use super::{get_call, get_parameter_name, new_signature};
+    use crate::features::members::Segment;
+    use crate::utils;
+
+    // The call at the `$` in `source`.
+    fn call_at(source: &str) -> Option<(Vec<Segment>, u32)> {
+        let byte = source.find('$').unwrap();
+        let source = source.replace('$', "");
+        get_call(&source, &utils::parse(&source), byte)
+    }
+
+    fn names(names: &[&str]) -> Vec<Segment> {
+        names
+            .iter()
+            .map(|name| Segment::Name(name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_call() {
+        let control = |statements: &str| {
+            format!("control C(inout headers hdr) {{ apply {{ {statements} }} }}")
+        };
+
+        let source = control("set_nhop(hdr.ipv4.dstAddr, (bit<9>)$port);");
+        assert_eq!(call_at(&source), Some((names(&["set_nhop"]), 1)));
+
+        let source = "control C() { register<bit<32>>($1) r; apply {} }";
+        assert_eq!(call_at(source), Some((names(&["register"]), 0)));
+
+        let source = control("packet.extract(hdr.mpls[f(1, 2)]$);");
+        assert_eq!(call_at(&source), Some((names(&["packet", "extract"]), 0)));
+
+        let source = control("if (hdr.ipv4.isValid($)) {}");
+        assert_eq!(
+            call_at(&source),
+            Some((names(&["hdr", "ipv4", "isValid"]), 0))
+        );
+
+        let source = control("t.apply(); x$ = 1;");
+        assert_eq!(call_at(&source), None);
+    }
+
+    #[test]
+    fn test_new_signature() {
+        // The parameters are matched as text, whatever their encoding.
+        let label = "action set_port(@name(\"pört\") bit<9> port, bit<48> mac)";
+        let parameters = new_signature(label, None).parameters.unwrap();
+        assert_eq!(
+            parameters[1].label,
+            ParameterLabel::Simple("bit<48> mac".to_string())
+        );
+
+        let label = "void read<T>(out T result, in bit<32> index)";
+        let parameters = new_signature(label, None).parameters.unwrap();
+
+        let labels: Vec<&str> = parameters
+            .iter()
+            .map(|parameter| match &parameter.label {
+                ParameterLabel::Simple(text) => text.as_str(),
+                ParameterLabel::LabelOffsets(_) => "",
+            })
+            .collect();
+        assert_eq!(labels, vec!["out T result", "in bit<32> index"]);
+    }
+
+    #[test]
+    fn test_get_parameter_name() {
+        assert_eq!(get_parameter_name("in bit<32> index"), Some("index"));
+        assert_eq!(get_parameter_name("macAddr_t dstAddr"), Some("dstAddr"));
+        assert_eq!(get_parameter_name("in bool hit = false"), Some("hit"));
+    }
+}
@@ -0,0 +1,277 @@
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, InsertTextFormat};
+
+/// Where a declaration or statement starts, which decides the constructs the
+/// grammar allows.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Scope {
+    TopLevel,
+    Parser,
+    State,
+    Control,
+    Table,
+    Statement,
+}
+
+struct Snippet {
+    label: &'static str,
+    detail: &'static str,
+    body: &'static str,
+    scopes: &'static [Scope],
+    // Architectures the snippet is written for, any if empty.
+    targets: &'static [&'static str],
+}
+
+const KEYWORDS: [(&str, &[Scope]); 27] = [
+    (
+        "const",
+        &[
+            Scope::TopLevel,
+            Scope::Parser,
+            Scope::Control,
+            Scope::State,
+            Scope::Statement,
+        ],
+    ),
+    ("typedef", &[Scope::TopLevel]),
+    ("header", &[Scope::TopLevel]),
+    ("header_union", &[Scope::TopLevel]),
+    ("struct", &[Scope::TopLevel]),
+    ("enum", &[Scope::TopLevel]),
+    ("error", &[Scope::TopLevel]),
+    ("match_kind", &[Scope::TopLevel]),
+    ("extern", &[Scope::TopLevel]),
+    ("package", &[Scope::TopLevel]),
+    ("parser", &[Scope::TopLevel]),
+    ("control", &[Scope::TopLevel]),
+    ("action", &[Scope::TopLevel, Scope::Control]),
+    ("table", &[Scope::Control]),
+    ("apply", &[Scope::Control]),
+    ("state", &[Scope::Parser]),
+    ("value_set", &[Scope::Parser]),
+    ("transition", &[Scope::State]),
+    ("key", &[Scope::Table]),
+    ("actions", &[Scope::Table]),
+    ("size", &[Scope::Table]),
+    ("default_action", &[Scope::Table]),
+    ("entries", &[Scope::Table]),
+    ("if", &[Scope::State, Scope::Statement]),
+    ("return", &[Scope::Statement]),
+    ("exit", &[Scope::Statement]),
+    ("switch", &[Scope::Statement]),
+];
+
+const SNIPPETS: [Snippet; 13] = [
+    Snippet {
+        label: "header",
+        detail: "header declaration",
+        body: "header ${1:name}_t {\n    ${2:bit<8>} ${3:field};\n}",
+        scopes: &[Scope::TopLevel],
+        targets: &[],
+    },
+    Snippet {
+        label: "struct",
+        detail: "struct declaration",
+        body: "struct ${1:name}_t {\n    $0\n}",
+        scopes: &[Scope::TopLevel],
+        targets: &[],
+    },
+    Snippet {
+        label: "parser",
+        detail: "parser with a start state",
+        body: "parser ${1:MyParser}(packet_in ${2:packet}, out ${3:headers_t} hdr) {\n    state start {\n        ${0:transition accept;}\n    }\n}",
+        scopes: &[Scope::TopLevel],
+        targets: &[],
+    },
+    Snippet {
+        label: "control",
+        detail: "control with an apply block",
+        body: "control ${1:MyControl}(${2:inout headers_t hdr}) {\n    apply {\n        $0\n    }\n}",
+        scopes: &[Scope::TopLevel],
+        targets: &[],
+    },
+    Snippet {
+        label: "action",
+        detail: "action declaration",
+        body: "action ${1:name}($2) {\n    $0\n}",
+        scopes: &[Scope::TopLevel, Scope::Control],
+        targets: &[],
+    },
+    Snippet {
+        label: "table",
+        detail: "table with key, actions, size and default action",
+        body: "table ${1:name} {\n    key = {\n        ${2:hdr.field}: ${3:exact};\n    }\n    actions = {\n        ${4:NoAction};\n    }\n    size = ${5:1024};\n    default_action = ${6:NoAction}();\n}",
+        scopes: &[Scope::Control],
+        targets: &[],
+    },
+    Snippet {
+        label: "apply",
+        detail: "apply block",
+        body: "apply {\n    $0\n}",
+        scopes: &[Scope::Control],
+        targets: &[],
+    },
+    Snippet {
+        label: "state",
+        detail: "parser state",
+        body: "state ${1:name} {\n    $0\n    transition ${2:accept};\n}",
+        scopes: &[Scope::Parser],
+        targets: &[],
+    },
+    Snippet {
+        label: "transition select",
+        detail: "transition on a select expression",
+        body: "transition select(${1:hdr.field}) {\n    ${2:value}: ${3:state};\n    default: ${4:accept};\n}",
+        scopes: &[Scope::State],
+        targets: &[],
+    },
+    Snippet {
+        label: "if",
+        detail: "if statement",
+        body: "if (${1:condition}) {\n    $0\n}",
+        scopes: &[Scope::State, Scope::Statement],
+        targets: &[],
+    },
+    Snippet {
+        label: "V1Switch main",
+        detail: "v1model package instantiation",
+        body: "V1Switch(${1:MyParser}(), ${2:MyVerifyChecksum}(), ${3:MyIngress}(), ${4:MyEgress}(), ${5:MyComputeChecksum}(), ${6:MyDeparser}()) main;",
+        scopes: &[Scope::TopLevel],
+        targets: &["v1model"],
+    },
+    Snippet {
+        label: "v1model pipeline",
+        detail: "v1model program skeleton",
+        body: "struct ${1:metadata_t} {\n}\n\n\
+struct ${2:headers_t} {\n}\n\n\
+parser ${3:MyParser}(packet_in packet, out $2 hdr, inout $1 meta, inout standard_metadata_t standard_metadata) {\n    state start {\n        transition accept;\n    }\n}\n\n\
+control ${4:MyVerifyChecksum}(inout $2 hdr, inout $1 meta) {\n    apply { }\n}\n\n\
+control ${5:MyIngress}(inout $2 hdr, inout $1 meta, inout standard_metadata_t standard_metadata) {\n    apply {\n        $0\n    }\n}\n\n\
+control ${6:MyEgress}(inout $2 hdr, inout $1 meta, inout standard_metadata_t standard_metadata) {\n    apply { }\n}\n\n\
+control ${7:MyComputeChecksum}(inout $2 hdr, inout $1 meta) {\n    apply { }\n}\n\n\
+control ${8:MyDeparser}(packet_out packet, in $2 hdr) {\n    apply { }\n}\n\n\
+V1Switch($3(), $4(), $5(), $6(), $7(), $8()) main;",
+        scopes: &[Scope::TopLevel],
+        targets: &["v1model"],
+    },
+    Snippet {
+        label: "psa pipeline",
+        detail: "PSA program skeleton",
+        body: "struct ${1:metadata_t} {\n}\n\n\
+struct ${2:headers_t} {\n}\n\n\
+parser ${3:IngressParserImpl}(packet_in buffer, out $2 hdr, inout $1 meta, in psa_ingress_parser_input_metadata_t istd, in empty_metadata_t resubmit_meta, in empty_metadata_t recirculate_meta) {\n    state start {\n        transition accept;\n    }\n}\n\n\
+control ${4:ingress}(inout $2 hdr, inout $1 meta, in psa_ingress_input_metadata_t istd, inout psa_ingress_output_metadata_t ostd) {\n    apply {\n        $0\n    }\n}\n\n\
+control ${5:IngressDeparserImpl}(packet_out buffer, out empty_metadata_t clone_i2e_meta, out empty_metadata_t resubmit_meta, out empty_metadata_t normal_meta, inout $2 hdr, in $1 meta, in psa_ingress_output_metadata_t istd) {\n    apply { }\n}\n\n\
+parser ${6:EgressParserImpl}(packet_in buffer, out $2 hdr, inout $1 meta, in psa_egress_parser_input_metadata_t istd, in empty_metadata_t normal_meta, in empty_metadata_t clone_i2e_meta, in empty_metadata_t clone_e2e_meta) {\n    state start {\n        transition accept;\n    }\n}\n\n\
+control ${7:egress}(inout $2 hdr, inout $1 meta, in psa_egress_input_metadata_t istd, inout psa_egress_output_metadata_t ostd) {\n    apply { }\n}\n\n\
+control ${8:EgressDeparserImpl}(packet_out buffer, out empty_metadata_t clone_e2e_meta, out empty_metadata_t recirculate_meta, inout $2 hdr, in $1 meta, in psa_egress_output_metadata_t istd, in psa_egress_deparser_input_metadata_t edstd) {\n    apply { }\n}\n\n\
+IngressPipeline($3(), $4(), $5()) ip;\n\n\
+EgressPipeline($6(), $7(), $8()) ep;\n\n\
+PSA_Switch(ip, PacketReplicationEngine(), ep, BufferingQueueingEngine()) main;",
+        scopes: &[Scope::TopLevel],
+        targets: &["psa"],
+    },
+];
+
+/// The keywords and snippets allowed in `scope`. Architecture specific
+/// snippets are limited to the configured target, when there is one. A
+/// keyword with a snippet of the same name is only offered as the snippet.
+pub fn get_items(scope: Scope, target: Option<&str>) -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = SNIPPETS
+        .iter()
+        .filter(|snippet| snippet.scopes.contains(&scope))
+        .filter(|snippet| {
+            snippet.targets.is_empty()
+                || target.is_none_or(|target| snippet.targets.contains(&target))
+        })
+        .map(|snippet| CompletionItem {
+            label: snippet.label.to_string(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(snippet.detail.to_string()),
+            insert_text: Some(snippet.body.to_string()),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        })
+        .collect();
+
+    let keywords: Vec<CompletionItem> = KEYWORDS
+        .iter()
+        .filter(|(_, scopes)| scopes.contains(&scope))
+        .filter(|(keyword, _)| !items.iter().any(|item| item.label == *keyword))
+        .map(|(keyword, _)| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        })
+        .collect();
+    items.extend(keywords);
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::CompletionItemKind;
+
+    use super::{get_items, Scope};
+
+    fn get_labels(scope: Scope, target: Option<&str>) -> Vec<(String, CompletionItemKind)> {
+        get_items(scope, target)
+            .into_iter()
+            .map(|item| (item.label, item.kind.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_scopes() {
+        let table = get_labels(Scope::Table, None);
+        assert!(table.contains(&("default_action".to_string(), CompletionItemKind::KEYWORD)));
+        assert!(!table
+            .iter()
+            .any(|(label, _)| label == "apply" || label == "state"));
+
+        let control = get_labels(Scope::Control, None);
+        assert!(control.contains(&("table".to_string(), CompletionItemKind::SNIPPET)));
+        assert!(!control.iter().any(|(label, _)| label == "header"));
+
+        let state = get_labels(Scope::State, None);
+        assert!(state.contains(&("transition".to_string(), CompletionItemKind::KEYWORD)));
+        assert!(state.contains(&("transition select".to_string(), CompletionItemKind::SNIPPET)));
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        for scope in [
+            Scope::TopLevel,
+            Scope::Parser,
+            Scope::State,
+            Scope::Control,
+            Scope::Table,
+            Scope::Statement,
+        ] {
+            let labels = get_labels(scope, None);
+            for (index, (label, _)) in labels.iter().enumerate() {
+                assert!(
+                    !labels[index + 1..].iter().any(|(other, _)| other == label),
+                    "`{label}` is offered twice in {scope:?}"
+                );
+            }
+        }
+
+        let top_level = get_labels(Scope::TopLevel, None);
+        assert!(top_level.contains(&("header".to_string(), CompletionItemKind::SNIPPET)));
+        assert!(!top_level.contains(&("header".to_string(), CompletionItemKind::KEYWORD)));
+    }
+
+    #[test]
+    fn test_targets() {
+        let has = |target: Option<&str>, label: &str| {
+            get_labels(Scope::TopLevel, target)
+                .iter()
+                .any(|(other, _)| other == label)
+        };
+        assert!(has(Some("v1model"), "V1Switch main"));
+        assert!(!has(Some("v1model"), "psa pipeline"));
+        assert!(has(Some("psa"), "psa pipeline"));
+        assert!(has(None, "V1Switch main") && has(None, "psa pipeline"));
+    }
+}
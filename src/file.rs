@@ -1,13 +1,30 @@
+use std::collections::HashMap;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 use tower_lsp::lsp_types::{
-    CompletionItem, Diagnostic, HoverContents, Location, Position, SemanticTokensResult,
-    TextDocumentContentChangeEvent, Url, WorkspaceEdit,
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, CodeActionContext,
+    CodeActionOrCommand, CompletionItem, CompletionItemKind, Diagnostic, DocumentHighlight,
+    DocumentHighlightKind, FoldingRange, FormattingOptions, HoverContents, InlayHint, Location,
+    Position, Range, SelectionRange, SemanticTokensResult, SignatureHelp,
+    TextDocumentContentChangeEvent, TextEdit, Url,
 };
 use tree_sitter::{InputEdit, Parser, Tree};
 
-use crate::features::{completion, diagnostics, goto, hover, rename, semantic_tokens};
-use crate::metadata::{AstEditor, AstManager, SymbolTableEditor, SymbolTableManager};
+use crate::features::completion::CompletionData;
+use crate::features::control_graph::{self, ControlGraph};
+use crate::features::goto::Prototype;
+use crate::features::preprocessor::{IncludeCache, Preprocessor};
+use crate::features::{
+    call_hierarchy, code_actions, completion, constant_folding, diagnostics, folding, formatting,
+    goto, highlight, hover, inlay_hints, refactoring, rename, selection, semantic_tokens,
+    signature_help,
+};
+use crate::metadata::{
+    AstEditor, AstManager, AstQuery, Symbol, SymbolTableEditor, SymbolTableManager,
+    SymbolTableQuery, Visitable,
+};
+use crate::settings::Settings;
 use crate::utils;
 
 pub struct File {
@@ -16,6 +33,9 @@ pub struct File {
     pub tree: Option<Tree>,
     pub symbol_table_manager: Arc<Mutex<SymbolTableManager>>,
     pub ast_manager: Arc<Mutex<AstManager>>,
+    pub preprocessor: Preprocessor,
+    include_cache: IncludeCache,
+    pub settings: Settings,
 }
 
 impl File {
@@ -36,6 +56,9 @@ impl File {
             tree: tree.clone(),
             symbol_table_manager,
             ast_manager,
+            preprocessor: Preprocessor::default(),
+            include_cache: IncludeCache::default(),
+            settings: Settings::default(),
         }
     }
 
@@ -79,18 +102,198 @@ impl File {
 
         ast_manager.update(&self.source_code, self.tree.to_owned().unwrap());
         st_manager.update(ast_manager.get_ast());
+        drop(ast_manager);
+        drop(st_manager);
+
+        self.update_preprocessor();
+    }
+
+    pub fn update_settings(&mut self, settings: Settings) {
+        self.settings = settings;
+        self.update_preprocessor();
+    }
+
+    fn update_preprocessor(&mut self) {
+        self.preprocessor = Preprocessor::new(
+            &self.uri,
+            &self.source_code,
+            &self.settings.get_include_paths(),
+            &self.settings.get_active_defines(),
+            &mut self.include_cache,
+        );
+    }
+
+    /// The diagnostics computed as the document is edited. `is_included`
+    /// tells whether another document includes this one.
+    pub fn get_quick_diagnostics(&self, is_included: bool) -> Vec<Diagnostic> {
+        let mut diagnostics = diagnostics::skip_included_names(
+            diagnostics::get_quick_diagnostics(&self.ast_manager, &self.symbol_table_manager),
+            &self.source_code,
+            &self.preprocessor,
+        );
+        diagnostics.append(&mut self.preprocessor.get_diagnostics());
+        diagnostics.append(&mut self.get_missing_core_diagnostics(is_included));
+        self.settings.apply_lints(diagnostics)
+    }
+
+    pub fn get_full_diagnostics(&self, is_included: bool) -> Vec<Diagnostic> {
+        let mut diagnostics = diagnostics::skip_included_names(
+            diagnostics::get_full_diagnostics(&self.ast_manager, &self.symbol_table_manager),
+            &self.source_code,
+            &self.preprocessor,
+        );
+        diagnostics.append(&mut self.preprocessor.get_diagnostics());
+        diagnostics.append(&mut self.get_missing_core_diagnostics(is_included));
+        self.settings.apply_lints(diagnostics)
+    }
+
+    fn get_missing_core_diagnostics(&self, is_included: bool) -> Vec<Diagnostic> {
+        // A header may rely on the documents including it for core.p4.
+        if is_included {
+            return vec![];
+        }
+
+        match &self.tree {
+            Some(tree) => diagnostics::get_missing_core_diagnostics(
+                &self.uri,
+                &self.source_code,
+                tree,
+                &self.preprocessor,
+                self.get_include_position(),
+            ),
+            None => vec![],
+        }
+    }
+
+    /// The quick fixes of the diagnostics in `context`, from the data they
+    /// carry, and the refactorings of the selection `range`.
+    pub fn get_code_actions(
+        &self,
+        range: Range,
+        context: &CodeActionContext,
+        is_included: bool,
+    ) -> Vec<CodeActionOrCommand> {
+        let mut actions = code_actions::get_code_actions(&self.uri, &context.diagnostics);
+        actions.extend(
+            diagnostics::get_default_action_fix(range.start, &self.ast_manager)
+                .map(|fix| code_actions::new_code_action(&self.uri, fix, None)),
+        );
+        actions.extend(refactoring::get_refactorings(
+            &self.uri,
+            range,
+            &self.source_code,
+            &self.preprocessor,
+            is_included,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        ));
+
+        match &context.only {
+            Some(kinds) => code_actions::filter_by_kind(actions, kinds),
+            None => actions,
+        }
     }
 
-    pub fn get_quick_diagnostics(&self) -> Vec<Diagnostic> {
-        diagnostics::get_quick_diagnostics(&self.ast_manager, &self.symbol_table_manager)
+    pub fn get_completion_list(
+        &self,
+        position: Position,
+        external: &[CompletionItem],
+    ) -> Option<Vec<CompletionItem>> {
+        let mut items = completion::get_list(
+            position,
+            &self.source_code,
+            self.tree.as_ref()?,
+            self.settings.target.as_deref(),
+            external,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )?;
+
+        let data = serde_json::to_value(CompletionData {
+            uri: self.uri.clone(),
+            position: Some(position),
+            document: self.uri.clone(),
+        })
+        .ok();
+        for item in &mut items {
+            let is_symbol = !matches!(
+                item.kind,
+                Some(CompletionItemKind::KEYWORD | CompletionItemKind::SNIPPET)
+            );
+            if item.data.is_none() && is_symbol {
+                item.data = data.clone();
+            }
+        }
+
+        Some(items)
+    }
+
+    /// The top-level declarations, to complete in `document`.
+    pub fn get_exported_items(&self, document: &Url) -> Vec<CompletionItem> {
+        let data = serde_json::to_value(CompletionData {
+            uri: self.uri.clone(),
+            position: None,
+            document: document.clone(),
+        })
+        .ok();
+
+        let mut items = completion::get_exported_items(&self.ast_manager);
+        for item in &mut items {
+            item.data = data.clone();
+        }
+        items
+    }
+
+    pub fn get_declaration(
+        &self,
+        name: &str,
+        position: Option<Position>,
+    ) -> Option<completion::Declaration> {
+        completion::get_declaration(
+            name,
+            position,
+            &self.source_code,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )
     }
 
-    pub fn get_full_diagnostics(&self) -> Vec<Diagnostic> {
-        diagnostics::get_full_diagnostics(&self.ast_manager, &self.symbol_table_manager)
+    /// The edit adding an `#include` of `path`, after the existing ones, or
+    /// `None` if the document already includes it.
+    pub fn get_include_edit(&self, path: &Path) -> Option<TextEdit> {
+        if self.preprocessor.is_included(path) {
+            return None;
+        }
+
+        let include_path = self
+            .settings
+            .get_include_paths()
+            .into_iter()
+            .find_map(|include_path| path.strip_prefix(include_path).ok().map(Path::to_path_buf));
+        let directive = match include_path {
+            Some(relative) => format!("#include <{}>\n", relative.display()),
+            None => {
+                let document = self.uri.to_file_path().ok()?;
+                let relative = utils::get_relative_path(document.parent()?, path);
+                format!("#include \"{}\"\n", relative.display())
+            }
+        };
+
+        let position = self.get_include_position();
+        Some(TextEdit::new(Range::new(position, position), directive))
     }
 
-    pub fn get_completion_list(&self, position: Position) -> Option<Vec<CompletionItem>> {
-        completion::get_list(position, &self.source_code, &self.symbol_table_manager)
+    /// Where a new `#include` goes: after the existing ones.
+    fn get_include_position(&self) -> Position {
+        let line = self
+            .source_code
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.trim_start().starts_with("#include"))
+            .map(|(index, _)| index + 1)
+            .last()
+            .unwrap_or(0);
+        Position::new(line as u32, 0)
     }
 
     pub fn get_hover_info(&self, position: Position) -> Option<HoverContents> {
@@ -102,6 +305,29 @@ impl File {
             .root_node()
             .named_descendant_for_point_range(point, point)?;
 
+        if let Some(definition) = self.preprocessor.get_macro_at(position) {
+            let expansion = self.preprocessor.expand(&definition.name);
+            let mut hover_content = hover::HoverContentBuilder::new()
+                .add_code(&definition.get_declaration())
+                .add_text(&format!("Expands to: `{expansion}`"));
+            if let Some(constant) = constant_folding::evaluate(&expansion, &HashMap::new()) {
+                hover_content = hover_content.add_constant(constant);
+            }
+            return Some(hover_content.build());
+        }
+
+        let name = utils::get_node_text(&node, &self.source_code);
+        let constant = self.get_symbol(&name, position).and_then(|symbol| {
+            constant_folding::get_constant(&self.ast_manager, symbol.get_definition_range())
+        });
+        if let Some(constant) = constant {
+            let hover_content = hover::HoverContentBuilder::new()
+                .add_text(&format!("`{name}`: {}", constant.type_))
+                .add_constant(constant)
+                .build();
+            return Some(hover_content);
+        }
+
         let mut node_hierarchy = node.kind().to_string();
         while node.kind() != "source_file" {
             node = node.parent()?;
@@ -115,24 +341,283 @@ impl File {
         Some(hover_content)
     }
 
+    pub fn get_signature_help(&self, position: Position) -> Option<SignatureHelp> {
+        signature_help::get_signature_help(
+            position,
+            &self.source_code,
+            self.tree.as_ref()?,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )
+    }
+
+    pub fn get_inlay_hints(&self, range: Range) -> Vec<InlayHint> {
+        let mut hints = vec![];
+
+        if self.settings.is_inlay_hint_enabled(inlay_hints::TYPES) {
+            hints.append(&mut inlay_hints::get_type_hints(range, &self.ast_manager));
+        }
+        if self
+            .settings
+            .is_inlay_hint_enabled(inlay_hints::PARAMETER_NAMES)
+        {
+            if let Some(tree) = &self.tree {
+                hints.append(&mut inlay_hints::get_parameter_hints(
+                    range,
+                    &self.source_code,
+                    tree,
+                    &self.ast_manager,
+                    &self.symbol_table_manager,
+                ));
+            }
+        }
+        if self
+            .settings
+            .is_inlay_hint_enabled(inlay_hints::CONTROL_PLANE)
+        {
+            hints.append(&mut inlay_hints::get_control_plane_hints(
+                range,
+                &self.ast_manager,
+            ));
+        }
+        if self
+            .settings
+            .is_inlay_hint_enabled(inlay_hints::DEFINE_VALUES)
+        {
+            hints.append(&mut inlay_hints::get_define_hints(
+                range,
+                &self.preprocessor,
+            ));
+        }
+
+        hints
+    }
+
+    pub fn get_folding_ranges(&self) -> Vec<FoldingRange> {
+        match &self.tree {
+            Some(tree) => folding::get_folding_ranges(&self.source_code, tree),
+            None => vec![],
+        }
+    }
+
+    pub fn get_selection_ranges(&self, positions: &[Position]) -> Option<Vec<SelectionRange>> {
+        let tree = self.tree.as_ref()?;
+
+        Some(selection::get_selection_ranges(positions, tree))
+    }
+
+    pub fn prepare_call_hierarchy(&self, position: Position) -> Option<Vec<CallHierarchyItem>> {
+        call_hierarchy::prepare(
+            &self.uri,
+            position,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )
+    }
+
+    pub fn get_incoming_calls(&self, item: &CallHierarchyItem) -> Vec<CallHierarchyIncomingCall> {
+        call_hierarchy::get_incoming_calls(
+            &self.uri,
+            item,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )
+    }
+
+    pub fn get_outgoing_calls(&self, item: &CallHierarchyItem) -> Vec<CallHierarchyOutgoingCall> {
+        call_hierarchy::get_outgoing_calls(
+            &self.uri,
+            item,
+            &self.ast_manager,
+            &self.symbol_table_manager,
+        )
+    }
+
+    pub fn format(&self, options: &FormattingOptions) -> Option<Vec<TextEdit>> {
+        let tree = self.tree.as_ref()?;
+
+        formatting::get_formatting_edits(&self.source_code, tree, options)
+    }
+
+    pub fn format_range(&self, range: Range, options: &FormattingOptions) -> Option<Vec<TextEdit>> {
+        let tree = self.tree.as_ref()?;
+
+        formatting::get_range_formatting_edits(&self.source_code, tree, options, range)
+    }
+
+    pub fn format_on_type(
+        &self,
+        position: Position,
+        character: &str,
+        options: &FormattingOptions,
+    ) -> Option<Vec<TextEdit>> {
+        let tree = self.tree.as_ref()?;
+
+        formatting::get_on_type_formatting_edits(
+            &self.source_code,
+            tree,
+            options,
+            position,
+            character,
+        )
+    }
+
     pub fn get_semantic_tokens(&self) -> Option<SemanticTokensResult> {
         Some(semantic_tokens::get_tokens())
     }
 
     pub fn get_definition_location(&self, position: Position) -> Option<Location> {
-        let range =
-            goto::get_definition_range(&self.ast_manager, &self.symbol_table_manager, position)?;
+        if let Some(definition) = self.preprocessor.get_macro_at(position) {
+            return definition.location.clone();
+        }
+
+        let range = goto::get_definition_range(
+            &self.ast_manager,
+            &self.symbol_table_manager,
+            position,
+            &self.source_code,
+            self.tree.as_ref()?,
+        )?;
         Some(Location::new(self.uri.clone(), range))
     }
 
-    pub fn rename_symbol(&self, position: Position, new_name: String) -> Option<WorkspaceEdit> {
-        rename::rename(
+    pub fn get_word(&self, position: Position) -> Option<String> {
+        goto::get_word(&self.source_code, position).map(|(word, _, _)| word.to_string())
+    }
+
+    pub fn get_type_name(&self, position: Position) -> Option<String> {
+        goto::get_type_name(
+            position,
+            &self.source_code,
+            self.tree.as_ref()?,
             &self.ast_manager,
             &self.symbol_table_manager,
+        )
+    }
+
+    pub fn find_type_declaration(&self, name: &str) -> Option<Location> {
+        let range = goto::find_type_declaration(name, &self.ast_manager)?;
+        Some(Location::new(self.uri.clone(), range))
+    }
+
+    pub fn get_callee(&self, position: Position) -> Option<(Option<String>, String)> {
+        goto::get_callee(
+            position,
+            &self.source_code,
+            self.tree.as_ref()?,
+            &self.ast_manager,
             &self.symbol_table_manager,
-            self.uri.clone(),
-            new_name,
+        )
+    }
+
+    pub fn find_method_declaration(
+        &self,
+        extern_name: Option<&str>,
+        name: &str,
+    ) -> Option<Location> {
+        let range = goto::find_method_declaration(extern_name, name, &self.ast_manager)?;
+        Some(Location::new(self.uri.clone(), range))
+    }
+
+    pub fn get_prototype(&self, name: &str) -> Option<Prototype> {
+        goto::get_prototype(name, &self.ast_manager)
+    }
+
+    pub fn get_implementations(&self, prototype: &Prototype) -> Vec<Location> {
+        goto::get_implementation_ranges(prototype, &self.ast_manager)
+            .into_iter()
+            .map(|range| Location::new(self.uri.clone(), range))
+            .collect()
+    }
+
+    pub fn get_references(
+        &self,
+        position: Position,
+        include_declaration: bool,
+    ) -> Option<Vec<Location>> {
+        if let Some(definition) = self.preprocessor.get_macro_at(position) {
+            return Some(
+                self.preprocessor
+                    .get_references(&definition.name, include_declaration),
+            );
+        }
+
+        let ast_manager = self.ast_manager.lock().unwrap();
+        let root_visit = ast_manager.visit_root();
+        let node = root_visit.get_node_at_position(position)?;
+        let symbol_table_manager = self.symbol_table_manager.lock().unwrap();
+        let symbol =
+            symbol_table_manager.get_symbol_at_pos(node.get().content.clone(), position)?;
+
+        let mut ranges = vec![];
+        if include_declaration {
+            ranges.push(symbol.get_definition_range());
+        }
+        ranges.extend(symbol.get_usages());
+
+        Some(
+            ranges
+                .into_iter()
+                .map(|range| Location::new(self.uri.clone(), range))
+                .collect(),
+        )
+    }
+
+    /// The name at `position` and its range, if it can be renamed.
+    pub fn get_renameable_name(
+        &self,
+        position: Position,
+    ) -> Result<Option<(String, Range)>, String> {
+        rename::get_renameable_name(position, &self.ast_manager)
+    }
+
+    pub fn get_symbol(&self, name: &str, position: Position) -> Option<Symbol> {
+        rename::get_symbol(name, position, &self.symbol_table_manager)
+    }
+
+    pub fn get_top_level_symbol(&self, name: &str) -> Option<Symbol> {
+        rename::get_top_level_symbol(name, &self.symbol_table_manager)
+    }
+
+    pub fn get_highlights(&self, position: Position) -> Option<Vec<DocumentHighlight>> {
+        if let Some(definition) = self.preprocessor.get_macro_at(position) {
+            return Some(
+                self.preprocessor
+                    .get_references(&definition.name, true)
+                    .into_iter()
+                    .filter(|location| location.uri == self.uri)
+                    .map(|location| DocumentHighlight {
+                        range: location.range,
+                        kind: Some(DocumentHighlightKind::TEXT),
+                    })
+                    .collect(),
+            );
+        }
+
+        highlight::get_highlights(
             position,
+            &self.source_code,
+            self.tree.as_ref()?,
+            &self.ast_manager,
+            &self.symbol_table_manager,
         )
     }
+
+    pub fn rename_symbol(&self, symbol: &Symbol, new_name: &str) -> Result<Vec<TextEdit>, String> {
+        rename::rename(symbol, new_name, &self.symbol_table_manager)
+    }
+
+    /// Renames the uses of `name` declared in an included document.
+    pub fn rename_external(&self, name: &str, new_name: &str) -> Result<Vec<TextEdit>, String> {
+        rename::rename_external(
+            name,
+            new_name,
+            &self.source_code,
+            &self.symbol_table_manager,
+        )
+    }
+
+    pub fn get_control_graphs(&self, control_name: Option<&str>) -> Vec<ControlGraph> {
+        control_graph::get_control_graphs(&self.ast_manager, control_name)
+    }
 }
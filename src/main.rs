@@ -1,9 +1,17 @@
+use std::collections::HashMap;
 use std::env;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 
-use features::semantic_tokens;
-use plugin_manager::PluginManager;
+use features::{control_graph, semantic_tokens};
+use plugin_manager::{Compiler, PluginManager};
+use serde_json::Value;
 use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::request::{
+    GotoDeclarationParams, GotoDeclarationResponse, GotoImplementationParams,
+    GotoImplementationResponse, GotoTypeDefinitionParams, GotoTypeDefinitionResponse,
+};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
@@ -26,17 +34,22 @@ mod settings;
 mod utils;
 mod workspace;
 
+use settings::Settings;
 use workspace::Workspace;
 
 struct Backend {
     client: Client,
     workspace: RwLock<Workspace>,
-    plugin_manager: RwLock<PluginManager>,
+    // The plugins of each project, by project root. Files outside of a
+    // project share the `None` entry.
+    plugin_managers: RwLock<HashMap<Option<PathBuf>, PluginManager>>,
+    supports_configuration: AtomicBool,
+    supports_inlay_hint_refresh: AtomicBool,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
         let log_file_path = env::temp_dir().join("p4-lsp.log");
 
         if let Ok(log_file) = File::create(log_file_path) {
@@ -59,10 +72,36 @@ impl LanguageServer for Backend {
 
         info!("Initializing lsp");
 
-        self.plugin_manager.write().unwrap().load_plugins();
+        let supports_configuration = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.configuration)
+            .unwrap_or(false);
+        self.supports_configuration
+            .store(supports_configuration, Ordering::Relaxed);
+        let supports_inlay_hint_refresh = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.inlay_hint.as_ref())
+            .and_then(|inlay_hint| inlay_hint.refresh_support)
+            .unwrap_or(false);
+        self.supports_inlay_hint_refresh
+            .store(supports_inlay_hint_refresh, Ordering::Relaxed);
+
+        let folders = match params.workspace_folders {
+            Some(folders) => folders.into_iter().map(|folder| folder.uri).collect(),
+            None => params.root_uri.into_iter().collect(),
+        };
+        self.workspace
+            .write()
+            .unwrap()
+            .update_folders(folders, vec![]);
 
         let mut completion_temp = CompletionOptions::default();
         completion_temp.trigger_characters = Some(vec![".".to_string()]);
+        completion_temp.resolve_provider = Some(true);
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 semantic_tokens_provider: Some(
@@ -77,6 +116,11 @@ impl LanguageServer for Backend {
                 ),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
                 completion_provider: Some(completion_temp),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
                 text_document_sync: Some(TextDocumentSyncCapability::Options(
                     TextDocumentSyncOptions {
                         open_close: Some(true),
@@ -86,11 +130,51 @@ impl LanguageServer for Backend {
                         save: Some(TextDocumentSyncSaveOptions::Supported(true)),
                     },
                 )),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_EXTRACT,
+                            CodeActionKind::REFACTOR_INLINE,
+                            CodeActionKind::REFACTOR_REWRITE,
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "}".to_string(),
+                    more_trigger_character: Some(vec![";".to_string()]),
+                }),
                 definition_provider: Some(OneOf::Left(true)),
+                type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
+                declaration_provider: Some(DeclarationCapability::Simple(true)),
+                implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+                references_provider: Some(OneOf::Left(true)),
+                document_highlight_provider: Some(OneOf::Left(true)),
                 rename_provider: Some(OneOf::Right(RenameOptions {
-                    prepare_provider: Some(false),
+                    prepare_provider: Some(true),
                     work_done_progress_options: WorkDoneProgressOptions::default(),
                 })),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        control_graph::COMMAND.to_string(),
+                        settings::SWITCH_PROFILE_COMMAND.to_string(),
+                    ],
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
                 ..Default::default()
             },
             ..Default::default()
@@ -99,6 +183,30 @@ impl LanguageServer for Backend {
 
     async fn initialized(&self, _: InitializedParams) {
         info!("Lsp initialized");
+
+        let watchers = settings::PROJECT_SETTINGS_FILES
+            .iter()
+            .map(|name| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{name}")),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "p4lsp-project-settings".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            error!("Couldn't watch project settings files: {}", err);
+        }
+
+        if self.supports_configuration.load(Ordering::Relaxed) {
+            self.pull_configuration().await;
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -110,20 +218,18 @@ impl LanguageServer for Backend {
         let doc = params.text_document;
         info!("Opening file: {}", doc.uri);
 
-        let mut diagnostics = {
+        let (mut diagnostics, errors) = {
             let mut workspace = self.workspace.write().unwrap();
             (*workspace).add_file(doc.uri.clone(), &doc.text);
 
-            (*workspace).get_full_diagnostics(doc.uri.clone())
+            (
+                (*workspace).get_full_diagnostics(doc.uri.clone()),
+                (*workspace).take_settings_errors(),
+            )
         };
+        self.show_settings_errors(errors).await;
 
-        diagnostics.append(
-            &mut self
-                .plugin_manager
-                .write()
-                .unwrap()
-                .run_diagnostic(doc.uri.path().into()),
-        );
+        diagnostics.append(&mut self.run_plugin_diagnostics(&doc.uri));
 
         self.client
             .publish_diagnostics(doc.uri, diagnostics, None)
@@ -150,13 +256,7 @@ impl LanguageServer for Backend {
             (*workspace).get_full_diagnostics(params.text_document.uri.clone())
         };
 
-        diagnostics.append(
-            &mut self
-                .plugin_manager
-                .write()
-                .unwrap()
-                .run_diagnostic(params.text_document.uri.path().into()),
-        );
+        diagnostics.append(&mut self.run_plugin_diagnostics(&params.text_document.uri));
 
         self.client
             .publish_diagnostics(params.text_document.uri, diagnostics, None)
@@ -182,6 +282,65 @@ impl LanguageServer for Backend {
         }
     }
 
+    async fn goto_type_definition(
+        &self,
+        params: GotoTypeDefinitionParams,
+    ) -> Result<Option<GotoTypeDefinitionResponse>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace
+            .get_type_definition_location(
+                params.text_document_position_params.text_document.uri,
+                params.text_document_position_params.position,
+            )
+            .map(GotoTypeDefinitionResponse::Scalar))
+    }
+
+    async fn goto_declaration(
+        &self,
+        params: GotoDeclarationParams,
+    ) -> Result<Option<GotoDeclarationResponse>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace
+            .get_declaration_location(
+                params.text_document_position_params.text_document.uri,
+                params.text_document_position_params.position,
+            )
+            .map(GotoDeclarationResponse::Scalar))
+    }
+
+    async fn goto_implementation(
+        &self,
+        params: GotoImplementationParams,
+    ) -> Result<Option<GotoImplementationResponse>> {
+        let workspace = self.workspace.read().unwrap();
+
+        let locations = workspace.get_implementation_locations(
+            params.text_document_position_params.text_document.uri,
+            params.text_document_position_params.position,
+        );
+        if locations.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(GotoImplementationResponse::Array(locations)))
+        }
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let response = {
+            let workspace = self.workspace.read().unwrap();
+
+            Ok((*workspace).get_references(
+                params.text_document_position.text_document.uri,
+                params.text_document_position.position,
+                params.context.include_declaration,
+            ))
+        };
+
+        response
+    }
+
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         let maybe_hover_info = {
             let workspace = self.workspace.read().unwrap();
@@ -230,23 +389,359 @@ impl LanguageServer for Backend {
         Ok(Some(CompletionResponse::Array(completion_list)))
     }
 
+    async fn completion_resolve(&self, params: CompletionItem) -> Result<CompletionItem> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.resolve_completion(params))
+    }
+
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_signature_help(
+            params.text_document_position_params.text_document.uri,
+            params.text_document_position_params.position,
+        ))
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_folding_ranges(params.text_document.uri))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_selection_ranges(params.text_document.uri, &params.positions))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_code_actions(params.text_document.uri, params.range, &params.context))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let workspace = self.workspace.read().unwrap();
+        let text_document_position = params.text_document_position_params;
+
+        Ok(workspace.prepare_call_hierarchy(
+            text_document_position.text_document.uri,
+            text_document_position.position,
+        ))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_incoming_calls(&params.item))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_outgoing_calls(&params.item))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.format(params.text_document.uri, &params.options))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.format_range(params.text_document.uri, params.range, &params.options))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let workspace = self.workspace.read().unwrap();
+        let text_document_position = params.text_document_position;
+
+        Ok(workspace.format_on_type(
+            text_document_position.text_document.uri,
+            text_document_position.position,
+            &params.ch,
+            &params.options,
+        ))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(Some(
+            workspace.get_inlay_hints(params.text_document.uri, params.range),
+        ))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let workspace = self.workspace.read().unwrap();
+
+        Ok(workspace.get_highlights(
+            params.text_document_position_params.text_document.uri,
+            params.text_document_position_params.position,
+        ))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let workspace = self.workspace.read().unwrap();
+
+        workspace
+            .prepare_rename(params.text_document.uri, params.position)
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)
+    }
+
     async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
-        let response = {
-            let mut workspace = self.workspace.write().unwrap();
+        let workspace = self.workspace.read().unwrap();
 
-            Ok((*workspace).rename_symbol(
+        workspace
+            .rename_symbol(
                 params.text_document_position.text_document.uri,
                 params.text_document_position.position,
                 params.new_name,
-            ))
+            )
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        info!("Executing command: {}", params.command);
+
+        let response = match params.command.as_str() {
+            control_graph::COMMAND => {
+                let uri = params
+                    .arguments
+                    .first()
+                    .and_then(|arg| arg.as_str())
+                    .and_then(|arg| Url::parse(arg).ok());
+                let control_name = params.arguments.get(1).and_then(|arg| arg.as_str());
+                let format = params
+                    .arguments
+                    .get(2)
+                    .and_then(|arg| arg.as_str())
+                    .unwrap_or("json");
+
+                let Some(uri) = uri else {
+                    return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                        "Expected a document URI as the first argument.",
+                    ));
+                };
+                let workspace = self.workspace.read().unwrap();
+                let graphs = (*workspace)
+                    .get_control_graph(uri, control_name, format)
+                    .map_err(tower_lsp::jsonrpc::Error::invalid_params)?;
+                Some(graphs)
+            }
+            settings::SWITCH_PROFILE_COMMAND => {
+                let name = params
+                    .arguments
+                    .first()
+                    .and_then(|arg| arg.as_str())
+                    .unwrap_or_default();
+                let switched = {
+                    let mut workspace = self.workspace.write().unwrap();
+                    (*workspace).set_active_profile(name)
+                };
+
+                if switched {
+                    self.on_settings_changed().await;
+                } else {
+                    self.client
+                        .show_message(
+                            MessageType::WARNING,
+                            format!("Unknown build profile: {name}"),
+                        )
+                        .await;
+                }
+
+                Some(Value::Bool(switched))
+            }
+            _ => None,
         };
 
-        response
+        Ok(response)
     }
 
     async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
-        let mut workspace = self.workspace.write().unwrap();
-        (*workspace).update_settings(params.settings);
+        // The notification is only a signal to pull when the client supports it.
+        if self.supports_configuration.load(Ordering::Relaxed) {
+            self.pull_configuration().await;
+            return;
+        }
+
+        self.show_settings_errors(Settings::validate(&params.settings))
+            .await;
+        {
+            let mut workspace = self.workspace.write().unwrap();
+            (*workspace).update_settings(params.settings, vec![]);
+        }
+
+        self.on_settings_changed().await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        {
+            let mut workspace = self.workspace.write().unwrap();
+            (*workspace).update_folders(
+                params
+                    .event
+                    .added
+                    .into_iter()
+                    .map(|folder| folder.uri)
+                    .collect(),
+                params
+                    .event
+                    .removed
+                    .into_iter()
+                    .map(|folder| folder.uri)
+                    .collect(),
+            );
+        }
+
+        if self.supports_configuration.load(Ordering::Relaxed) {
+            self.pull_configuration().await;
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        for change in &params.changes {
+            info!("Project settings changed: {}", change.uri);
+        }
+
+        {
+            let mut workspace = self.workspace.write().unwrap();
+            (*workspace).reload_project_settings();
+        }
+
+        self.on_settings_changed().await;
+    }
+}
+
+impl Backend {
+    /// Requests the global settings and those of each workspace folder.
+    async fn pull_configuration(&self) {
+        let folders = self.workspace.read().unwrap().get_folders();
+
+        let items = std::iter::once(None)
+            .chain(folders.iter().cloned().map(Some))
+            .map(|scope_uri| ConfigurationItem {
+                scope_uri,
+                section: Some(settings::CONFIGURATION_SECTION.to_string()),
+            })
+            .collect();
+        let mut values = match self.client.configuration(items).await {
+            Ok(values) => values.into_iter(),
+            Err(err) => {
+                error!("Couldn't get the configuration: {}", err);
+                return;
+            }
+        };
+
+        let settings = values.next().unwrap_or_default();
+        let folder_settings: Vec<(Url, Value)> = folders.into_iter().zip(values).collect();
+
+        let mut errors = Settings::validate(&settings);
+        for (folder, value) in &folder_settings {
+            errors.extend(
+                Settings::validate(value)
+                    .into_iter()
+                    .map(|error| format!("{error} ({folder})")),
+            );
+        }
+        self.show_settings_errors(errors).await;
+
+        {
+            let mut workspace = self.workspace.write().unwrap();
+            (*workspace).update_settings(settings, folder_settings);
+        }
+
+        self.on_settings_changed().await;
+    }
+
+    async fn show_settings_errors(&self, errors: Vec<String>) {
+        for error in errors {
+            warn!("Invalid settings: {}", error);
+            self.client
+                .show_message(MessageType::WARNING, format!("p4lsp settings: {error}"))
+                .await;
+        }
+    }
+
+    /// Runs the plugins with the compiler and plugins configured for the file.
+    fn run_plugin_diagnostics(&self, url: &Url) -> Vec<Diagnostic> {
+        let ((path, flags), (project_root, plugin_paths)) = {
+            let workspace = self.workspace.read().unwrap();
+            (
+                (*workspace).get_compiler(url),
+                (*workspace).get_plugins(url),
+            )
+        };
+
+        let mut plugin_managers = self.plugin_managers.write().unwrap();
+        let plugin_manager = plugin_managers.entry(project_root).or_insert_with(|| {
+            let mut plugin_manager = PluginManager::new();
+            plugin_manager.load_plugins(&plugin_paths);
+            plugin_manager
+        });
+        plugin_manager.update_plugins(&plugin_paths);
+        plugin_manager.run_diagnostic(url.path().into(), Compiler { path, flags })
+    }
+
+    /// Reports the project settings files that couldn't be read, and updates
+    /// what depends on the settings: the diagnostics and the inlay hints.
+    async fn on_settings_changed(&self) {
+        let errors = self.workspace.write().unwrap().take_settings_errors();
+        self.show_settings_errors(errors).await;
+
+        self.publish_all_diagnostics().await;
+
+        if self.supports_inlay_hint_refresh.load(Ordering::Relaxed) {
+            if let Err(err) = self.client.inlay_hint_refresh().await {
+                error!("Couldn't refresh the inlay hints: {}", err);
+            }
+        }
+    }
+
+    async fn publish_all_diagnostics(&self) {
+        let urls = self.workspace.read().unwrap().get_file_urls();
+
+        for url in urls {
+            let mut diagnostics = {
+                let workspace = self.workspace.read().unwrap();
+                (*workspace).get_full_diagnostics(url.clone())
+            };
+
+            diagnostics.append(&mut self.run_plugin_diagnostics(&url));
+
+            self.client
+                .publish_diagnostics(url, diagnostics, None)
+                .await;
+        }
     }
 }
 
@@ -258,7 +753,9 @@ async fn main() {
     let (service, socket) = LspService::new(|client| Backend {
         client,
         workspace: Workspace::new().into(),
-        plugin_manager: PluginManager::new().into(),
+        plugin_managers: HashMap::new().into(),
+        supports_configuration: AtomicBool::new(false),
+        supports_inlay_hint_refresh: AtomicBool::new(false),
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
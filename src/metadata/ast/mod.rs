@@ -1,4 +1,4 @@
 mod translator;
 mod tree;
 
-pub use tree::{Ast, Node, NodeKind, TypeDecType, VisitNode, Visitable};
+pub use tree::{Ast, Direction, Node, NodeKind, TypeDecType, VisitNode, Visitable};
@@ -86,29 +86,29 @@ impl TreesitterTranslator {
         for child in node.named_children(&mut cursor) {
             let child_node_id =
                 self.arena
-                    .new_node(Node::new(NodeKind::Method, &node, &self.source_code));
+                    .new_node(Node::new(NodeKind::Method, &child, &self.source_code));
             if let Some(annotation) = child.child_by_field_name("annotation") {
-                node_id.append(
+                child_node_id.append(
                     self.parse_annotation(&annotation)
                         .unwrap_or_else(|| self.new_error_node(&annotation)),
                     &mut self.arena,
                 );
             }
-            if let Some(type_node) = node.child_by_field_name("type") {
+            if let Some(type_node) = child.child_by_field_name("type") {
                 child_node_id.append(
                     self.parse_type_ref(&type_node, NodeKind::Type)
                         .unwrap_or_else(|| self.new_error_node(&type_node)),
                     &mut self.arena,
                 );
 
-                if let Some(paramters) = node.child_by_field_name("parameters") {
+                if let Some(paramters) = child.child_by_field_name("parameters") {
                     let params_node_id = self
                         .parse_params(&paramters)
                         .unwrap_or_else(|| self.new_error_node(&paramters));
                     child_node_id.append(params_node_id, &mut self.arena);
                 }
             }
-            if let Some(function) = node.child_by_field_name("function") {
+            if let Some(function) = child.child_by_field_name("function") {
                 child_node_id.append(
                     self.function_prototype(&function)
                         .unwrap_or_else(|| self.new_error_node(&function)),
@@ -1826,9 +1826,11 @@ impl TreesitterTranslator {
         let mut cursor = body_node.walk();
         for body_child in body_node.named_children(&mut cursor) {
             if body_child.kind() == "switch_case" {
-                let label: NodeId =
-                    self.arena
-                        .new_node(Node::new(NodeKind::SwitchLabel, node, &self.source_code));
+                let label: NodeId = self.arena.new_node(Node::new(
+                    NodeKind::SwitchLabel,
+                    &body_child,
+                    &self.source_code,
+                ));
                 let n = body_child.child_by_field_name("name")?;
                 label.append(
                     self.parse_value(&n)
@@ -1836,7 +1838,7 @@ impl TreesitterTranslator {
                     &mut self.arena,
                 );
 
-                if let Some(value_node) = node.child_by_field_name("value") {
+                if let Some(value_node) = body_child.child_by_field_name("value") {
                     label.append(
                         self.parse_block(&value_node)
                             .unwrap_or_else(|| self.new_error_node(&value_node)),
@@ -2107,11 +2109,12 @@ impl TreesitterTranslator {
                         ));
                         action_node_id.append(name_node, &mut self.arena);
 
-                        if let Some(params_syntax_node) = node.child_by_field_name("args") {
+                        if let Some(params_syntax_node) = actions_child.child_by_field_name("args")
+                        {
                             let params_node_id = self
                                 .parse_args(&params_syntax_node)
                                 .unwrap_or_else(|| self.new_error_node(&params_syntax_node));
-                            node_id.append(params_node_id, &mut self.arena);
+                            action_node_id.append(params_node_id, &mut self.arena);
                         }
 
                         actions_node_id.append(action_node_id, &mut self.arena);
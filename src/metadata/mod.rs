@@ -4,8 +4,8 @@ mod st_manager;
 mod symbol_table;
 mod types;
 
-pub use ast::{Ast, Node, NodeKind, VisitNode, Visitable};
+pub use ast::{Ast, Direction, Node, NodeKind, TypeDecType, VisitNode, Visitable};
 pub use ast_manager::{AstEditor, AstManager, AstQuery};
-pub use st_manager::{SymbolTableEdit, SymbolTableEditor, SymbolTableManager, SymbolTableQuery};
-pub use symbol_table::Field;
+pub use st_manager::{SymbolTableEditor, SymbolTableManager, SymbolTableQuery};
 pub use symbol_table::{Symbol, SymbolTable, SymbolTableActions, Symbols};
+pub use types::{BaseType, Type};
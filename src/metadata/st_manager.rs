@@ -1,25 +1,20 @@
+use super::symbol_table::SymbolTable;
 use super::Ast;
-use super::{symbol_table::SymbolTable, Field};
 
 use crate::metadata::{Symbol, Symbols};
-use tower_lsp::lsp_types::Position;
+use tower_lsp::lsp_types::{Position, Range};
 
 use crate::metadata::symbol_table::SymbolTableActions;
 
-#[derive(Debug, Clone)]
-pub enum SymbolTableEdit {
-    Rename { symbol_id: usize, new_name: String },
-}
-
 pub trait SymbolTableEditor {
-    fn new_edit(&mut self, edit: SymbolTableEdit);
     fn update(&mut self, ast: &Ast);
 }
 
 pub trait SymbolTableQuery {
     fn get_symbols_at_pos(&self, position: Position) -> Symbols;
-    fn get_name_field(&self, position: Position, source_code: &str) -> Option<Vec<Field>>;
     fn get_symbol_at_pos(&self, name: String, position: Position) -> Option<&Symbol>;
+    fn get_top_level_symbols(&self) -> Option<Symbols>;
+    fn get_undefined_ranges(&self) -> Vec<Range>;
 }
 
 #[derive(Debug, Clone)]
@@ -44,21 +39,16 @@ impl SymbolTableQuery for SymbolTableManager {
         self.symbol_table.get_symbol_at_pos(name, position)
     }
 
-    fn get_name_field(&self, position: Position, source_code: &str) -> Option<Vec<Field>> {
-        self.symbol_table.get_variable_in_pos(position, source_code)
+    fn get_top_level_symbols(&self) -> Option<Symbols> {
+        self.symbol_table.get_top_level_symbols()
     }
-}
 
-impl SymbolTableEditor for SymbolTableManager {
-    fn new_edit(&mut self, edit: SymbolTableEdit) {
-        match edit {
-            SymbolTableEdit::Rename {
-                symbol_id,
-                new_name,
-            } => self.symbol_table.rename_symbol(symbol_id, new_name.clone()),
-        }
+    fn get_undefined_ranges(&self) -> Vec<Range> {
+        self.symbol_table.get_undefined_ranges()
     }
+}
 
+impl SymbolTableEditor for SymbolTableManager {
     fn update(&mut self, ast: &Ast) {
         *self = SymbolTableManager::new(ast)
     }
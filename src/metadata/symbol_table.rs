@@ -1,4 +1,3 @@
-use crate::utils;
 use std::fmt;
 
 use crate::metadata::ast::{Ast, NodeKind, TypeDecType, VisitNode, Visitable};
@@ -21,11 +20,9 @@ pub struct SymbolTable {
 
 pub trait SymbolTableActions {
     fn get_symbols_in_scope(&self, position: Position) -> Symbols;
-    fn get_variable_in_pos(&self, position: Position, source_code: &str) -> Option<Vec<Field>>;
     fn get_top_level_symbols(&self) -> Option<Symbols>;
     fn get_symbol_at_pos(&self, name: String, position: Position) -> Option<&Symbol>;
     fn get_symbol_at_pos_mut(&mut self, name: String, position: Position) -> Option<&mut Symbol>;
-    fn rename_symbol(&mut self, id: usize, new_name: String);
 }
 
 impl SymbolTableActions for SymbolTable {
@@ -58,103 +55,10 @@ impl SymbolTableActions for SymbolTable {
         return symbols;
     }
 
-    fn get_variable_in_pos(&self, position: Position, source_code_t: &str) -> Option<Vec<Field>> {
-        let mut source_code = source_code_t.to_string();
-        let pos = utils::pos_to_byte(position, &source_code);
-        let _ = source_code.split_off(pos);
-        let mut index = source_code.len();
-        let mut text: String;
-        loop {
-            index -= 1;
-            let chara = source_code.chars().nth(index).unwrap();
-            if !(chara.is_ascii_alphanumeric()
-                || chara == '.'
-                || chara == '_'
-                || chara.is_ascii_whitespace())
-            {
-                text = source_code.split_off(index + 1);
-                break;
-            }
-        }
-        text = text.split_whitespace().collect::<Vec<&str>>().join("");
-        if text.contains(".") {
-            let t: Vec<&str> = source_code.split("\n").collect::<Vec<&str>>();
-            let l = t.len();
-            let position_start = Position {
-                line: l as u32,
-                character: (t[l - 1].len() + 1) as u32,
-            };
-            let names: Vec<&str> = text.split(".").collect();
-
-            let symbols: &Option<&Symbol> =
-                &self.get_symbol_at_pos(names[0].to_string(), position_start);
-            if let Some(mut symbol) = symbols {
-                if let Some(x) = symbol.type_.get_name() {
-                    if x == Type::Name {
-                        let node = symbol.type_.get_node()?;
-                        let name = node.content.clone();
-                        let pos = node.range.start;
-                        match self.get_symbol_at_pos(name, pos) {
-                            Some(x) => {
-                                symbol = x;
-                            }
-                            None => {
-                                return Some(vec![]);
-                            }
-                        }
-                    }
-                }
-                for index_name in 1..(names.len() - 1) {
-                    let test = names[index_name];
-                    let fields = symbol.contains_fields(test.to_string());
-                    if let Some(field) = fields {
-                        if let Some(x) = field.type_.get_name() {
-                            if x == Type::Name {
-                                let node = field.type_.get_node()?;
-                                let name = node.content.clone();
-                                let pos = node.range.start;
-                                match self.get_symbol_at_pos(name, pos) {
-                                    Some(x) => {
-                                        symbol = x;
-                                    }
-                                    None => {
-                                        return Some(vec![]);
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        return Some(vec![]);
-                    }
-                }
-
-                match symbol.get_fields() {
-                    Some(fields) => {
-                        return Some(fields.to_owned());
-                    }
-                    None => {}
-                }
-            }
-
-            return Some(vec![]); //Some(name_fields)
-        } else {
-            return None;
-        }
-    }
-
     fn get_top_level_symbols(&self) -> Option<Symbols> {
         Some(self.arena.get(self.root_id?)?.get().symbols.clone())
     }
 
-    fn rename_symbol(&mut self, id: usize, new_name: String) {
-        for scope in self.arena.iter_mut() {
-            if let Some(symbol) = scope.get_mut().symbols.get_mut(id) {
-                symbol.name = new_name;
-                break;
-            }
-        }
-    }
-
     fn get_symbol_at_pos_mut(&mut self, name: String, position: Position) -> Option<&mut Symbol> {
         let scope_id = self.get_scope_id(position)?;
 
@@ -202,6 +106,12 @@ impl SymbolTable {
         table
     }
 
+    /// The names used but not declared in the document, like those of
+    /// included files.
+    pub fn get_undefined_ranges(&self) -> Vec<Range> {
+        self.undefined_list.clone()
+    }
+
     fn get_scope_id(&self, position: Position) -> Option<NodeId> {
         self._get_scope_id(position, self.root_id?)
     }
@@ -423,31 +333,6 @@ impl Symbols {
 
         None
     }
-
-    pub fn get_mut(&mut self, id: usize) -> Option<&mut Symbol> {
-        for symbol in &mut self.types {
-            if symbol.id == id {
-                return Some(symbol);
-            }
-        }
-        for symbol in &mut self.constants {
-            if symbol.id == id {
-                return Some(symbol);
-            }
-        }
-        for symbol in &mut self.variables {
-            if symbol.id == id {
-                return Some(symbol);
-            }
-        }
-        for symbol in &mut self.functions {
-            if symbol.id == id {
-                return Some(symbol);
-            }
-        }
-
-        None
-    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -581,21 +466,7 @@ impl ScopeSymbolTable {
                                         field_visit.get_child_of_kind(NodeKind::Name).unwrap();
                                     let name = name_node.get().content.clone();
 
-                                    let type_node = field_visit.get_type_node();
-
-                                    let mut node: Option<super::Node> = None;
-                                    let type_ = if let Some(type_node) = type_node {
-                                        node = Some(type_node.get().clone());
-                                        type_node.get_type()
-                                    } else {
-                                        None
-                                    };
-
-                                    fields.push(Field::new(
-                                        name,
-                                        name_node.get().range,
-                                        TypeSymbol::new(type_, node),
-                                    ));
+                                    fields.push(Field::new(name, name_node.get().range));
                                 }
                             }
                             return fields;
@@ -689,7 +560,7 @@ impl ScopeSymbolTable {
                             table.symbols.functions.push(x);
                         } else {
                             if let Some(fn_node) =
-                                root_visit_node.get_child_of_kind(NodeKind::FunctionName)
+                                child_visit_node.get_child_of_kind(NodeKind::FunctionName)
                             {
                                 if let Some(x) = _create_symbol_for_parse(fn_node, NodeKind::Name) {
                                     table.symbols.functions.push(x);
@@ -699,7 +570,7 @@ impl ScopeSymbolTable {
                     }
                     NodeKind::Function => {
                         if let Some(fn_node) =
-                            root_visit_node.get_child_of_kind(NodeKind::FunctionName)
+                            child_visit_node.get_child_of_kind(NodeKind::FunctionName)
                         {
                             if let Some(x) = _create_symbol_for_parse(fn_node, NodeKind::Name) {
                                 table.symbols.functions.push(x);
@@ -757,8 +628,6 @@ impl ScopeSymbolTable {
 pub struct Field {
     name: String,
     def_position: Range,
-    type_: TypeSymbol,
-    usages: Vec<Range>,
 }
 
 #[derive(Debug, Clone)]
@@ -821,10 +690,6 @@ impl Symbol {
         self.id
     }
 
-    pub fn rename(&mut self, new_name: String) {
-        self.name = new_name;
-    }
-
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
@@ -837,8 +702,9 @@ impl Symbol {
         &self.usages
     }
 
-    pub fn get_fields(&self) -> &Option<Vec<Field>> {
-        &self.fields
+    /// The type as written in the declaration, e.g. `bit<32>` or `headers_t`.
+    pub fn get_type_name(&self) -> Option<String> {
+        self.type_.get_node().map(|node| node.content)
     }
 
     pub fn contains_fields(&self, name: String) -> Option<Field> {
@@ -857,13 +723,8 @@ impl Symbol {
 }
 
 impl Field {
-    pub fn new(name: String, def_position: Range, type_: TypeSymbol) -> Field {
-        Field {
-            name,
-            def_position,
-            type_,
-            usages: vec![],
-        }
+    pub fn new(name: String, def_position: Range) -> Field {
+        Field { name, def_position }
     }
 
     pub fn get_name(&self) -> String {
@@ -873,10 +734,6 @@ impl Field {
     pub fn get_definition_range(&self) -> Range {
         self.def_position
     }
-
-    pub fn get_usages(&self) -> &Vec<Range> {
-        &self.usages
-    }
 }
 
 impl TypeSymbol {
@@ -884,10 +741,6 @@ impl TypeSymbol {
         TypeSymbol { name, node }
     }
 
-    pub fn get_name(&self) -> Option<Type> {
-        self.name.clone()
-    }
-
     pub fn get_node(&self) -> Option<super::Node> {
         self.node.clone()
     }
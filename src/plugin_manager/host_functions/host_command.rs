@@ -1,4 +1,7 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock};
 
 use super::HostFunction;
 use extism::{CurrentPlugin, Error, Function, UserData, Val, ValType};
@@ -6,13 +9,52 @@ use serde::{Deserialize, Serialize};
 
 pub struct HostCommand;
 
+// The compiler plugins run, replaced by the configured one.
+const DEFAULT_COMPILER: &str = "p4test";
+
+/// The compiler of the document a plugin runs on, with the flags of its
+/// project and active build profile.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Compiler {
+    pub path: Option<PathBuf>,
+    pub flags: Vec<String>,
+}
+
+impl Compiler {
+    /// Whether the plugin command `command` runs this compiler, by its path,
+    /// its file name or the default compiler's name.
+    fn is_command(&self, command: &str) -> bool {
+        let path = self.path.as_deref().unwrap_or(Path::new(DEFAULT_COMPILER));
+        let name = Path::new(command).file_name();
+        Path::new(command) == path
+            || name == path.file_name()
+            || name == Some(OsStr::new(DEFAULT_COMPILER))
+    }
+
+    /// The program and the arguments running the plugin command `command`:
+    /// the configured compiler with the flags of the project and the active
+    /// profile, if the command runs the compiler.
+    fn get_invocation(&self, command: &[String]) -> Option<(String, Vec<String>)> {
+        let (program, args) = command.split_first()?;
+        if !self.is_command(program) {
+            return Some((program.clone(), args.to_vec()));
+        }
+
+        let program = match &self.path {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => program.clone(),
+        };
+        Some((program, [self.flags.clone(), args.to_vec()].concat()))
+    }
+}
+
 impl HostFunction for HostCommand {
-    fn get() -> Function {
+    fn get(compiler: &Arc<RwLock<Compiler>>) -> Function {
         Function::new(
             "host_command",
             [ValType::I64],
             [ValType::I64],
-            None,
+            Some(UserData::new(compiler.clone())),
             host_command,
         )
     }
@@ -28,7 +70,7 @@ fn host_command(
     plugin: &mut CurrentPlugin,
     inputs: &[Val],
     outputs: &mut [Val],
-    _user_data: UserData,
+    user_data: UserData,
 ) -> Result<(), Error> {
     let offset = if let Val::I64(value) = inputs[0] {
         value as usize
@@ -46,7 +88,16 @@ fn host_command(
 
     let split_command = shell_words::split(command).unwrap();
 
-    let (stdout, stderr) = get_command_output(&split_command[0], &split_command[1..]);
+    let compiler = user_data
+        .any()
+        .and_then(|any| any.downcast_ref::<Arc<RwLock<Compiler>>>())
+        .map(|compiler| compiler.read().unwrap().clone())
+        .unwrap_or_default();
+    let Some((program, args)) = compiler.get_invocation(&split_command) else {
+        return Err(Error::msg("Empty command."));
+    };
+
+    let (stdout, stderr) = get_command_output(&program, &args);
 
     let serialized = serde_json::to_string(&CommandOutput { stdout, stderr })?;
 
@@ -72,3 +123,62 @@ fn get_command_output(command: &str, args: &[String]) -> (Vec<u8>, Vec<u8>) {
         (vec![], vec![])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::Compiler;
+
+    #[test]
+    fn test_is_command() {
+        let compiler = Compiler {
+            path: Some(PathBuf::from("/opt/p4c/bin/p4test")),
+            flags: vec!["-DTOFINO=1".to_string()],
+        };
+        assert!(compiler.is_command("/opt/p4c/bin/p4test"));
+        assert!(compiler.is_command("p4test"));
+        assert!(compiler.is_command("/usr/bin/p4test"));
+        assert!(!compiler.is_command("/opt/p4c/bin/p4test-wrapper"));
+        assert!(!compiler.is_command("p4c-graphs"));
+
+        let renamed = Compiler {
+            path: Some(PathBuf::from("/opt/p4c/bin/p4c-bm2-ss")),
+            flags: vec![],
+        };
+        assert!(renamed.is_command("p4c-bm2-ss"));
+        assert!(renamed.is_command("p4test"));
+
+        let default = Compiler::default();
+        assert!(default.is_command("p4test"));
+        assert!(!default.is_command("p4c"));
+    }
+
+    #[test]
+    fn test_get_invocation() {
+        let compiler = Compiler {
+            path: Some(PathBuf::from("/opt/p4c/bin/p4test")),
+            flags: vec!["-DTOFINO=1".to_string()],
+        };
+        let command =
+            |text: &str| -> Vec<String> { text.split_whitespace().map(str::to_string).collect() };
+
+        // The profile applies to a bare `p4test`, run as the configured one.
+        assert_eq!(
+            compiler.get_invocation(&command("p4test main.p4")),
+            Some((
+                "/opt/p4c/bin/p4test".to_string(),
+                command("-DTOFINO=1 main.p4")
+            ))
+        );
+        assert_eq!(
+            compiler.get_invocation(&command("p4c-graphs main.p4")),
+            Some(("p4c-graphs".to_string(), command("main.p4")))
+        );
+        assert_eq!(
+            Compiler::default().get_invocation(&command("p4test main.p4")),
+            Some(("p4test".to_string(), command("main.p4")))
+        );
+        assert_eq!(compiler.get_invocation(&[]), None);
+    }
+}
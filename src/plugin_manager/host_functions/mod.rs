@@ -1,11 +1,16 @@
+use std::sync::{Arc, RwLock};
+
 use extism::Function;
 
 mod host_command;
 
+pub use host_command::Compiler;
+
 pub trait HostFunction {
-    fn get() -> Function;
+    fn get(compiler: &Arc<RwLock<Compiler>>) -> Function;
 }
 
-lazy_static! {
-    pub static ref FUNCTIONS: Vec<Function> = vec![host_command::HostCommand::get()];
+/// The functions the plugins can call, running commands with `compiler`.
+pub fn get_functions(compiler: &Arc<RwLock<Compiler>>) -> Vec<Function> {
+    vec![host_command::HostCommand::get(compiler)]
 }
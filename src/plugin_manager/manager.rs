@@ -1,60 +1,88 @@
-use super::host_functions::FUNCTIONS;
+use super::host_functions::{self, Compiler};
 use extism::Plugin;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use std::{env, fs};
 use tower_lsp::lsp_types::Diagnostic;
 
 pub struct PluginManager {
     plugins: Vec<Plugin<'static>>,
+    extra_paths: Vec<PathBuf>,
+    // Shared with the host functions of the plugins, set for each run.
+    compiler: Arc<RwLock<Compiler>>,
 }
 
 impl PluginManager {
     pub fn new() -> PluginManager {
         PluginManager {
             plugins: Vec::new(),
+            extra_paths: Vec::new(),
+            compiler: Arc::new(RwLock::new(Compiler::default())),
         }
     }
 
-    pub fn load_plugins(&mut self) {
+    /// Loads the plugins of the user's plugin directory and those listed in
+    /// the settings.
+    pub fn load_plugins(&mut self, extra_paths: &[PathBuf]) {
         info!("Loading plugins");
         self.plugins = Vec::new();
+        self.extra_paths = extra_paths.to_vec();
 
         if let Some(mut home_path) = env::var_os("HOME") {
             home_path.push("/.config/p4_lsp/plugins/");
 
-            let paths = match fs::read_dir(&home_path) {
-                Ok(paths) => paths,
+            match fs::read_dir(&home_path) {
+                Ok(paths) => {
+                    for dir_entry in paths.flatten() {
+                        self.load_plugin(&dir_entry.path());
+                    }
+                }
                 Err(_) => {
                     error!("Couldn't read from plugins path ({:?}).", home_path);
-                    return;
                 }
             };
+        }
 
-            for path in paths {
-                if let Ok(dir_entry) = path {
-                    info!("Loading plugin: {}", dir_entry.path().display());
-                    let file_content = fs::read(dir_entry.path()).unwrap();
-                    let functions = (*FUNCTIONS).clone();
-
-                    match Plugin::create(file_content, functions, true) {
-                        Ok(plugin) => {
-                            self.plugins.push(plugin);
-                        }
-                        Err(err) => {
-                            error!(
-                                "Failed loading plugin: {} Error: {}",
-                                dir_entry.path().display(),
-                                err
-                            );
-                        }
-                    }
-                }
-            }
+        for path in extra_paths {
+            self.load_plugin(path);
         }
 
         info!("Loaded {} plugin(s)", self.plugins.len());
     }
 
-    pub fn run_diagnostic(&mut self, file_path: String) -> Vec<Diagnostic> {
+    /// Reloads the plugins if the ones listed in the settings changed.
+    pub fn update_plugins(&mut self, extra_paths: &[PathBuf]) {
+        if self.extra_paths != extra_paths {
+            self.load_plugins(extra_paths);
+        }
+    }
+
+    fn load_plugin(&mut self, path: &Path) {
+        info!("Loading plugin: {}", path.display());
+        let file_content = match fs::read(path) {
+            Ok(file_content) => file_content,
+            Err(err) => {
+                error!("Failed reading plugin: {} Error: {}", path.display(), err);
+                return;
+            }
+        };
+        let functions = host_functions::get_functions(&self.compiler);
+
+        match Plugin::create(file_content, functions, true) {
+            Ok(plugin) => {
+                self.plugins.push(plugin);
+            }
+            Err(err) => {
+                error!("Failed loading plugin: {} Error: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Runs the plugins on `file_path`, their compiler commands running
+    /// `compiler`.
+    pub fn run_diagnostic(&mut self, file_path: String, compiler: Compiler) -> Vec<Diagnostic> {
+        *self.compiler.write().unwrap() = compiler;
+
         let mut diags = vec![];
         for plugin in &mut self.plugins {
             if plugin.has_function("diagnostic") {
@@ -1,4 +1,5 @@
 mod host_functions;
 mod manager;
 
+pub use host_functions::Compiler;
 pub use manager::PluginManager;
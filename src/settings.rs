@@ -1,10 +1,34 @@
 use serde_json::Value;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString};
 
-#[derive(Debug, Default)]
+pub const CONFIGURATION_SECTION: &str = "p4lsp";
+pub const SWITCH_PROFILE_COMMAND: &str = "p4lsp.switchProfile";
+pub const PROJECT_SETTINGS_FILES: [&str; 4] =
+    [".p4lsp.toml", "p4lsp.toml", "p4lsp.json", ".p4lsp.json"];
+
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    pub defines: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Settings {
     pub include_path: Option<PathBuf>,
+    pub include_paths: Vec<PathBuf>,
     pub p4test_path: Option<PathBuf>,
+    pub compiler_args: Vec<String>,
+    pub target: Option<String>,
+    pub defines: Vec<(String, String)>,
+    pub profiles: HashMap<String, Profile>,
+    pub active_profile: Option<String>,
+    // A `None` severity turns the lint off.
+    pub lints: HashMap<String, Option<DiagnosticSeverity>>,
+    // Inlay hint categories are on unless turned off here.
+    pub inlay_hints: HashMap<String, bool>,
+    pub plugins: Vec<PathBuf>,
 }
 
 impl Settings {
@@ -22,10 +46,84 @@ impl Settings {
                 } else {
                     None
                 };
+            let include_paths: Vec<PathBuf> = parse_strings(map.get("include_paths"))
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
+            let compiler_args = parse_strings(map.get("compiler_args"));
+            let target: Option<String> = if let Some(Value::String(target)) = map.get("target") {
+                Some(target.clone())
+            } else {
+                None
+            };
+            let defines = parse_defines(map.get("defines"));
+            let profiles: HashMap<String, Profile> =
+                if let Some(Value::Object(profiles)) = map.get("profiles") {
+                    profiles
+                        .iter()
+                        .map(|(name, profile)| {
+                            let profile = Profile {
+                                defines: parse_defines(profile.get("defines")),
+                            };
+                            (name.clone(), profile)
+                        })
+                        .collect()
+                } else {
+                    HashMap::new()
+                };
+            let active_profile: Option<String> =
+                if let Some(Value::String(name)) = map.get("active_profile") {
+                    Some(name.clone())
+                } else {
+                    None
+                };
+
+            let lints: HashMap<String, Option<DiagnosticSeverity>> =
+                if let Some(Value::Object(lints)) = map.get("lints") {
+                    lints
+                        .iter()
+                        .filter_map(|(code, severity)| {
+                            let severity = match severity.as_str()? {
+                                "error" => Some(DiagnosticSeverity::ERROR),
+                                "warning" => Some(DiagnosticSeverity::WARNING),
+                                "information" | "info" => Some(DiagnosticSeverity::INFORMATION),
+                                "hint" => Some(DiagnosticSeverity::HINT),
+                                "off" => None,
+                                _ => return None,
+                            };
+                            Some((code.clone(), severity))
+                        })
+                        .collect()
+                } else {
+                    HashMap::new()
+                };
+            let inlay_hints: HashMap<String, bool> = if let Some(Value::Object(inlay_hints)) =
+                map.get("inlay_hints")
+            {
+                inlay_hints
+                    .iter()
+                    .filter_map(|(category, enabled)| Some((category.clone(), enabled.as_bool()?)))
+                    .collect()
+            } else {
+                HashMap::new()
+            };
+            let plugins: Vec<PathBuf> = parse_strings(map.get("plugins"))
+                .into_iter()
+                .map(PathBuf::from)
+                .collect();
 
             Settings {
                 include_path,
+                include_paths,
                 p4test_path,
+                compiler_args,
+                target,
+                defines,
+                profiles,
+                active_profile,
+                lints,
+                inlay_hints,
+                plugins,
             }
         } else {
             Settings {
@@ -33,4 +131,395 @@ impl Settings {
             }
         }
     }
+
+    /// Describes the values `parse` would ignore because they have the wrong
+    /// type, so they can be reported instead of silently dropped.
+    pub fn validate(value: &Value) -> Vec<String> {
+        let map = match value {
+            Value::Object(map) => map,
+            Value::Null => return vec![],
+            _ => return vec!["Settings must be an object.".to_string()],
+        };
+
+        let mut errors = vec![];
+        for (key, value) in map {
+            if value.is_null() {
+                continue;
+            }
+            let valid = match key.as_str() {
+                "include_path" | "p4test_path" | "target" | "active_profile" => value.is_string(),
+                "include_paths" | "compiler_args" | "plugins" => is_string_list(value),
+                "defines" => is_defines(value),
+                "profiles" => value.as_object().is_some_and(|profiles| {
+                    profiles.values().all(|profile| {
+                        profile.is_object() && profile.get("defines").is_none_or(is_defines)
+                    })
+                }),
+                "lints" => value.as_object().is_some_and(|lints| {
+                    lints.values().all(|severity| {
+                        matches!(
+                            severity.as_str(),
+                            Some("error" | "warning" | "information" | "info" | "hint" | "off")
+                        )
+                    })
+                }),
+                "inlay_hints" => value
+                    .as_object()
+                    .is_some_and(|inlay_hints| inlay_hints.values().all(Value::is_boolean)),
+                _ => {
+                    errors.push(format!("Unknown setting `{key}`."));
+                    continue;
+                }
+            };
+
+            if !valid {
+                errors.push(format!("Invalid value for `{key}`: {value}"));
+            }
+        }
+
+        errors
+    }
+
+    /// Reads a project settings file, in TOML or JSON depending on its
+    /// extension. Relative paths in it are relative to the file's directory,
+    /// except for a bare compiler name which is looked up in the `PATH`.
+    pub fn parse_file(path: &Path) -> Result<Settings, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Couldn't read {}: {}", path.display(), err))?;
+        let value: Result<Value, String> = match path.extension() {
+            Some(extension) if extension == "toml" => {
+                toml::from_str(&content).map_err(|err| err.to_string())
+            }
+            _ => serde_json::from_str(&content).map_err(|err| err.to_string()),
+        };
+        let value = value
+            .map_err(|err| format!("Invalid project settings ({}): {}", path.display(), err))?;
+
+        let mut settings = Settings::parse(value);
+        if let Some(directory) = path.parent() {
+            let resolve = |path: &mut PathBuf| *path = directory.join(&*path);
+            settings.include_path.iter_mut().for_each(resolve);
+            settings.include_paths.iter_mut().for_each(resolve);
+            settings
+                .p4test_path
+                .iter_mut()
+                .filter(|path| path.components().count() > 1)
+                .for_each(resolve);
+            settings.plugins.iter_mut().for_each(resolve);
+        }
+
+        Ok(settings)
+    }
+
+    /// Looks for a project settings file in `directory` and its ancestors.
+    pub fn find_project_file(directory: &Path) -> Option<PathBuf> {
+        directory.ancestors().find_map(|ancestor| {
+            PROJECT_SETTINGS_FILES
+                .iter()
+                .map(|name| ancestor.join(name))
+                .find(|path| path.is_file())
+        })
+    }
+
+    /// Combines the client settings with a project's. Project values win,
+    /// except for the active profile which the client can switch.
+    pub fn merge(&self, project: &Settings) -> Settings {
+        let mut profiles = self.profiles.clone();
+        profiles.extend(project.profiles.clone());
+        let mut lints = self.lints.clone();
+        lints.extend(project.lints.clone());
+        let mut inlay_hints = self.inlay_hints.clone();
+        inlay_hints.extend(project.inlay_hints.clone());
+
+        Settings {
+            include_path: project.include_path.clone().or(self.include_path.clone()),
+            include_paths: [self.include_paths.clone(), project.include_paths.clone()].concat(),
+            p4test_path: project.p4test_path.clone().or(self.p4test_path.clone()),
+            compiler_args: [self.compiler_args.clone(), project.compiler_args.clone()].concat(),
+            target: project.target.clone().or(self.target.clone()),
+            defines: [self.defines.clone(), project.defines.clone()].concat(),
+            profiles,
+            active_profile: self
+                .active_profile
+                .clone()
+                .or(project.active_profile.clone()),
+            lints,
+            inlay_hints,
+            plugins: [self.plugins.clone(), project.plugins.clone()].concat(),
+        }
+    }
+
+    pub fn get_include_paths(&self) -> Vec<PathBuf> {
+        self.include_path
+            .iter()
+            .chain(self.include_paths.iter())
+            .cloned()
+            .collect()
+    }
+
+    /// Changes the severity of diagnostics configured in `lints` and removes
+    /// those turned off.
+    pub fn apply_lints(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                if let Some(NumberOrString::String(code)) = &diagnostic.code {
+                    if let Some(severity) = self.lints.get(code) {
+                        diagnostic.severity = Some((*severity)?);
+                    }
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+
+    pub fn is_inlay_hint_enabled(&self, category: &str) -> bool {
+        self.inlay_hints.get(category).copied().unwrap_or(true)
+    }
+
+    /// The global defines followed by those of the active profile, which take
+    /// precedence.
+    pub fn get_active_defines(&self) -> Vec<(String, String)> {
+        let mut defines = self.defines.clone();
+        if let Some(profile) = self
+            .active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+        {
+            defines.retain(|(name, _)| !profile.defines.iter().any(|(n, _)| n == name));
+            defines.extend(profile.defines.iter().cloned());
+        }
+
+        defines
+    }
+
+    pub fn get_compiler_flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self
+            .get_include_paths()
+            .iter()
+            .map(|path| format!("-I{}", path.display()))
+            .collect();
+        flags.extend(
+            self.get_active_defines()
+                .iter()
+                .map(|(name, value)| format!("-D{name}={value}")),
+        );
+        flags.extend(self.compiler_args.iter().cloned());
+
+        flags
+    }
+}
+
+fn is_string_list(value: &Value) -> bool {
+    value
+        .as_array()
+        .is_some_and(|values| values.iter().all(Value::is_string))
+}
+
+fn is_defines(value: &Value) -> bool {
+    is_string_list(value) || value.is_object()
+}
+
+fn parse_strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(|value| value.as_str())
+            .map(String::from)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Accepts either a list of `NAME` or `NAME=VALUE` strings, or an object
+/// mapping names to values. Like `-DNAME`, a define without value is `1`.
+fn parse_defines(value: Option<&Value>) -> Vec<(String, String)> {
+    match value {
+        Some(Value::Array(defines)) => defines
+            .iter()
+            .filter_map(|define| define.as_str())
+            .map(|define| match define.split_once('=') {
+                Some((name, value)) => (name.trim().to_string(), value.trim().to_string()),
+                None => (define.trim().to_string(), "1".to_string()),
+            })
+            .collect(),
+        Some(Value::Object(defines)) => defines
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(value) => value.clone(),
+                    Value::Null => "1".to_string(),
+                    value => value.to_string(),
+                };
+                (name.clone(), value)
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use serde_json::json;
+    use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, Range};
+
+    use super::{parse_defines, Settings};
+
+    fn defines(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_defines() {
+        assert_eq!(
+            parse_defines(Some(&json!(["TOFINO", "PORTS = 64", 3]))),
+            defines(&[("TOFINO", "1"), ("PORTS", "64")])
+        );
+        assert_eq!(
+            parse_defines(Some(&json!({"TOFINO": null, "PORTS": 64, "NAME": "x"}))),
+            defines(&[("NAME", "x"), ("PORTS", "64"), ("TOFINO", "1")])
+        );
+        assert_eq!(parse_defines(Some(&json!("TOFINO"))), vec![]);
+        assert_eq!(parse_defines(None), vec![]);
+    }
+
+    #[test]
+    fn test_get_active_defines() {
+        let settings = Settings::parse(json!({
+            "defines": ["TOFINO", "PORTS=32"],
+            "profiles": {
+                "tofino2": { "defines": { "PORTS": "64", "TOFINO2": null } },
+            },
+        }));
+        assert_eq!(
+            settings.get_active_defines(),
+            defines(&[("TOFINO", "1"), ("PORTS", "32")])
+        );
+
+        let settings = Settings {
+            active_profile: Some("tofino2".to_string()),
+            ..settings
+        };
+        assert_eq!(
+            settings.get_active_defines(),
+            defines(&[("TOFINO", "1"), ("PORTS", "64"), ("TOFINO2", "1")])
+        );
+
+        let settings = Settings {
+            active_profile: Some("missing".to_string()),
+            ..settings
+        };
+        assert_eq!(settings.get_active_defines().len(), 2);
+    }
+
+    #[test]
+    fn test_merge() {
+        let client = Settings::parse(json!({
+            "include_paths": ["/client"],
+            "p4test_path": "/usr/bin/p4test",
+            "target": "psa",
+            "active_profile": "client",
+            "lints": { "literal-overflow": "error", "parameter-order": "hint" },
+        }));
+        let project = Settings::parse(json!({
+            "include_paths": ["/project"],
+            "target": "v1model",
+            "active_profile": "project",
+            "lints": { "literal-overflow": "off" },
+        }));
+
+        let settings = client.merge(&project);
+        assert_eq!(
+            settings.include_paths,
+            vec![PathBuf::from("/client"), PathBuf::from("/project")]
+        );
+        assert_eq!(settings.p4test_path, Some(PathBuf::from("/usr/bin/p4test")));
+        assert_eq!(settings.target.as_deref(), Some("v1model"));
+        // The client switches the profile.
+        assert_eq!(settings.active_profile.as_deref(), Some("client"));
+        assert_eq!(settings.lints["literal-overflow"], None);
+        assert_eq!(
+            settings.lints["parameter-order"],
+            Some(DiagnosticSeverity::HINT)
+        );
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let directory = std::env::temp_dir().join(format!("p4-settings-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let json_file = directory.join("p4lsp.json");
+        fs::write(
+            &json_file,
+            r#"{ "include_paths": ["includes"], "p4test_path": "p4test", "plugins": ["lint.wasm"] }"#,
+        )
+        .unwrap();
+        let settings = Settings::parse_file(&json_file).unwrap();
+        assert_eq!(settings.include_paths, vec![directory.join("includes")]);
+        // A bare compiler name is left to the `PATH` lookup.
+        assert_eq!(settings.p4test_path, Some(PathBuf::from("p4test")));
+        assert_eq!(settings.plugins, vec![directory.join("lint.wasm")]);
+
+        let toml_file = directory.join(".p4lsp.toml");
+        fs::write(
+            &toml_file,
+            "p4test_path = \"bin/p4test\"\ndefines = [\"TOFINO\"]\n\n[lints]\nliteral-overflow = \"off\"\n",
+        )
+        .unwrap();
+        let settings = Settings::parse_file(&toml_file).unwrap();
+        assert_eq!(settings.p4test_path, Some(directory.join("bin/p4test")));
+        assert_eq!(
+            settings.defines,
+            vec![("TOFINO".to_string(), "1".to_string())]
+        );
+        assert_eq!(settings.lints["literal-overflow"], None);
+
+        fs::write(&json_file, "{ invalid").unwrap();
+        assert!(Settings::parse_file(&json_file)
+            .is_err_and(|error| error.starts_with("Invalid project settings")));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_apply_lints() {
+        let settings = Settings::parse(json!({
+            "lints": { "literal-overflow": "error", "parameter-order": "off" },
+        }));
+        let diagnostic = |code: &str| Diagnostic {
+            code: Some(NumberOrString::String(code.to_string())),
+            severity: Some(DiagnosticSeverity::WARNING),
+            ..Diagnostic::new_simple(Range::default(), String::new())
+        };
+
+        let diagnostics = settings.apply_lints(vec![
+            diagnostic("literal-overflow"),
+            diagnostic("parameter-order"),
+            diagnostic("unbound-parameters"),
+        ]);
+        let severities: Vec<(Option<NumberOrString>, Option<DiagnosticSeverity>)> = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.severity))
+            .collect();
+        assert_eq!(
+            severities,
+            vec![
+                (
+                    Some(NumberOrString::String("literal-overflow".to_string())),
+                    Some(DiagnosticSeverity::ERROR)
+                ),
+                (
+                    Some(NumberOrString::String("unbound-parameters".to_string())),
+                    Some(DiagnosticSeverity::WARNING)
+                ),
+            ]
+        );
+    }
 }
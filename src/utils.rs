@@ -1,6 +1,10 @@
-use tower_lsp::lsp_types::Position;
+use std::path::{Component, Path, PathBuf};
+
+use tower_lsp::lsp_types::{Position, Range};
 use tree_sitter::Point;
 
+use crate::metadata::{NodeKind, Type, VisitNode, Visitable};
+
 pub fn pos_to_point(pos: Position) -> Point {
     Point {
         row: pos.line as usize,
@@ -56,11 +60,267 @@ pub fn get_node_text(node: &tree_sitter::Node, source_code: &str) -> String {
     node.utf8_text(source_code.as_bytes()).unwrap().to_string()
 }
 
+/// `path` relative to the `directory`, going up with `..` where needed.
+pub fn get_relative_path(directory: &Path, path: &Path) -> PathBuf {
+    let directory: Vec<Component> = directory.components().collect();
+    let path: Vec<Component> = path.components().collect();
+    let common = directory
+        .iter()
+        .zip(path.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..directory.len() {
+        relative.push("..");
+    }
+    relative.extend(&path[common..]);
+    relative
+}
+
+/// The comment right above `line`, either `//` lines or a `/* */` block,
+/// without the comment markers.
+pub fn get_doc_comment(source_code: &str, line: usize) -> Option<String> {
+    let lines: Vec<&str> = source_code.lines().take(line).map(str::trim).collect();
+    let mut comment: Vec<String> = vec![];
+
+    if lines.last()?.ends_with("*/") {
+        for line in lines.iter().rev() {
+            let text = line.trim_end_matches("*/");
+            let start = text.find("/*");
+            let text = match start {
+                Some(start) => &text[start + 2..],
+                None => text,
+            };
+            comment.push(text.trim_start_matches('*').trim().to_string());
+            if start.is_some() {
+                break;
+            }
+        }
+    } else {
+        for line in lines.iter().rev() {
+            match line.strip_prefix("//") {
+                Some(text) => comment.push(text.trim_start_matches('/').trim().to_string()),
+                None => break,
+            }
+        }
+    }
+
+    comment.reverse();
+    let comment = comment.join("\n").trim().to_string();
+    if comment.is_empty() {
+        None
+    } else {
+        Some(comment)
+    }
+}
+
+/// The text without whitespace, to compare names and expressions however
+/// they are spaced.
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace().collect()
+}
+
+/// The name of the declaration `node`, from its `Name` child.
+pub fn get_name(node: &VisitNode) -> Option<String> {
+    Some(normalize(
+        &node.get_child_of_kind(NodeKind::Name)?.get().content,
+    ))
+}
+
+/// The member accesses in the expression `value`, like `hdr.ipv4.ttl` or
+/// `ipv4_lpm.apply().hit`, as their names with their ranges. Arguments of
+/// calls aren't part of the AST's values.
+pub fn get_value_paths(value: &VisitNode) -> Vec<Vec<(String, Range)>> {
+    let node = value.get();
+    let mut names: Vec<(String, Range)> = value
+        .get_descendants()
+        .iter()
+        .filter(|descendant| {
+            matches!(
+                descendant.get().kind,
+                NodeKind::Type(Type::Name) | NodeKind::ValueSymbol
+            )
+        })
+        .map(|name| (normalize(&name.get().content), name.get().range))
+        .collect();
+    names.sort_by_key(|(_, range)| range.start);
+
+    let mut paths: Vec<Vec<(String, Range)>> = vec![];
+    let mut depths: Vec<usize> = vec![];
+    for (name, range) in names {
+        let offset = get_offset(&node.content, node.range.start, range.start);
+        let depth = get_depth(node.content.get(..offset).unwrap_or_default());
+
+        // A member follows its receiver, after its call arguments or index,
+        // at the same nesting depth.
+        let receiver = depths.iter().rposition(|&other| other == depth);
+        let is_member = receiver
+            .and_then(|index| paths[index].last())
+            .is_some_and(|last| {
+                let start = get_offset(&node.content, node.range.start, last.1.end);
+                let between = normalize(node.content.get(start..offset).unwrap_or_default());
+                between.strip_suffix('.').is_some_and(is_groups)
+            });
+        match receiver {
+            Some(index) if is_member => paths[index].push((name, range)),
+            _ => {
+                paths.push(vec![(name, range)]);
+                depths.push(depth);
+            }
+        }
+    }
+
+    paths
+}
+
+/// The number of parentheses and brackets left open at the end of `text`.
+pub fn get_depth(text: &str) -> usize {
+    let mut depth: usize = 0;
+    for c in text.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    depth
+}
+
+// Whether `text` is only parenthesized or bracketed groups, like `()` or
+// `(x)[0]`.
+fn is_groups(text: &str) -> bool {
+    let mut depth = 0;
+    for c in text.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ if depth == 0 => return false,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+/// The names of the assigned or called expression of a statement, like
+/// `hdr`, `ipv4` and `ttl` for `hdr.ipv4.ttl = 0;`, with their ranges.
+pub fn get_lvalue_path(name_statement: &VisitNode) -> Vec<(String, Range)> {
+    fn add_members(node: &VisitNode, path: &mut Vec<(String, Range)>) {
+        let access = node.get_children().into_iter().find(|child| {
+            matches!(
+                child.get().kind,
+                NodeKind::StatementDot | NodeKind::StatementExpr | NodeKind::StatementDouble
+            )
+        });
+        if let Some(access) = access {
+            if let Some(member) = access.get_value_symbol_node() {
+                path.push((normalize(&member.get().content), member.get().range));
+            }
+            add_members(&access, path);
+        }
+    }
+
+    let mut path = vec![];
+    if let Some(root) = name_statement.get_type_node() {
+        path.push((normalize(&root.get().content), root.get().range));
+        add_members(&root, &mut path);
+    }
+
+    path
+}
+
+/// The byte offset of `position` in the node `content` starting at `start`.
+pub fn get_offset(content: &str, start: Position, position: Position) -> usize {
+    let relative = if position.line == start.line {
+        Position::new(0, position.character.saturating_sub(start.character))
+    } else {
+        Position::new(position.line.saturating_sub(start.line), position.character)
+    };
+    if relative.line as usize >= content.lines().count().max(1) {
+        return content.len();
+    }
+    pos_to_byte(relative, content).min(content.len())
+}
+
+/// The whitespace `node` is indented with, read from the text of `parent`,
+/// or spaces up to its column when it doesn't start a line of `parent`.
+pub fn get_indentation(parent: &VisitNode, node: &VisitNode) -> String {
+    let content = &parent.get().content;
+    let start = node.get().range.start;
+    let offset = get_offset(content, parent.get().range.start, start);
+
+    let line = content[..offset]
+        .rfind('\n')
+        .map(|index| &content[index + 1..offset]);
+    match line {
+        Some(line) if line.trim().is_empty() => line.to_string(),
+        _ => " ".repeat(start.character as usize),
+    }
+}
+
+/// The parts of `text` between the `separator`s that aren't nested in
+/// parentheses, braces or brackets.
+pub fn split_top_level(text: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut depth = 0;
+
+    for c in text.chars() {
+        match c {
+            '(' | '{' | '[' => depth += 1,
+            ')' | '}' | ']' => depth -= 1,
+            _ => {}
+        }
+        if c == separator && depth == 0 {
+            parts.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    parts.push(current);
+
+    parts
+}
+
+#[cfg(test)]
+pub fn parse(source_code: &str) -> tree_sitter::Tree {
+    let mut parser = tree_sitter::Parser::new();
+    parser.set_language(tree_sitter_p4::language()).unwrap();
+    parser.parse(source_code, None).unwrap()
+}
+
+/// A document of `source_code`, with its AST and symbol table.
+#[cfg(test)]
+pub fn parse_file(source_code: &str) -> crate::file::File {
+    crate::file::File::new(
+        tower_lsp::lsp_types::Url::parse("file:///test.p4").unwrap(),
+        source_code,
+        &Some(parse(source_code)),
+    )
+}
+
+#[cfg(test)]
+pub fn apply_edits(source_code: &str, edits: &[tower_lsp::lsp_types::TextEdit]) -> String {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| edit.range.start);
+
+    let mut result = source_code.to_string();
+    for edit in edits.iter().rev() {
+        let start = pos_to_byte(edit.range.start, source_code);
+        let end = pos_to_byte(edit.range.end, source_code);
+        result.replace_range(start..end, &edit.new_text);
+    }
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use tower_lsp::lsp_types::Position;
 
-    use super::pos_to_byte;
+    use super::{get_doc_comment, pos_to_byte, split_top_level};
 
     #[test]
     fn test_pos_to_byte() {
@@ -87,4 +347,28 @@ mod tests {
             5
         );
     }
+
+    #[test]
+    fn test_get_doc_comment() {
+        let string = "// Ethernet header\n// from IEEE 802.3\nheader ethernet_t {}\n\n/**\n * Parses packets.\n */\nparser P();\nconst bit<8> A = 1;";
+
+        assert_eq!(
+            get_doc_comment(string, 2).as_deref(),
+            Some("Ethernet header\nfrom IEEE 802.3")
+        );
+        assert_eq!(
+            get_doc_comment(string, 7).as_deref(),
+            Some("Parses packets.")
+        );
+        assert_eq!(get_doc_comment(string, 8), None);
+    }
+
+    #[test]
+    fn test_split_top_level() {
+        assert_eq!(
+            split_top_level("a, f(b, c), {d, e}", ','),
+            vec!["a", " f(b, c)", " {d, e}"]
+        );
+        assert_eq!(split_top_level("", ','), vec![""]);
+    }
 }
@@ -1,17 +1,30 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use serde_json::Value;
 use tower_lsp::lsp_types::{
-    CompletionItem, Diagnostic, HoverContents, Location, Position, SemanticTokensResult,
-    TextDocumentContentChangeEvent, Url, WorkspaceEdit,
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, CodeActionContext,
+    CodeActionOrCommand, CompletionItem, Diagnostic, DocumentHighlight, Documentation,
+    FoldingRange, FormattingOptions, HoverContents, InlayHint, Location, MarkupContent, MarkupKind,
+    Position, PrepareRenameResponse, Range, SelectionRange, SemanticTokensResult, SignatureHelp,
+    TextDocumentContentChangeEvent, TextEdit, Url, WorkspaceEdit,
 };
 use tree_sitter::Parser;
 use tree_sitter_p4::language;
 
+use crate::features::completion::CompletionData;
+use crate::features::rename;
+use crate::metadata::Symbol;
 use crate::{file::File, settings::Settings};
 
 pub struct Workspace {
     settings: Settings,
+    folders: Vec<Url>,
+    folder_settings: HashMap<Url, Settings>,
+    project_settings: HashMap<PathBuf, Settings>,
+    active_profile: Option<String>,
+    // Project settings files that couldn't be read, not yet reported.
+    settings_errors: Vec<String>,
     files: HashMap<Url, File>,
     parser: Parser,
 }
@@ -23,6 +36,11 @@ impl Workspace {
 
         Workspace {
             settings: Settings::default(),
+            folders: Vec::new(),
+            folder_settings: HashMap::new(),
+            project_settings: HashMap::new(),
+            active_profile: None,
+            settings_errors: Vec::new(),
             files: HashMap::new(),
             parser,
         }
@@ -31,8 +49,10 @@ impl Workspace {
     pub fn add_file(&mut self, url: Url, content: &str) {
         let tree = self.parser.parse(content, None);
 
-        self.files
-            .insert(url.clone(), File::new(url, content, &tree));
+        let mut file = File::new(url.clone(), content, &tree);
+        file.update_settings(self.get_file_settings(&url));
+
+        self.files.insert(url, file);
     }
 
     pub fn update_file(&mut self, url: Url, changes: Vec<TextDocumentContentChangeEvent>) {
@@ -44,18 +64,199 @@ impl Workspace {
     pub fn get_definition_location(&self, url: Url, symbol_position: Position) -> Option<Location> {
         let file = self.files.get(&url)?;
 
-        file.get_definition_location(symbol_position)
+        // Names not declared in the document may be top-level declarations of
+        // an included one.
+        file.get_definition_location(symbol_position).or_else(|| {
+            let name = file.get_word(symbol_position)?;
+            self.get_visible_files(&url)
+                .into_iter()
+                .skip(1)
+                .find_map(|(url, file)| {
+                    let symbol = file.get_top_level_symbol(&name)?;
+                    Some(Location::new(url.clone(), symbol.get_definition_range()))
+                })
+        })
+    }
+
+    pub fn get_type_definition_location(&self, url: Url, position: Position) -> Option<Location> {
+        let file = self.files.get(&url)?;
+        let type_name = file.get_type_name(position)?;
+
+        self.get_visible_files(&url)
+            .into_iter()
+            .find_map(|(_, file)| file.find_type_declaration(&type_name))
+    }
+
+    /// The declaration of the extern method or function called at `position`,
+    /// or else the definition of the symbol.
+    pub fn get_declaration_location(&self, url: Url, position: Position) -> Option<Location> {
+        let file = self.files.get(&url)?;
+
+        if let Some((extern_name, name)) = file.get_callee(position) {
+            let location = self
+                .get_visible_files(&url)
+                .into_iter()
+                .find_map(|(_, file)| file.find_method_declaration(extern_name.as_deref(), &name));
+            if location.is_some() {
+                return location;
+            }
+        }
+
+        file.get_definition_location(position)
+    }
+
+    /// The parsers and controls implementing the parser or control type at
+    /// `position`, or the instantiations of the package.
+    pub fn get_implementation_locations(&self, url: Url, position: Position) -> Vec<Location> {
+        let Some(name) = self
+            .files
+            .get(&url)
+            .and_then(|file| file.get_word(position))
+        else {
+            return vec![];
+        };
+        let Some((declaring_url, prototype)) = self
+            .get_visible_files(&url)
+            .into_iter()
+            .find_map(|(url, file)| Some((url.clone(), file.get_prototype(&name)?)))
+        else {
+            return vec![];
+        };
+        let declaring_path = declaring_url.to_file_path().ok();
+
+        self.files
+            .iter()
+            .filter(|(url, file)| {
+                **url == declaring_url
+                    || declaring_path
+                        .as_ref()
+                        .is_some_and(|path| file.preprocessor.is_included(path))
+            })
+            .flat_map(|(_, file)| file.get_implementations(&prototype))
+            .collect()
     }
 
+    /// The document and the documents it includes, whose declarations it
+    /// sees.
+    fn get_visible_files(&self, url: &Url) -> Vec<(&Url, &File)> {
+        let Some((url, file)) = self.files.get_key_value(url) else {
+            return vec![];
+        };
+
+        let mut files = vec![(url, file)];
+        files.extend(self.files.iter().filter(|(other_url, _)| {
+            *other_url != url
+                && other_url
+                    .to_file_path()
+                    .is_ok_and(|path| file.preprocessor.is_included(&path))
+        }));
+        files
+    }
+
+    pub fn get_references(
+        &self,
+        url: Url,
+        symbol_position: Position,
+        include_declaration: bool,
+    ) -> Option<Vec<Location>> {
+        let file = self.files.get(&url)?;
+
+        file.get_references(symbol_position, include_declaration)
+    }
+
+    pub fn get_highlights(&self, url: Url, position: Position) -> Option<Vec<DocumentHighlight>> {
+        let file = self.files.get(&url)?;
+
+        file.get_highlights(position)
+    }
+
+    pub fn prepare_rename(
+        &self,
+        url: Url,
+        position: Position,
+    ) -> Result<Option<PrepareRenameResponse>, String> {
+        let Some(file) = self.files.get(&url) else {
+            return Ok(None);
+        };
+        let Some((name, range)) = file.get_renameable_name(position)? else {
+            return Ok(None);
+        };
+        if self.find_symbol(&url, &name, position).is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+            range,
+            placeholder: name,
+        }))
+    }
+
+    /// Renames the symbol at `position` in its document and, for a top-level
+    /// declaration, in the documents including it.
     pub fn rename_symbol(
-        &mut self,
+        &self,
         url: Url,
         symbol_position: Position,
         new_name: String,
-    ) -> Option<WorkspaceEdit> {
-        let file = self.files.get_mut(&url).unwrap();
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        let Some(file) = self.files.get(&url) else {
+            return Ok(None);
+        };
+        let Some((name, _)) = file.get_renameable_name(symbol_position)? else {
+            return Ok(None);
+        };
+        rename::validate_name(&new_name)?;
+        let Some((owner_url, symbol)) = self.find_symbol(&url, &name, symbol_position) else {
+            return Ok(None);
+        };
+        if new_name == name {
+            return Ok(None);
+        }
+
+        let owner = &self.files[&owner_url];
+        let mut changes =
+            HashMap::from([(owner_url.clone(), owner.rename_symbol(&symbol, &new_name)?)]);
+
+        let is_top_level = owner
+            .get_top_level_symbol(&name)
+            .is_some_and(|top_level| top_level.get_id() == symbol.get_id());
+        if let (true, Ok(path)) = (is_top_level, owner_url.to_file_path()) {
+            for (other_url, other) in &self.files {
+                if *other_url == owner_url || !other.preprocessor.is_included(&path) {
+                    continue;
+                }
+                if other.get_top_level_symbol(&new_name).is_some() {
+                    return Err(format!(
+                        "`{new_name}` is already declared in {}.",
+                        other_url.path()
+                    ));
+                }
+
+                let edits = other.rename_external(&name, &new_name)?;
+                if !edits.is_empty() {
+                    changes.insert(other_url.clone(), edits);
+                }
+            }
+        }
+
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+
+    /// The symbol `name` used at `position` and the document declaring it,
+    /// either the document itself or one it includes.
+    fn find_symbol(&self, url: &Url, name: &str, position: Position) -> Option<(Url, Symbol)> {
+        let file = self.files.get(url)?;
+        if let Some(symbol) = file.get_symbol(name, position) {
+            return Some((url.clone(), symbol));
+        }
 
-        file.rename_symbol(symbol_position, new_name)
+        self.files.iter().find_map(|(other_url, other)| {
+            let path = other_url.to_file_path().ok()?;
+            if other_url == url || !file.preprocessor.is_included(&path) {
+                return None;
+            }
+            Some((other_url.clone(), other.get_top_level_symbol(name)?))
+        })
     }
 
     pub fn get_semantic_tokens(&self, url: Url) -> Option<SemanticTokensResult> {
@@ -67,7 +268,64 @@ impl Workspace {
     pub fn get_completion(&self, url: Url, position: Position) -> Option<Vec<CompletionItem>> {
         let file = self.files.get(&url)?;
 
-        file.get_completion_list(position)
+        // Declarations of the other documents, resolved with an `#include`
+        // when needed. Those including the document would include each other.
+        let path = url.to_file_path().ok();
+        let external: Vec<CompletionItem> = self
+            .files
+            .iter()
+            .filter(|(other_url, other)| {
+                **other_url != url
+                    && !path
+                        .as_ref()
+                        .is_some_and(|path| other.preprocessor.is_included(path))
+            })
+            .flat_map(|(_, other)| other.get_exported_items(&url))
+            .collect();
+
+        file.get_completion_list(position, &external)
+    }
+
+    /// Adds the declaration preview, documentation and type to the item and,
+    /// for a symbol of another document, the edit including it.
+    pub fn resolve_completion(&self, mut item: CompletionItem) -> CompletionItem {
+        let Some(data) = item
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<CompletionData>(data).ok())
+        else {
+            return item;
+        };
+        let Some(file) = self.files.get(&data.uri) else {
+            return item;
+        };
+
+        if let Some(declaration) = file.get_declaration(&item.label, data.position) {
+            let mut value = format!("```p4\n{}\n```", declaration.preview);
+            if let Some(documentation) = declaration.documentation {
+                value.push_str(&format!("\n\n{documentation}"));
+            }
+            if data.uri != data.document {
+                value.push_str(&format!("\n\nFrom `{}`", data.uri.path()));
+            }
+            item.documentation = Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }));
+            if item.detail.is_none() {
+                item.detail = declaration.type_name;
+            }
+        }
+
+        if data.uri != data.document {
+            let edit = match (self.files.get(&data.document), data.uri.to_file_path()) {
+                (Some(document), Ok(path)) => document.get_include_edit(&path),
+                _ => None,
+            };
+            item.additional_text_edits = edit.map(|edit| vec![edit]);
+        }
+
+        item
     }
 
     pub fn get_hover_info(&self, url: Url, position: Position) -> Option<HoverContents> {
@@ -76,11 +334,118 @@ impl Workspace {
         file.get_hover_info(position)
     }
 
+    pub fn get_signature_help(&self, url: Url, position: Position) -> Option<SignatureHelp> {
+        let file = self.files.get(&url)?;
+
+        file.get_signature_help(position)
+    }
+
+    pub fn get_folding_ranges(&self, url: Url) -> Option<Vec<FoldingRange>> {
+        let file = self.files.get(&url)?;
+
+        Some(file.get_folding_ranges())
+    }
+
+    pub fn get_selection_ranges(
+        &self,
+        url: Url,
+        positions: &[Position],
+    ) -> Option<Vec<SelectionRange>> {
+        let file = self.files.get(&url)?;
+
+        file.get_selection_ranges(positions)
+    }
+
+    pub fn get_code_actions(
+        &self,
+        url: Url,
+        range: Range,
+        context: &CodeActionContext,
+    ) -> Option<Vec<CodeActionOrCommand>> {
+        let file = self.files.get(&url)?;
+
+        Some(file.get_code_actions(range, context, self.is_included(&url)))
+    }
+
+    pub fn prepare_call_hierarchy(
+        &self,
+        url: Url,
+        position: Position,
+    ) -> Option<Vec<CallHierarchyItem>> {
+        let file = self.files.get(&url)?;
+
+        file.prepare_call_hierarchy(position)
+    }
+
+    pub fn get_incoming_calls(
+        &self,
+        item: &CallHierarchyItem,
+    ) -> Option<Vec<CallHierarchyIncomingCall>> {
+        let file = self.files.get(&item.uri)?;
+
+        Some(file.get_incoming_calls(item))
+    }
+
+    pub fn get_outgoing_calls(
+        &self,
+        item: &CallHierarchyItem,
+    ) -> Option<Vec<CallHierarchyOutgoingCall>> {
+        let file = self.files.get(&item.uri)?;
+
+        Some(file.get_outgoing_calls(item))
+    }
+
+    pub fn format(&self, url: Url, options: &FormattingOptions) -> Option<Vec<TextEdit>> {
+        let file = self.files.get(&url)?;
+
+        file.format(options)
+    }
+
+    pub fn format_range(
+        &self,
+        url: Url,
+        range: Range,
+        options: &FormattingOptions,
+    ) -> Option<Vec<TextEdit>> {
+        let file = self.files.get(&url)?;
+
+        file.format_range(range, options)
+    }
+
+    pub fn format_on_type(
+        &self,
+        url: Url,
+        position: Position,
+        character: &str,
+        options: &FormattingOptions,
+    ) -> Option<Vec<TextEdit>> {
+        let file = self.files.get(&url)?;
+
+        file.format_on_type(position, character, options)
+    }
+
+    pub fn get_inlay_hints(&self, url: Url, range: Range) -> Vec<InlayHint> {
+        match self.files.get(&url) {
+            Some(file) => file.get_inlay_hints(range),
+            None => vec![],
+        }
+    }
+
+    /// Whether another open document includes the document `url`.
+    fn is_included(&self, url: &Url) -> bool {
+        let Ok(path) = url.to_file_path() else {
+            return false;
+        };
+        self.files
+            .iter()
+            .any(|(other_url, file)| other_url != url && file.preprocessor.is_included(&path))
+    }
+
     pub fn get_quick_diagnostics(&self, url: Url) -> Vec<Diagnostic> {
         let maybe_file = self.files.get(&url);
 
         if let Some(file) = maybe_file {
-            file.get_quick_diagnostics()
+            file.get_quick_diagnostics(self.is_included(&url))
         } else {
             vec![]
         }
@@ -90,14 +455,362 @@ impl Workspace {
         let maybe_file = self.files.get(&url);
 
         if let Some(file) = maybe_file {
-            file.get_full_diagnostics()
+            file.get_full_diagnostics(self.is_included(&url))
         } else {
             vec![]
         }
     }
 
-    pub fn update_settings(&mut self, settings: Value) {
+    pub fn get_control_graph(
+        &self,
+        url: Url,
+        control_name: Option<&str>,
+        format: &str,
+    ) -> Result<Value, String> {
+        let Some(file) = self.files.get(&url) else {
+            return Err(format!("Unknown document `{url}`."));
+        };
+        let graphs = file.get_control_graphs(control_name);
+
+        match format {
+            "dot" => Ok(Value::String(
+                graphs
+                    .iter()
+                    .map(|graph| graph.to_dot())
+                    .collect::<String>(),
+            )),
+            "json" => Ok(Value::Array(
+                graphs.iter().map(|graph| graph.to_json()).collect(),
+            )),
+            _ => Err(format!(
+                "Unknown control graph format `{format}`, expected `json` or `dot`."
+            )),
+        }
+    }
+
+    pub fn get_folders(&self) -> Vec<Url> {
+        self.folders.clone()
+    }
+
+    pub fn update_folders(&mut self, added: Vec<Url>, removed: Vec<Url>) {
+        self.folders.retain(|folder| !removed.contains(folder));
+        self.folder_settings
+            .retain(|folder, _| !removed.contains(folder));
+        for folder in added {
+            if !self.folders.contains(&folder) {
+                self.folders.push(folder);
+            }
+        }
+    }
+
+    /// Replaces the client settings, the global ones and those scoped to each
+    /// workspace folder.
+    pub fn update_settings(&mut self, settings: Value, folder_settings: Vec<(Url, Value)>) {
         self.settings = Settings::parse(settings);
         info!("Settings: {:?}", self.settings);
+
+        self.folder_settings = folder_settings
+            .into_iter()
+            .map(|(folder, settings)| (folder, Settings::parse(settings)))
+            .collect();
+        for (folder, settings) in &self.folder_settings {
+            info!("Settings for {}: {:?}", folder, settings);
+        }
+
+        self.update_file_settings();
+    }
+
+    pub fn set_active_profile(&mut self, name: &str) -> bool {
+        let exists = self.settings.profiles.contains_key(name)
+            || self
+                .files
+                .values()
+                .any(|file| file.settings.profiles.contains_key(name));
+        if !exists {
+            return false;
+        }
+        self.active_profile = Some(name.to_string());
+        info!("Active profile: {}", name);
+
+        self.update_file_settings();
+        true
+    }
+
+    /// The errors in the project settings files read since the last call.
+    pub fn take_settings_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.settings_errors)
+    }
+
+    /// Rereads the project settings files, after one of them changed.
+    pub fn reload_project_settings(&mut self) {
+        self.project_settings.clear();
+        self.update_file_settings();
+    }
+
+    pub fn get_compiler(&self, url: &Url) -> (Option<PathBuf>, Vec<String>) {
+        let settings = match self.files.get(url) {
+            Some(file) => &file.settings,
+            None => &self.settings,
+        };
+
+        (settings.p4test_path.clone(), settings.get_compiler_flags())
+    }
+
+    /// The root of the project the file is in, the directory of its settings
+    /// file, with the plugins configured for the file.
+    pub fn get_plugins(&self, url: &Url) -> (Option<PathBuf>, Vec<PathBuf>) {
+        let project_root = url
+            .to_file_path()
+            .ok()
+            .and_then(|path| Settings::find_project_file(path.parent()?))
+            .and_then(|project_file| Some(project_file.parent()?.to_path_buf()));
+        let settings = match self.files.get(url) {
+            Some(file) => &file.settings,
+            None => &self.settings,
+        };
+
+        (project_root, settings.plugins.clone())
+    }
+
+    pub fn get_file_urls(&self) -> Vec<Url> {
+        self.files.keys().cloned().collect()
+    }
+
+    fn update_file_settings(&mut self) {
+        let urls = self.get_file_urls();
+        for url in urls {
+            let settings = self.get_file_settings(&url);
+            if let Some(file) = self.files.get_mut(&url) {
+                file.update_settings(settings);
+            }
+        }
+    }
+
+    /// The client settings of the file's workspace folder merged with those
+    /// of the project the file is in.
+    fn get_file_settings(&mut self, url: &Url) -> Settings {
+        let client_settings = self
+            .folders
+            .iter()
+            .filter(|folder| {
+                let folder = folder.as_str().trim_end_matches('/');
+                url.as_str().starts_with(&format!("{folder}/"))
+            })
+            .max_by_key(|folder| folder.as_str().len())
+            .and_then(|folder| self.folder_settings.get(folder))
+            .unwrap_or(&self.settings)
+            .clone();
+
+        let project_file = url
+            .to_file_path()
+            .ok()
+            .and_then(|path| Settings::find_project_file(path.parent()?));
+
+        let mut settings = match project_file {
+            Some(project_file) => {
+                if !self.project_settings.contains_key(&project_file) {
+                    info!("Loading project settings: {}", project_file.display());
+                    let project_settings = match Settings::parse_file(&project_file) {
+                        Ok(project_settings) => project_settings,
+                        Err(error) => {
+                            self.settings_errors.push(error);
+                            Settings::default()
+                        }
+                    };
+                    self.project_settings
+                        .insert(project_file.clone(), project_settings);
+                }
+                client_settings.merge(&self.project_settings[&project_file])
+            }
+            None => client_settings,
+        };
+
+        // A profile switched to with the command overrides the settings.
+        if self.active_profile.is_some() {
+            settings.active_profile = self.active_profile.clone();
+        }
+
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use serde_json::json;
+    use tower_lsp::lsp_types::{
+        CompletionItem, Documentation, MarkupKind, Position, Range, TextEdit, Url,
+    };
+
+    use super::Workspace;
+
+    /// A workspace of `main.p4`, a document including it, one beside it and
+    /// one in an include path, and the directory holding them.
+    fn new_workspace(name: &str) -> (Workspace, std::path::PathBuf) {
+        let directory = std::env::temp_dir().join(format!("p4-{name}-{}", std::process::id()));
+        let include_path = directory.join("lib");
+        std::fs::create_dir_all(&include_path).unwrap();
+
+        let documents = [
+            (
+                include_path.join("util.p4"),
+                "/// Drops the packet.\naction drop_packet() {}\n",
+            ),
+            (directory.join("common.p4"), "action forward() {}\n"),
+            (
+                directory.join("consumer.p4"),
+                "#include \"main.p4\"\naction consume() {}\n",
+            ),
+            (
+                directory.join("main.p4"),
+                "#include <core.p4>\ncontrol C() {\n    apply {  }\n}\n",
+            ),
+        ];
+
+        let mut workspace = Workspace::new();
+        workspace.update_settings(json!({ "include_paths": [include_path] }), vec![]);
+        for (path, content) in documents {
+            std::fs::write(&path, content).unwrap();
+            workspace.add_file(Url::from_file_path(&path).unwrap(), content);
+        }
+        (workspace, directory)
+    }
+
+    fn get_item(workspace: &Workspace, directory: &Path, label: &str) -> Option<CompletionItem> {
+        let url = Url::from_file_path(directory.join("main.p4")).unwrap();
+        workspace
+            .get_completion(url, Position::new(2, 12))?
+            .into_iter()
+            .find(|item| item.label == label)
+    }
+
+    #[test]
+    fn test_completion_skips_including_documents() {
+        let (workspace, directory) = new_workspace("completion");
+
+        assert!(get_item(&workspace, &directory, "drop_packet").is_some());
+        assert!(get_item(&workspace, &directory, "forward").is_some());
+        assert!(get_item(&workspace, &directory, "consume").is_none());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_completion() {
+        let (workspace, directory) = new_workspace("resolve");
+        let resolve = |label| {
+            let item = get_item(&workspace, &directory, label).unwrap();
+            workspace.resolve_completion(item)
+        };
+        let include_edit = |text: &str| {
+            let position = Position::new(1, 0);
+            Some(vec![TextEdit::new(
+                Range::new(position, position),
+                text.to_string(),
+            )])
+        };
+
+        // The preview and doc comment, and the include path form of the
+        // `#include`.
+        let item = resolve("drop_packet");
+        let Some(Documentation::MarkupContent(content)) = item.documentation else {
+            panic!("no documentation: {item:?}");
+        };
+        assert_eq!(content.kind, MarkupKind::Markdown);
+        let util = directory.join("lib").join("util.p4");
+        assert_eq!(
+            content.value,
+            format!(
+                "```p4\naction drop_packet() {{}}\n```\n\nDrops the packet.\n\nFrom `{}`",
+                Url::from_file_path(util).unwrap().path()
+            )
+        );
+        assert_eq!(
+            item.additional_text_edits,
+            include_edit("#include <util.p4>\n")
+        );
+
+        // A document beside it is included by its relative path.
+        let item = resolve("forward");
+        assert_eq!(
+            item.additional_text_edits,
+            include_edit("#include \"common.p4\"\n")
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_rename_symbol() {
+        let directory = std::env::temp_dir().join(format!("p4-rename-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut workspace = Workspace::new();
+        let add_file = |workspace: &mut Workspace, name: &str, content: &str| {
+            let path = directory.join(name);
+            std::fs::write(&path, content).unwrap();
+            let url = Url::from_file_path(path).unwrap();
+            workspace.add_file(url.clone(), content);
+            url
+        };
+
+        let common = add_file(&mut workspace, "common.p4", "action forward() {}\n");
+        let main = add_file(
+            &mut workspace,
+            "main.p4",
+            "#include \"common.p4\"\ncontrol C() {\n    apply {\n        forward();\n    }\n}\n",
+        );
+
+        // The declaration, and its uses in the document including it.
+        let edit = |line, start, end| {
+            vec![TextEdit::new(
+                Range::new(Position::new(line, start), Position::new(line, end)),
+                "send".to_string(),
+            )]
+        };
+        let changes = workspace
+            .rename_symbol(common.clone(), Position::new(0, 8), "send".to_string())
+            .unwrap()
+            .and_then(|edit| edit.changes)
+            .unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[&common], edit(0, 7, 14));
+        assert_eq!(changes[&main], edit(3, 8, 15));
+
+        // A document including it already declares the new name.
+        let other = add_file(
+            &mut workspace,
+            "other.p4",
+            "#include \"common.p4\"\naction send() {}\n",
+        );
+        assert_eq!(
+            workspace.rename_symbol(common, Position::new(0, 8), "send".to_string()),
+            Err(format!("`send` is already declared in {}.", other.path()))
+        );
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_settings_errors() {
+        let directory = std::env::temp_dir().join(format!("p4-project-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("p4lsp.json"), "{ invalid").unwrap();
+
+        // A malformed project file is reported once, until it is reread.
+        let mut workspace = Workspace::new();
+        for name in ["a.p4", "b.p4"] {
+            let url = Url::from_file_path(directory.join(name)).unwrap();
+            workspace.add_file(url, "");
+        }
+        let errors = workspace.take_settings_errors();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("Invalid project settings"));
+        assert!(workspace.take_settings_errors().is_empty());
+
+        workspace.reload_project_settings();
+        assert_eq!(workspace.take_settings_errors().len(), 1);
+
+        std::fs::remove_dir_all(&directory).unwrap();
     }
 }